@@ -0,0 +1,50 @@
+//! Batch Z-Score throughput: stateful engine loop vs stateless batch function
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use quant_scalper_rust::{batch_zscore_static, ZScoreEngine};
+
+fn synthetic_prices(len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| 5000.0 + (i as f64 * 0.01).sin() * 20.0 + (i % 13) as f64 * 0.25)
+        .collect()
+}
+
+fn bench_batch_zscore(c: &mut Criterion) {
+    let prices = synthetic_prices(100_000);
+    let mut group = c.benchmark_group("batch_zscore");
+
+    for lookback in [20, 200] {
+        group.bench_with_input(
+            BenchmarkId::new("engine_loop", lookback),
+            &lookback,
+            |b, &lookback| {
+                b.iter(|| {
+                    let mut engine = ZScoreEngine::new(lookback).unwrap();
+                    let out: Vec<f64> = prices
+                        .iter()
+                        .map(|&p| engine.update(p).unwrap().unwrap_or(f64::NAN))
+                        .collect();
+                    black_box(out)
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("static", lookback),
+            &lookback,
+            |b, &lookback| {
+                // Clone outside the timed routine; engine_loop borrows the prices
+                b.iter_batched(
+                    || prices.clone(),
+                    |prices| black_box(batch_zscore_static(prices, lookback)),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_batch_zscore);
+criterion_main!(benches);
@@ -0,0 +1,281 @@
+//! Rolling correlation between two series
+//!
+//! Companion to `ZScoreEngine` for pairs trading. Each series is shifted
+//! by its own reference value K and the co-moment Σ(x - Kx)(y - Ky) is
+//! kept alongside the shifted sums, so the covariance stays accurate
+//! for large price levels.
+
+use pyo3::prelude::*;
+use std::collections::VecDeque;
+
+use crate::error::Error;
+
+/// Rolling Pearson correlation of two series over a fixed window
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import RollingCorrelation
+///
+/// corr = RollingCorrelation(50)
+///
+/// for es, nq in zip(es_prices, nq_prices):
+///     rho = corr.update(es, nq)
+/// ```
+#[pyclass]
+#[derive(Clone)]
+#[allow(non_snake_case)] // K/Ex/Exy follow the shifted-data algorithm's notation
+pub struct RollingCorrelation {
+    pairs: VecDeque<(f64, f64)>,
+    lookback: usize,
+    Kx: f64,  // Reference value for x
+    Ky: f64,  // Reference value for y
+    Ex: f64,  // Sum of (x - Kx)
+    Ey: f64,  // Sum of (y - Ky)
+    Ex2: f64, // Sum of (x - Kx)²
+    Ey2: f64, // Sum of (y - Ky)²
+    Exy: f64, // Sum of (x - Kx)(y - Ky)
+    k_ttl: usize, // Evictions left before K is re-centered
+}
+
+#[pymethods]
+impl RollingCorrelation {
+    /// Create a new correlation engine with specified lookback period
+    ///
+    /// # Arguments
+    /// * `lookback` - Number of bars for rolling calculation (e.g., 50)
+    #[new]
+    pub fn new(lookback: usize) -> Result<Self, Error> {
+        if lookback < 2 {
+            return Err(Error::InvalidArgument("Lookback must be > 1".to_string()));
+        }
+
+        Ok(Self {
+            pairs: VecDeque::with_capacity(lookback + 1),
+            lookback,
+            Kx: 0.0,
+            Ky: 0.0,
+            Ex: 0.0,
+            Ey: 0.0,
+            Ex2: 0.0,
+            Ey2: 0.0,
+            Exy: 0.0,
+            k_ttl: 0,
+        })
+    }
+
+    /// Update with a new (x, y) pair and return the current correlation
+    ///
+    /// Returns None if insufficient data (warming up period).
+    pub fn update(&mut self, x: f64, y: f64) -> Option<f64> {
+        // Initialize K on the first pair for numerical stability
+        if self.pairs.is_empty() {
+            self.Kx = x;
+            self.Ky = y;
+            self.k_ttl = 1;
+        }
+
+        let (dx, dy) = (x - self.Kx, y - self.Ky);
+        self.Ex += dx;
+        self.Ey += dy;
+        self.Ex2 += dx * dx;
+        self.Ey2 += dy * dy;
+        self.Exy += dx * dy;
+        self.pairs.push_back((x, y));
+
+        if self.pairs.len() > self.lookback {
+            if let Some((old_x, old_y)) = self.pairs.pop_front() {
+                let (dx, dy) = (old_x - self.Kx, old_y - self.Ky);
+                self.Ex -= dx;
+                self.Ey -= dy;
+                self.Ex2 -= dx * dx;
+                self.Ey2 -= dy * dy;
+                self.Exy -= dx * dy;
+
+                self.k_ttl = self.k_ttl.saturating_sub(1);
+                if self.k_ttl == 0 {
+                    self.recenter();
+                }
+            }
+        }
+
+        self.get_correlation()
+    }
+
+    /// Get current correlation without adding new data
+    ///
+    /// Returns 0.0 when either series is flat over the window.
+    pub fn get_correlation(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let n = self.pairs.len() as f64;
+        let sxx = (self.Ex2 - self.Ex * self.Ex / n).max(0.0);
+        let syy = (self.Ey2 - self.Ey * self.Ey / n).max(0.0);
+        let sxy = self.Exy - self.Ex * self.Ey / n;
+
+        // If either variance is essentially zero, correlation is undefined
+        if sxx / (n - 1.0) < 1e-10 || syy / (n - 1.0) < 1e-10 {
+            return Some(0.0);
+        }
+
+        // Rounding can push |rho| a hair past 1
+        Some((sxy / (sxx * syy).sqrt()).clamp(-1.0, 1.0))
+    }
+
+    /// Get the regression slope of y on x over the window
+    ///
+    /// This is the hedge ratio `cov(x, y) / var(x)`. Returns None during
+    /// warmup or when x is flat over the window.
+    pub fn get_beta(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let n = self.pairs.len() as f64;
+        let sxx = self.Ex2 - self.Ex * self.Ex / n;
+        if sxx / (n - 1.0) < 1e-10 {
+            return None;
+        }
+        Some((self.Exy - self.Ex * self.Ey / n) / sxx)
+    }
+
+    /// Get current rolling sample covariance (None if fewer than 2 pairs)
+    pub fn get_covariance(&self) -> Option<f64> {
+        let n = self.pairs.len() as f64;
+        if n >= 2.0 {
+            Some((self.Exy - self.Ex * self.Ey / n) / (n - 1.0))
+        } else {
+            None
+        }
+    }
+
+    /// Reset the engine, clearing all data
+    pub fn reset(&mut self) {
+        self.pairs.clear();
+        self.Ex = 0.0;
+        self.Ey = 0.0;
+        self.Ex2 = 0.0;
+        self.Ey2 = 0.0;
+        self.Exy = 0.0;
+        self.k_ttl = 0;
+    }
+
+    /// Check if engine has enough data for a valid correlation
+    pub fn is_ready(&self) -> bool {
+        self.pairs.len() >= self.lookback
+    }
+
+    /// Get number of pairs currently in the window
+    pub fn count(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Get the lookback period
+    pub fn lookback(&self) -> usize {
+        self.lookback
+    }
+}
+
+impl RollingCorrelation {
+    /// Re-center both K values on the window means and rebuild the sums
+    ///
+    /// Same scheme as `ZScoreEngine`: runs once per window turnover so
+    /// rounding error from the incremental updates never accumulates.
+    fn recenter(&mut self) {
+        if self.pairs.is_empty() {
+            return;
+        }
+
+        let n = self.pairs.len() as f64;
+        self.Kx += self.Ex / n;
+        self.Ky += self.Ey / n;
+
+        self.Ex = 0.0;
+        self.Ey = 0.0;
+        self.Ex2 = 0.0;
+        self.Ey2 = 0.0;
+        self.Exy = 0.0;
+        for &(x, y) in &self.pairs {
+            let (dx, dy) = (x - self.Kx, y - self.Ky);
+            self.Ex += dx;
+            self.Ey += dy;
+            self.Ex2 += dx * dx;
+            self.Ey2 += dy * dy;
+            self.Exy += dx * dy;
+        }
+
+        self.k_ttl = self.pairs.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two-pass Pearson correlation for reference
+    fn naive_correlation(pairs: &[(f64, f64)]) -> f64 {
+        let n = pairs.len() as f64;
+        let mx = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+        let my = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+        let sxy: f64 = pairs.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum();
+        let sxx: f64 = pairs.iter().map(|p| (p.0 - mx).powi(2)).sum();
+        let syy: f64 = pairs.iter().map(|p| (p.1 - my).powi(2)).sum();
+        sxy / (sxx * syy).sqrt()
+    }
+
+    #[test]
+    fn test_correlation_basic() {
+        let mut corr = RollingCorrelation::new(5).unwrap();
+        for i in 0..4 {
+            assert_eq!(corr.update(i as f64, 2.0 * i as f64), None);
+        }
+        assert!((corr.update(4.0, 8.0).unwrap() - 1.0).abs() < 1e-12);
+
+        // Perfectly inverse series
+        corr.reset();
+        for i in 0..5 {
+            corr.update(i as f64, -(i as f64));
+        }
+        assert!((corr.get_correlation().unwrap() + 1.0).abs() < 1e-12);
+        assert!((corr.get_covariance().unwrap() + 2.5).abs() < 1e-12);
+        assert!((corr.get_beta().unwrap() + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_correlation_flat_series() {
+        let mut corr = RollingCorrelation::new(3).unwrap();
+        for i in 0..5 {
+            corr.update(100.0, i as f64);
+        }
+        assert_eq!(corr.get_correlation(), Some(0.0));
+        assert_eq!(corr.get_beta(), None);
+        assert!(RollingCorrelation::new(0).is_err());
+    }
+
+    #[test]
+    fn test_correlation_large_price_levels() {
+        let mut corr = RollingCorrelation::new(50).unwrap();
+        let mut window = VecDeque::new();
+
+        let mut state: u64 = 7;
+        for i in 0..10_037 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let noise = (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5;
+            let common = (i as f64 * 0.05).sin();
+            let (x, y) = (1e9 + common, 5e8 + 0.5 * common + noise);
+
+            corr.update(x, y);
+            window.push_back((x, y));
+            if window.len() > 50 {
+                window.pop_front();
+            }
+        }
+
+        let expected = naive_correlation(window.make_contiguous());
+        let rho = corr.get_correlation().unwrap();
+        assert!((rho - expected).abs() < 1e-6, "{} vs {}", rho, expected);
+    }
+}
@@ -0,0 +1,46 @@
+//! Error type shared by the trading components
+//!
+//! Errors are plain Rust values so the core logic stays testable
+//! without a Python interpreter. They convert to the matching Python
+//! exception at the PyO3 boundary.
+
+use pyo3::exceptions::{PyKeyError, PyOSError, PyRuntimeError, PyValueError};
+use pyo3::PyErr;
+use std::fmt;
+
+/// Errors raised by the trading components
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// Argument outside its valid domain (raised as `ValueError`)
+    InvalidArgument(String),
+    /// Symbol not known to the calculator (raised as `KeyError`)
+    UnknownSymbol(String),
+    /// Operation refused by a configured risk limit (raised as `RuntimeError`)
+    LimitExceeded(String),
+    /// File system failure while saving or loading state (raised as `OSError`)
+    Io(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(msg) => write!(f, "{}", msg),
+            Error::UnknownSymbol(symbol) => write!(f, "Unknown symbol '{}'", symbol),
+            Error::LimitExceeded(msg) => write!(f, "{}", msg),
+            Error::Io(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        match err {
+            Error::InvalidArgument(msg) => PyValueError::new_err(msg),
+            Error::UnknownSymbol(symbol) => PyKeyError::new_err(symbol),
+            Error::LimitExceeded(msg) => PyRuntimeError::new_err(msg),
+            Error::Io(msg) => PyOSError::new_err(msg),
+        }
+    }
+}
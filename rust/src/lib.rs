@@ -5,17 +5,47 @@
 
 use pyo3::prelude::*;
 
+mod error;
+mod rolling_stats;
 mod zscore;
 mod risk_calculator;
+mod ma_spread;
+mod welford;
+mod correlation;
+mod spread;
+mod rsi;
+mod multi_zscore;
 
-pub use zscore::ZScoreEngine;
-pub use risk_calculator::RiskCalculator;
+pub use error::Error;
+pub use rolling_stats::RollingStats;
+pub use zscore::{batch_zscore_static, Cross, InputMode, Signal, ZScoreEngine, ZScoreStream};
+pub use risk_calculator::{CircuitBreaker, LevelHit, MarkSource, Position, RiskCalculator, RiskState};
+pub use ma_spread::MaSpreadZScoreEngine;
+pub use welford::WelfordZScoreEngine;
+pub use correlation::RollingCorrelation;
+pub use spread::SpreadZScoreEngine;
+pub use rsi::RsiEngine;
+pub use multi_zscore::MultiZScore;
 
 /// Python module definition
 #[pymodule]
-fn quant_scalper_rust(_py: Python, m: &PyModule) -> PyResult<()> {
+fn quant_scalper_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<ZScoreEngine>()?;
+    m.add_class::<ZScoreStream>()?;
+    m.add_class::<Signal>()?;
+    m.add_class::<Cross>()?;
     m.add_class::<RiskCalculator>()?;
+    m.add_class::<RiskState>()?;
+    m.add_class::<LevelHit>()?;
+    m.add_class::<CircuitBreaker>()?;
+    m.add_class::<Position>()?;
+    m.add_class::<MaSpreadZScoreEngine>()?;
+    m.add_class::<WelfordZScoreEngine>()?;
+    m.add_class::<RollingCorrelation>()?;
+    m.add_class::<SpreadZScoreEngine>()?;
+    m.add_class::<RsiEngine>()?;
+    m.add_class::<MultiZScore>()?;
+    m.add_function(wrap_pyfunction!(batch_zscore_static, m)?)?;
     
     // Module version
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
@@ -0,0 +1,203 @@
+//! Z-Score of a price against the moving average of another series
+//!
+//! Used for lead-lag strategies, where one instrument's price is compared
+//! with a smoothed version of the leading instrument.
+
+use pyo3::prelude::*;
+use std::collections::VecDeque;
+
+use crate::error::Error;
+use crate::zscore::ZScoreEngine;
+
+/// Rolling Z-Score of the spread `price_a - MA(price_b)`
+///
+/// Keeps a simple moving average of series B and feeds the difference
+/// between series A's latest price and that average into a regular
+/// `ZScoreEngine`.
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import MaSpreadZScoreEngine
+///
+/// engine = MaSpreadZScoreEngine(10, 20)  # 10-bar MA of B, 20-bar Z-Score
+///
+/// for a, b in zip(prices_a, prices_b):
+///     zscore = engine.update(a, b)
+/// ```
+#[pyclass]
+pub struct MaSpreadZScoreEngine {
+    b_window: VecDeque<f64>,
+    ma_period: usize,
+    b_sum: f64,
+    evictions: usize, // Evictions since b_sum was last rebuilt
+    spread: Option<f64>,
+    zscore: ZScoreEngine,
+}
+
+#[pymethods]
+impl MaSpreadZScoreEngine {
+    /// Create a new engine
+    ///
+    /// # Arguments
+    /// * `ma_period` - Number of bars in the moving average of B
+    /// * `lookback` - Number of spread values for the Z-Score window
+    #[new]
+    pub fn new(ma_period: usize, lookback: usize) -> Result<Self, Error> {
+        if ma_period == 0 {
+            return Err(Error::InvalidArgument("MA period must be > 0".to_string()));
+        }
+
+        Ok(Self {
+            b_window: VecDeque::with_capacity(ma_period + 1),
+            ma_period,
+            b_sum: 0.0,
+            evictions: 0,
+            spread: None,
+            zscore: ZScoreEngine::new(lookback)?,
+        })
+    }
+
+    /// Update with the latest prices of both series and return the Z-Score
+    ///
+    /// Returns None until the moving average and the Z-Score window are
+    /// both warmed up.
+    ///
+    /// # Arguments
+    /// * `price_a` - Latest price of the series being compared
+    /// * `price_b` - Latest price of the series being averaged
+    pub fn update(&mut self, price_a: f64, price_b: f64) -> Result<Option<f64>, Error> {
+        self.b_window.push_back(price_b);
+        self.b_sum += price_b;
+
+        if self.b_window.len() > self.ma_period {
+            if let Some(old) = self.b_window.pop_front() {
+                self.b_sum -= old;
+                self.evictions += 1;
+            }
+
+            // Rebuild the running sum once per window turnover so
+            // rounding error from add/subtract doesn't accumulate
+            if self.evictions >= self.ma_period {
+                self.b_sum = self.b_window.iter().sum();
+                self.evictions = 0;
+            }
+        }
+
+        match self.get_moving_average() {
+            Some(ma) => {
+                let spread = price_a - ma;
+                self.spread = Some(spread);
+                self.zscore.update(spread)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get the current moving average of series B (None during warmup)
+    pub fn get_moving_average(&self) -> Option<f64> {
+        if self.b_window.len() >= self.ma_period {
+            Some(self.b_sum / self.b_window.len() as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Get the latest spread `price_a - MA(price_b)`
+    pub fn get_spread(&self) -> Option<f64> {
+        self.spread
+    }
+
+    /// Get current Z-Score of the spread without adding new data
+    pub fn get_zscore(&self) -> Option<f64> {
+        self.zscore.get_zscore()
+    }
+
+    /// Get rolling mean of the spread
+    pub fn get_mean(&self) -> Option<f64> {
+        self.zscore.get_mean()
+    }
+
+    /// Get rolling standard deviation of the spread
+    pub fn get_std(&self) -> Option<f64> {
+        self.zscore.get_std()
+    }
+
+    /// Check if engine has enough data to generate signals
+    pub fn is_ready(&self) -> bool {
+        self.zscore.is_ready()
+    }
+
+    /// Reset the engine, clearing all data
+    pub fn reset(&mut self) {
+        self.b_window.clear();
+        self.b_sum = 0.0;
+        self.evictions = 0;
+        self.spread = None;
+        self.zscore.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warmup() {
+        let mut engine = MaSpreadZScoreEngine::new(3, 2).unwrap();
+
+        // MA needs 3 bars, then the Z-Score needs 2 spreads
+        assert!(engine.update(1.0, 10.0).unwrap().is_none());
+        assert!(engine.update(1.0, 11.0).unwrap().is_none());
+        assert!(engine.get_moving_average().is_none());
+
+        assert!(engine.update(1.0, 12.0).unwrap().is_none());
+        assert_eq!(engine.get_moving_average(), Some(11.0));
+        assert_eq!(engine.get_spread(), Some(-10.0));
+
+        assert!(engine.update(5.0, 13.0).unwrap().is_some());
+        assert_eq!(engine.get_moving_average(), Some(12.0));
+        assert!(engine.is_ready());
+
+        engine.reset();
+        assert!(!engine.is_ready());
+        assert!(engine.get_spread().is_none());
+        assert!(engine.get_moving_average().is_none());
+    }
+
+    #[test]
+    fn test_invalid_periods() {
+        assert!(MaSpreadZScoreEngine::new(0, 20).is_err());
+        assert!(MaSpreadZScoreEngine::new(10, 1).is_err());
+    }
+
+    #[test]
+    fn test_lagged_ma_spread_oscillates_around_zero() {
+        let ma_period = 10;
+        let mut engine = MaSpreadZScoreEngine::new(ma_period, 50).unwrap();
+
+        let b: Vec<f64> = (0..2000)
+            .map(|i| 5000.0 + (i as f64 * 0.05).sin() * 25.0)
+            .collect();
+
+        let mut zscores = Vec::new();
+        for i in 0..b.len() {
+            // A follows B's moving average with small noise
+            let start = (i + 1).saturating_sub(ma_period);
+            let window = &b[start..=i];
+            let ma = window.iter().sum::<f64>() / window.len() as f64;
+            let a = ma + (i as f64 * 0.9).sin() * 0.5;
+
+            if let Some(z) = engine.update(a, b[i]).unwrap() {
+                zscores.push(z);
+            }
+        }
+
+        let spread_mean = engine.get_mean().unwrap();
+        assert!(spread_mean.abs() < 0.2, "Spread mean {} not near zero", spread_mean);
+
+        let avg_z = zscores.iter().sum::<f64>() / zscores.len() as f64;
+        assert!(avg_z.abs() < 0.2, "Average Z-Score {} not near zero", avg_z);
+        assert!(zscores.iter().any(|&z| z > 1.0));
+        assert!(zscores.iter().any(|&z| z < -1.0));
+    }
+}
@@ -0,0 +1,147 @@
+//! Z-Scores of one price stream at several lookbacks
+//!
+//! Scalpers often confirm a fast signal against medium and slow
+//! windows. `MultiZScore` feeds each price to one shifted-data engine
+//! per lookback and answers the combined questions in one call.
+
+use pyo3::prelude::*;
+
+use crate::error::Error;
+use crate::zscore::ZScoreEngine;
+
+/// Rolling Z-Scores of the same prices over several lookbacks
+///
+/// Z-Scores are reported in the order the lookbacks were given.
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import MultiZScore
+///
+/// multi = MultiZScore([10, 50, 200])
+///
+/// for price in prices:
+///     fast, medium, slow = multi.update(price)
+///     if multi.all_below(-2.0):
+///         print("Oversold on every timeframe!")
+/// ```
+#[pyclass]
+#[derive(Clone)]
+pub struct MultiZScore {
+    engines: Vec<ZScoreEngine>,
+}
+
+#[pymethods]
+impl MultiZScore {
+    /// Create one engine per lookback
+    ///
+    /// # Arguments
+    /// * `lookbacks` - Distinct lookbacks (each > 1), e.g. fast, medium, slow
+    #[new]
+    pub fn new(lookbacks: Vec<usize>) -> Result<Self, Error> {
+        if lookbacks.is_empty() {
+            return Err(Error::InvalidArgument("Need at least one lookback".to_string()));
+        }
+        for (i, lookback) in lookbacks.iter().enumerate() {
+            if lookbacks[..i].contains(lookback) {
+                return Err(Error::InvalidArgument(format!(
+                    "Duplicate lookback {}",
+                    lookback
+                )));
+            }
+        }
+
+        let engines = lookbacks
+            .into_iter()
+            .map(ZScoreEngine::new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { engines })
+    }
+
+    /// Update every window with a new price and return the Z-Scores
+    ///
+    /// One entry per lookback, None for windows still warming up.
+    pub fn update(&mut self, price: f64) -> Result<Vec<Option<f64>>, Error> {
+        self.engines.iter_mut().map(|engine| engine.update(price)).collect()
+    }
+
+    /// Get the current Z-Scores without adding new data
+    pub fn get_zscores(&self) -> Vec<Option<f64>> {
+        self.engines.iter().map(ZScoreEngine::get_zscore).collect()
+    }
+
+    /// Get the lookbacks, in the order Z-Scores are reported
+    pub fn lookbacks(&self) -> Vec<usize> {
+        self.engines.iter().map(ZScoreEngine::lookback).collect()
+    }
+
+    /// Check if every Z-Score is above `threshold`
+    ///
+    /// False while any window is warming up.
+    pub fn all_above(&self, threshold: f64) -> bool {
+        self.get_zscores().iter().all(|z| z.is_some_and(|z| z > threshold))
+    }
+
+    /// Check if every Z-Score is below `threshold`
+    ///
+    /// False while any window is warming up.
+    pub fn all_below(&self, threshold: f64) -> bool {
+        self.get_zscores().iter().all(|z| z.is_some_and(|z| z < threshold))
+    }
+
+    /// Check if every window has enough data to generate signals
+    pub fn is_ready(&self) -> bool {
+        self.engines.iter().all(ZScoreEngine::is_ready)
+    }
+
+    /// Reset every window, clearing all data
+    pub fn reset(&mut self) {
+        for engine in &mut self.engines {
+            engine.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_zscore_matches_single_engines() {
+        let mut multi = MultiZScore::new(vec![5, 2, 3]).unwrap();
+        let mut singles: Vec<ZScoreEngine> =
+            [5, 2, 3].map(|n| ZScoreEngine::new(n).unwrap()).to_vec();
+        assert_eq!(multi.lookbacks(), vec![5, 2, 3]);
+
+        for i in 0..20 {
+            let price = 100.0 + (i as f64 * 0.9).sin() * 3.0;
+            let expected: Vec<_> = singles.iter_mut().map(|e| e.update(price).unwrap()).collect();
+            assert_eq!(multi.update(price).unwrap(), expected);
+            assert_eq!(multi.is_ready(), i >= 4);
+        }
+        assert_eq!(multi.get_zscores(), singles.iter().map(|e| e.get_zscore()).collect::<Vec<_>>());
+
+        multi.reset();
+        assert!(!multi.is_ready());
+        assert_eq!(multi.get_zscores(), vec![None, None, None]);
+    }
+
+    #[test]
+    fn test_multi_zscore_thresholds() {
+        let mut multi = MultiZScore::new(vec![2, 3]).unwrap();
+        multi.update(10.0).unwrap();
+        multi.update(10.0).unwrap();
+        // Only the fast window is ready
+        assert!(!multi.all_above(-1.0));
+
+        // Z-Scores: fast (10, 20) -> 0.71, slow (10, 10, 20) -> 1.15
+        multi.update(20.0).unwrap();
+        assert!(multi.all_above(0.5));
+        assert!(!multi.all_above(1.0));
+        assert!(multi.all_below(1.5));
+        assert!(!multi.all_below(0.0));
+
+        assert!(MultiZScore::new(vec![]).is_err());
+        assert!(MultiZScore::new(vec![5, 1]).is_err());
+        assert!(MultiZScore::new(vec![5, 10, 5]).is_err());
+    }
+}
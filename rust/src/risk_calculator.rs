@@ -2,29 +2,454 @@
 //! 
 //! Tracks positions and calculates P&L with minimal latency.
 
+use numpy::{PyArray1, PyReadonlyArray1};
 use pyo3::prelude::*;
-use std::collections::HashMap;
+use pyo3::exceptions::PyRuntimeWarning;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+
+/// Which price is used to mark positions for unrealized P&L
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkSource {
+    /// Last traded price
+    Last,
+    /// Bid/ask midpoint
+    Mid,
+    /// Price we could exit at: bid for longs, ask for shorts
+    Conservative,
+}
+
+impl std::str::FromStr for MarkSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "last" => Ok(MarkSource::Last),
+            "mid" => Ok(MarkSource::Mid),
+            "conservative" => Ok(MarkSource::Conservative),
+            _ => Err(Error::InvalidArgument(format!(
+                "Unknown mark source '{}', expected 'last', 'mid' or 'conservative'",
+                s
+            ))),
+        }
+    }
+}
+
+impl MarkSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MarkSource::Last => "last",
+            MarkSource::Mid => "mid",
+            MarkSource::Conservative => "conservative",
+        }
+    }
+}
+
+/// Where daily P&L sits relative to the loss limit
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RiskState {
+    /// Loss below the warning level
+    Ok,
+    /// Loss past the warning fraction of the limit
+    Warning,
+    /// Daily loss limit hit
+    Breached,
+}
+
+/// Trading lifecycle around a daily loss limit breach
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CircuitBreaker {
+    /// Trading allowed
+    Active,
+    /// Loss limit hit; positions still need to be flattened
+    Tripped,
+    /// Flattened after a trip; waiting for a manual `arm`
+    Cooldown,
+}
+
+/// Which static exit level the last price has reached
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelHit {
+    /// Stop-loss level reached
+    Stop,
+    /// Take-profit level reached
+    Target,
+}
+
+/// Quantities within this of zero are treated as flat
+const QTY_EPSILON: f64 = 1e-9;
+
+/// Check whether a quantity is flat (within `QTY_EPSILON` of zero)
+fn is_flat(quantity: f64) -> bool {
+    quantity.abs() <= QTY_EPSILON
+}
+
+/// Direction of a quantity: 1 long, -1 short, 0 flat
+fn side(quantity: f64) -> f64 {
+    if is_flat(quantity) {
+        0.0
+    } else {
+        quantity.signum()
+    }
+}
+
+/// Copy entries of `from` whose keys are missing in `into`
+fn fill_missing<V: Clone>(into: &mut HashMap<String, V>, from: &HashMap<String, V>) {
+    for (key, value) in from {
+        into.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+}
+
+/// Serde format for limits that may be infinite (e.g., "no limit")
+///
+/// JSON numbers cannot hold non-finite floats, so those are written as
+/// strings ("inf", "-inf", "NaN") and parsed back on load.
+mod float_repr {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Number(f64),
+        Text(String),
+    }
+
+    impl Repr {
+        fn into_f64<E: serde::de::Error>(self) -> Result<f64, E> {
+            match self {
+                Repr::Number(x) => Ok(x),
+                Repr::Text(text) => text.parse().map_err(E::custom),
+            }
+        }
+    }
+
+    fn serialize_f64<S: Serializer>(x: f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if x.is_finite() {
+            serializer.serialize_f64(x)
+        } else {
+            serializer.collect_str(&x)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(x: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_f64(*x, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Repr::deserialize(deserializer)?.into_f64()
+    }
+
+    /// Same format for the values of a map keyed by symbol
+    pub mod map {
+        use super::*;
+        use serde::ser::SerializeMap;
+
+        pub fn serialize<S: Serializer>(
+            map: &HashMap<String, f64>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            struct Value(f64);
+            impl serde::Serialize for Value {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serialize_f64(self.0, serializer)
+                }
+            }
+
+            let mut out = serializer.serialize_map(Some(map.len()))?;
+            for (key, &value) in map {
+                out.serialize_entry(key, &Value(value))?;
+            }
+            out.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<HashMap<String, f64>, D::Error> {
+            HashMap::<String, Repr>::deserialize(deserializer)?
+                .into_iter()
+                .map(|(key, value)| value.into_f64().map(|x| (key, x)))
+                .collect()
+        }
+    }
+}
+
+/// Hook invoked with total P&L when the daily loss limit is breached
+type BreachHook = Arc<dyn Fn(f64) + Send + Sync>;
+
+/// Slot holding the breach hook
+///
+/// Clones start empty, so a scenario copy that breaches never fires the
+/// live calculator's callback.
+#[derive(Default)]
+struct BreachHookSlot(Option<BreachHook>);
+
+impl Clone for BreachHookSlot {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+/// Stop that follows the best price seen by a fixed distance
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct TrailingStop {
+    distance: f64,
+    best: f64, // Most favorable price since the stop was set
+}
+
+/// Open quantity filled at a single price
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Lot {
+    quantity: f64,
+    price: f64,
+}
 
 /// Position data
-#[derive(Clone, Debug)]
-struct Position {
+///
+/// Exposed to Python as read-only snapshots from
+/// `RiskCalculator.get_positions`.
+#[pyclass]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Position {
     symbol: String,
-    quantity: i32,
+    quantity: f64,
     entry_price: f64,
     current_price: f64,
     multiplier: f64,
+    bid: Option<f64>,
+    ask: Option<f64>,
+    lots: VecDeque<Lot>, // Open lots, oldest first
+    trailing_stop: Option<TrailingStop>,
+    stop_level: Option<f64>,   // Static stop-loss price
+    target_level: Option<f64>, // Static take-profit price
+    mark_source: MarkSource, // Mark used by the Python unrealized_pnl getter
+}
+
+#[pymethods]
+impl Position {
+    /// Instrument symbol
+    #[getter(symbol)]
+    fn py_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Signed position size (positive=long, negative=short)
+    #[getter(quantity)]
+    fn py_quantity(&self) -> f64 {
+        self.quantity
+    }
+
+    /// Average entry price of the open lots
+    #[getter(entry_price)]
+    fn py_entry_price(&self) -> f64 {
+        self.entry_price
+    }
+
+    /// Last traded price
+    #[getter(current_price)]
+    fn py_current_price(&self) -> f64 {
+        self.current_price
+    }
+
+    /// Contract multiplier
+    #[getter(multiplier)]
+    fn py_multiplier(&self) -> f64 {
+        self.multiplier
+    }
+
+    /// Unrealized P&L under the calculator's mark source at snapshot time
+    #[getter(unrealized_pnl)]
+    fn py_unrealized_pnl(&self) -> f64 {
+        self.unrealized_pnl(self.mark_source)
+    }
+
+    /// Get the position as a dict
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = pyo3::types::PyDict::new(py);
+        dict.set_item("symbol", &self.symbol)?;
+        dict.set_item("quantity", self.quantity)?;
+        dict.set_item("entry_price", self.entry_price)?;
+        dict.set_item("current_price", self.current_price)?;
+        dict.set_item("multiplier", self.multiplier)?;
+        dict.set_item("unrealized_pnl", self.py_unrealized_pnl())?;
+        Ok(dict.into_any().unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Position(symbol='{}', quantity={}, entry_price={}, current_price={})",
+            self.symbol, self.quantity, self.entry_price, self.current_price
+        )
+    }
 }
 
 impl Position {
+    fn new(symbol: String, quantity: f64, entry_price: f64, multiplier: f64) -> Self {
+        Self {
+            symbol,
+            quantity,
+            entry_price,
+            current_price: entry_price,
+            multiplier,
+            bid: None,
+            ask: None,
+            lots: VecDeque::from([Lot { quantity, price: entry_price }]),
+            trailing_stop: None,
+            stop_level: None,
+            target_level: None,
+            mark_source: MarkSource::Last,
+        }
+    }
+
+    /// Apply a signed fill, closing open lots FIFO before opening new ones
+    ///
+    /// Returns the realized P&L, or None if nothing was closed.
+    fn apply_fill(&mut self, quantity: f64, price: f64) -> Option<f64> {
+        let prev_side = side(self.quantity);
+        let mut remaining = quantity;
+        let mut realized = None;
+
+        while !is_flat(remaining) {
+            match self.lots.front_mut() {
+                // Opposite side: close against the oldest lot
+                Some(lot) if side(lot.quantity) != side(remaining) => {
+                    let closed = remaining.abs().min(lot.quantity.abs()) * lot.quantity.signum();
+                    *realized.get_or_insert(0.0) += (price - lot.price) * closed * self.multiplier;
+                    lot.quantity -= closed;
+                    remaining += closed;
+                    if is_flat(lot.quantity) {
+                        self.lots.pop_front();
+                    }
+                }
+                // Flat or same side: open a new lot
+                _ => {
+                    self.lots.push_back(Lot { quantity: remaining, price });
+                    remaining = 0.0;
+                }
+            }
+        }
+
+        // Quantity and entry price follow the remaining lots
+        self.quantity = self.lots.iter().map(|l| l.quantity).sum();
+        if is_flat(self.quantity) {
+            self.quantity = 0.0;
+        } else {
+            let cost: f64 = self.lots.iter().map(|l| l.price * l.quantity).sum();
+            self.entry_price = cost / self.quantity;
+        }
+
+        // Exit levels only make sense for the side they were set on
+        if side(self.quantity) != prev_side {
+            self.clear_exits();
+        }
+
+        realized
+    }
+
+    /// Drop the trailing stop and static levels (e.g., on a flip)
+    fn clear_exits(&mut self) {
+        self.trailing_stop = None;
+        self.stop_level = None;
+        self.target_level = None;
+    }
+
+    /// Static level reached by the last price, if any
+    ///
+    /// The stop wins if both are somehow reached at once.
+    fn level_hit(&self) -> Option<LevelHit> {
+        let long = self.quantity > 0.0;
+        let price = self.current_price;
+        let reached = |level: Option<f64>, above: bool| {
+            level.is_some_and(|level| if above { price >= level } else { price <= level })
+        };
+
+        if reached(self.stop_level, !long) {
+            Some(LevelHit::Stop)
+        } else if reached(self.target_level, long) {
+            Some(LevelHit::Target)
+        } else {
+            None
+        }
+    }
+
+    /// Ratchet the trailing stop toward the current price (never loosens)
+    fn trail(&mut self) {
+        let (long, price) = (self.quantity > 0.0, self.current_price);
+        if let Some(stop) = self.trailing_stop.as_mut() {
+            if (long && price > stop.best) || (!long && price < stop.best) {
+                stop.best = price;
+            }
+        }
+    }
+
+    /// Current trailing stop level (None if no stop is set)
+    fn trailing_stop_level(&self) -> Option<f64> {
+        self.trailing_stop.as_ref().map(|stop| {
+            if self.quantity > 0.0 {
+                stop.best - stop.distance
+            } else {
+                stop.best + stop.distance
+            }
+        })
+    }
+
+    /// Mark price under `source`, falling back to the last price when
+    /// the required quote is missing
+    fn mark_price(&self, source: MarkSource) -> f64 {
+        match source {
+            MarkSource::Last => self.current_price,
+            MarkSource::Mid => match (self.bid, self.ask) {
+                (Some(bid), Some(ask)) => (bid + ask) / 2.0,
+                _ => self.current_price,
+            },
+            MarkSource::Conservative => {
+                let exit = if self.quantity > 0.0 { self.bid } else { self.ask };
+                exit.unwrap_or(self.current_price)
+            }
+        }
+    }
+
+    fn unrealized_pnl(&self, source: MarkSource) -> f64 {
+        let price_diff = self.mark_price(source) - self.entry_price;
+        price_diff * self.quantity * self.multiplier
+    }
+
+    /// Signed notional value at the last price (negative when short)
+    fn notional(&self) -> f64 {
+        self.quantity * self.current_price * self.multiplier
+    }
+}
+
+/// Single leg of a multi-leg spread
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SpreadLeg {
+    symbol: String,
+    ratio: f64,
+    entry_price: f64,
+    current_price: f64,
+    multiplier: f64,
+}
+
+impl SpreadLeg {
     fn unrealized_pnl(&self) -> f64 {
         let price_diff = self.current_price - self.entry_price;
-        price_diff * self.quantity as f64 * self.multiplier
+        price_diff * self.ratio * self.multiplier
     }
 }
 
 /// Real-time risk calculator
 /// 
-/// Tracks positions and calculates P&L metrics with O(1) updates.
+/// Tracks positions and calculates P&L metrics. Each price update is
+/// O(positions): it re-totals P&L to track peak equity and drawdown and
+/// to check the loss limit, so batch snapshots with `update_prices`.
 /// 
 /// # Example (Python)
 /// ```python
@@ -42,10 +467,48 @@ impl Position {
 /// print(f"Unrealized P&L: ${calc.unrealized_pnl():.2f}")
 /// ```
 #[pyclass]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RiskCalculator {
     positions: HashMap<String, Position>,
+    instruments: HashMap<String, f64>, // Registered multipliers by symbol
+    currencies: HashMap<String, String>, // Non-base currency by symbol
+    fx_rates: HashMap<String, f64>,      // Rate to base by currency
+    spreads: HashMap<String, Vec<SpreadLeg>>,
+    #[serde(with = "float_repr")]
     max_daily_loss: f64,
     realized_pnl: f64,
+    realized_by_symbol: HashMap<String, f64>,
+    win_count: usize,
+    loss_count: usize,
+    gross_wins: f64,
+    gross_losses: f64, // Sum of losing trades as a positive amount
+    #[serde(with = "float_repr::map")]
+    symbol_loss_limits: HashMap<String, f64>,
+    #[serde(with = "float_repr::map")]
+    profit_targets: HashMap<String, f64>,
+    commission_per_contract: f64,
+    symbol_commissions: HashMap<String, f64>,
+    total_commissions: f64,
+    turnover: f64,     // Traded notional today, in base currency
+    fill_count: usize, // Fills executed today
+    max_concentration: Option<f64>, // Max fraction of gross exposure in one position
+    max_positions: Option<usize>,   // Max number of open positions
+    strict: bool, // update_price raises for symbols not held
+    allow_flips: bool, // Trades may reverse a position in one step
+    warning_fraction: Option<f64>,  // Fraction of max_daily_loss that raises a warning
+    profit_lock: Option<(f64, f64)>, // (trigger, giveback) for the profit lock
+    mark_source: MarkSource,
+    peak_equity: f64,
+    peak_time: f64, // Seconds since epoch when equity was last at its peak
+    max_drawdown: f64,
+    drawdown_limit: Option<f64>, // Max decline from peak before breaching
+    clock: Option<f64>, // Pinned time in seconds (None = wall clock)
+    #[serde(skip)]
+    breach_hook: BreachHookSlot,
+    breach_armed: bool, // Hook fires on the next breach (re-armed on recovery)
+    breaker: CircuitBreaker,
+    equity_log: Option<VecDeque<(f64, f64)>>, // (time, total P&L), opt-in
+    equity_log_len: usize,
 }
 
 #[pymethods]
@@ -56,11 +519,63 @@ impl RiskCalculator {
     /// * `max_daily_loss` - Maximum loss allowed before circuit breaker (positive number)
     #[new]
     pub fn new(max_daily_loss: f64) -> Self {
-        Self {
+        let mut calc = Self {
             positions: HashMap::new(),
+            instruments: HashMap::new(),
+            currencies: HashMap::new(),
+            fx_rates: HashMap::new(),
+            spreads: HashMap::new(),
             max_daily_loss: max_daily_loss.abs(),
             realized_pnl: 0.0,
-        }
+            realized_by_symbol: HashMap::new(),
+            win_count: 0,
+            loss_count: 0,
+            gross_wins: 0.0,
+            gross_losses: 0.0,
+            symbol_loss_limits: HashMap::new(),
+            profit_targets: HashMap::new(),
+            commission_per_contract: 0.0,
+            symbol_commissions: HashMap::new(),
+            total_commissions: 0.0,
+            turnover: 0.0,
+            fill_count: 0,
+            max_concentration: None,
+            max_positions: None,
+            strict: false,
+            allow_flips: true,
+            warning_fraction: None,
+            profit_lock: None,
+            mark_source: MarkSource::Last,
+            peak_equity: 0.0,
+            peak_time: 0.0,
+            max_drawdown: 0.0,
+            drawdown_limit: None,
+            clock: None,
+            breach_hook: BreachHookSlot::default(),
+            breach_armed: true,
+            breaker: CircuitBreaker::Active,
+            equity_log: None,
+            equity_log_len: 0,
+        };
+        calc.peak_time = calc.now();
+        calc
+    }
+
+    /// Get an independent copy of the calculator (e.g., to branch scenarios)
+    ///
+    /// Positions and P&L state are copied; a breach callback is not, so
+    /// the copy can breach without notifying the original's callback.
+    #[pyo3(name = "clone")]
+    fn py_clone(&self) -> Self {
+        self.clone()
+    }
+
+    fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.clone()
     }
 
     /// Add or update a position
@@ -69,218 +584,3749 @@ impl RiskCalculator {
     /// * `symbol` - Instrument symbol (e.g., "MES")
     /// * `quantity` - Position size (positive=long, negative=short, 0=remove)
     /// * `entry_price` - Average entry price
-    /// * `multiplier` - Contract multiplier (e.g., 5 for MES)
-    pub fn update_position(
+    /// * `multiplier` - Contract multiplier (e.g., 5 for MES); if omitted,
+    ///   the multiplier from `register_instrument` is used, raising
+    ///   KeyError for unregistered symbols
+    ///
+    /// Raises ValueError for a multiplier <= 0 (unless removing the
+    /// position) and RuntimeError if opening a new symbol would exceed
+    /// the `set_max_positions` cap or if the call would reverse the
+    /// position while flips are disallowed (see `set_allow_flips`).
+    #[pyo3(name = "update_position", signature = (symbol, quantity, entry_price, multiplier=None))]
+    fn py_update_position(
         &mut self,
         symbol: String,
-        quantity: i32,
+        quantity: f64,
         entry_price: f64,
+        multiplier: Option<f64>,
+    ) -> Result<(), Error> {
+        let multiplier = match multiplier {
+            Some(multiplier) => multiplier,
+            // Removing a position does not need a multiplier
+            None if is_flat(quantity) => 0.0,
+            None => self.registered_multiplier(&symbol)?,
+        };
+        if !is_flat(quantity) {
+            validate_multiplier(multiplier)?;
+            self.check_position_limit(&symbol)?;
+            self.check_flip(&symbol, quantity)?;
+        }
+        self.update_position(symbol, quantity, entry_price, multiplier);
+        Ok(())
+    }
+
+    /// Register a symbol's contract multiplier
+    ///
+    /// Lets `update_position` be called without a multiplier.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `multiplier` - Contract multiplier (e.g., 5 for MES; must be > 0)
+    pub fn register_instrument(&mut self, symbol: String, multiplier: f64) -> Result<(), Error> {
+        validate_multiplier(multiplier)?;
+        self.instruments.insert(symbol, multiplier);
+        Ok(())
+    }
+
+    /// Get a registered contract multiplier (None if not registered)
+    pub fn get_instrument_multiplier(&self, symbol: &str) -> Option<f64> {
+        self.instruments.get(symbol).copied()
+    }
+
+    /// Set the conversion rate from a currency to the base currency
+    ///
+    /// P&L of symbols tagged with `currency` (see `set_symbol_currency`)
+    /// is multiplied by this rate. Realized P&L is converted at the rate
+    /// in effect when it is booked.
+    ///
+    /// # Arguments
+    /// * `currency` - Currency code (e.g., "EUR")
+    /// * `rate_to_base` - Base currency per unit of `currency` (> 0)
+    pub fn set_fx_rate(&mut self, currency: String, rate_to_base: f64) -> Result<(), Error> {
+        validate_fx_rate(rate_to_base)?;
+        self.fx_rates.insert(currency, rate_to_base);
+        self.track_equity();
+        Ok(())
+    }
+
+    /// Get the rate from a currency to the base currency (None if not set)
+    pub fn get_fx_rate(&self, currency: &str) -> Option<f64> {
+        self.fx_rates.get(currency).copied()
+    }
+
+    /// Tag a symbol as denominated in `currency`
+    ///
+    /// Untagged symbols are in the base currency. Raises ValueError if no
+    /// rate has been set for `currency`, so P&L is never summed across
+    /// currencies unconverted.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `currency` - Currency code with a rate from `set_fx_rate`
+    pub fn set_symbol_currency(&mut self, symbol: String, currency: String) -> Result<(), Error> {
+        if !self.fx_rates.contains_key(&currency) {
+            return Err(Error::InvalidArgument(format!(
+                "No FX rate set for currency '{}'",
+                currency
+            )));
+        }
+        self.currencies.insert(symbol, currency);
+        self.track_equity();
+        Ok(())
+    }
+
+    /// Get a symbol's currency tag (None if in the base currency)
+    pub fn get_symbol_currency(&self, symbol: &str) -> Option<String> {
+        self.currencies.get(symbol).cloned()
+    }
+
+    /// Apply a fill to a position with FIFO lot accounting
+    ///
+    /// * flat -> open: new position at `fill_price`
+    /// * same-side add: a new lot is opened, and the entry price becomes
+    ///   the volume-weighted average of the open lots
+    /// * reduce: the oldest lots are closed first (FIFO) and their P&L is
+    ///   realized; the entry price is the average of the lots left
+    /// * close: all P&L is realized and the position removed
+    /// * flip: the old position is closed out and the excess opens a new
+    ///   position at `fill_price`
+    ///
+    /// Returns the P&L realized by this fill, before commission. The
+    /// commission for the fill is deducted from realized P&L separately.
+    /// Raises ValueError for a multiplier <= 0 or one that differs from
+    /// the held position's, and RuntimeError if opening a new symbol
+    /// would exceed the `set_max_positions` cap or if the fill would flip
+    /// the position while flips are disallowed.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `quantity` - Signed fill size (positive=buy, negative=sell)
+    /// * `fill_price` - Execution price
+    /// * `multiplier` - Contract multiplier (must be > 0)
+    pub fn add_fill(
+        &mut self,
+        symbol: String,
+        quantity: f64,
+        fill_price: f64,
         multiplier: f64,
-    ) {
-        if quantity == 0 {
-            self.positions.remove(&symbol);
-        } else {
-            let current_price = self
-                .positions
-                .get(&symbol)
-                .map(|p| p.current_price)
-                .unwrap_or(entry_price);
+    ) -> Result<f64, Error> {
+        if is_flat(quantity) {
+            return Ok(0.0);
+        }
 
-            self.positions.insert(
-                symbol.clone(),
-                Position {
-                    symbol,
-                    quantity,
-                    entry_price,
-                    current_price,
-                    multiplier,
-                },
-            );
+        validate_multiplier(multiplier)?;
+        if let Some(pos) = self.positions.get(&symbol) {
+            if pos.multiplier != multiplier {
+                return Err(Error::InvalidArgument(format!(
+                    "Cannot fill {}: multiplier {} differs from the position's {}",
+                    symbol, multiplier, pos.multiplier
+                )));
+            }
+        }
+        self.check_position_limit(&symbol)?;
+        let held = self.positions.get(&symbol).map_or(0.0, |pos| pos.quantity);
+        self.check_flip(&symbol, held + quantity)?;
+        self.record_fill(&symbol, quantity, fill_price, multiplier);
+        self.charge_commission(&symbol, quantity);
+
+        if !self.positions.contains_key(&symbol) {
+            // Flat -> open
+            self.update_position(symbol, quantity, fill_price, multiplier);
+            return Ok(0.0);
+        }
+
+        Ok(self.apply_fill(symbol, quantity, fill_price))
+    }
+
+    /// Close part of a position FIFO against its open lots
+    ///
+    /// Closing more than is open flips the position: the excess opens a
+    /// new lot on the opposite side at `fill_price`. While flips are
+    /// disallowed this raises RuntimeError instead.
+    ///
+    /// Returns the P&L realized by the close, before commission.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol (must be held)
+    /// * `quantity` - Amount to close (positive)
+    /// * `fill_price` - Execution price
+    pub fn close_quantity(&mut self, symbol: String, quantity: f64, fill_price: f64) -> Result<f64, Error> {
+        if quantity.is_nan() || quantity <= QTY_EPSILON {
+            return Err(Error::InvalidArgument(format!(
+                "Close quantity must be > 0, got {}",
+                quantity
+            )));
         }
+
+        let (held, multiplier) = match self.positions.get(&symbol) {
+            Some(pos) => (pos.quantity, pos.multiplier),
+            None => return Err(Error::UnknownSymbol(symbol)),
+        };
+        let side = side(held);
+        self.check_flip(&symbol, held - side * quantity)?;
+
+        self.record_fill(&symbol, quantity, fill_price, multiplier);
+        self.charge_commission(&symbol, quantity);
+        Ok(self.apply_fill(symbol, -side * quantity, fill_price))
+    }
+
+    /// Get open lots for a symbol as (quantity, price), oldest first
+    pub fn get_lots(&self, symbol: &str) -> Vec<(f64, f64)> {
+        self.positions
+            .get(symbol)
+            .map(|p| p.lots.iter().map(|l| (l.quantity, l.price)).collect())
+            .unwrap_or_default()
     }
 
     /// Update current market price for a position
+    ///
+    /// Prices for symbols not held are ignored, or raise KeyError in
+    /// strict mode (see `set_strict`).
     /// 
     /// # Arguments
     /// * `symbol` - Instrument symbol
     /// * `price` - Current market price
-    pub fn update_price(&mut self, symbol: &str, price: f64) {
-        if let Some(pos) = self.positions.get_mut(symbol) {
-            pos.current_price = price;
+    #[pyo3(name = "update_price")]
+    fn py_update_price(&mut self, symbol: &str, price: f64) -> Result<(), Error> {
+        if self.strict && !self.positions.contains_key(symbol) {
+            return Err(Error::UnknownSymbol(symbol.to_string()));
         }
+        self.update_price(symbol, price);
+        Ok(())
     }
 
-    /// Add realized P&L from a closed trade
-    /// 
-    /// # Arguments
-    /// * `pnl` - Realized profit/loss amount
-    pub fn add_realized_pnl(&mut self, pnl: f64) {
-        self.realized_pnl += pnl;
+    /// Make `update_price` raise KeyError for symbols not held
+    ///
+    /// Off by default, where such prices are silently ignored.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
     }
 
-    /// Get total unrealized P&L across all positions
-    pub fn unrealized_pnl(&self) -> f64 {
-        self.positions.values().map(|p| p.unrealized_pnl()).sum()
+    /// Check whether strict mode is on
+    pub fn is_strict(&self) -> bool {
+        self.strict
     }
 
-    /// Get realized P&L for the day
-    pub fn get_realized_pnl(&self) -> f64 {
-        self.realized_pnl
+    /// Allow or forbid trades that reverse a position in one step
+    ///
+    /// With flips forbidden, an `update_position`, `add_fill` or
+    /// `close_quantity` that would take a long position short (or vice
+    /// versa) raises RuntimeError and changes nothing; close the position
+    /// first. Guards against sign errors in order routing. Allowed by
+    /// default.
+    pub fn set_allow_flips(&mut self, allow: bool) {
+        self.allow_flips = allow;
     }
 
-    /// Get total P&L (realized + unrealized)
-    pub fn total_pnl(&self) -> f64 {
-        self.realized_pnl + self.unrealized_pnl()
+    /// Check whether trades may reverse a position in one step
+    pub fn allows_flips(&self) -> bool {
+        self.allow_flips
     }
 
-    /// Check if daily loss limit is breached
-    pub fn is_daily_loss_breached(&self) -> bool {
-        self.total_pnl() <= -self.max_daily_loss
+    /// Update current prices for many positions in one call
+    ///
+    /// Symbols not currently held are ignored. Equity tracking and the
+    /// breach check run once, on the state after the whole snapshot.
+    ///
+    /// # Arguments
+    /// * `prices` - Map of symbol -> latest price
+    pub fn update_prices(&mut self, prices: HashMap<String, f64>) {
+        for (symbol, price) in prices {
+            if let Some(pos) = self.positions.get_mut(&symbol) {
+                pos.current_price = price;
+                pos.trail();
+            }
+        }
+        self.track_equity();
     }
 
-    /// Get remaining risk budget before circuit breaker
-    pub fn remaining_risk(&self) -> f64 {
-        self.max_daily_loss + self.total_pnl()
-    }
+    /// Trail a stop `distance` behind the best price since now
+    ///
+    /// For longs the stop ratchets up with new highs, for shorts down
+    /// with new lows; it never loosens. The stop is dropped if the
+    /// position flips side.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol (must have an open position)
+    /// * `distance` - Stop distance in price units (must be > 0)
+    pub fn set_trailing_stop(&mut self, symbol: &str, distance: f64) -> Result<(), Error> {
+        validate_trailing_distance(distance)?;
 
-    /// Get number of open positions
-    pub fn position_count(&self) -> usize {
-        self.positions.len()
+        let pos = self
+            .positions
+            .get_mut(symbol)
+            .ok_or_else(|| Error::UnknownSymbol(symbol.to_string()))?;
+
+        // Start from the better of entry and the current price
+        let best = if pos.quantity > 0.0 {
+            pos.entry_price.max(pos.current_price)
+        } else {
+            pos.entry_price.min(pos.current_price)
+        };
+        pos.trailing_stop = Some(TrailingStop { distance, best });
+        Ok(())
     }
 
-    /// Check if a specific position exists
-    pub fn has_position(&self, symbol: &str) -> bool {
-        self.positions.contains_key(symbol)
+    /// Remove a symbol's trailing stop
+    pub fn clear_trailing_stop(&mut self, symbol: &str) {
+        if let Some(pos) = self.positions.get_mut(symbol) {
+            pos.trailing_stop = None;
+        }
     }
 
-    /// Get position quantity for a symbol (0 if no position)
-    pub fn get_quantity(&self, symbol: &str) -> i32 {
-        self.positions.get(symbol).map(|p| p.quantity).unwrap_or(0)
+    /// Get a symbol's current trailing stop level (None if not set)
+    pub fn get_trailing_stop(&self, symbol: &str) -> Option<f64> {
+        self.positions.get(symbol)?.trailing_stop_level()
     }
 
-    /// Get position details as a list of dicts
-    pub fn get_positions(&self, py: Python) -> PyResult<Vec<PyObject>> {
-        let mut result = Vec::new();
-        
-        for pos in self.positions.values() {
-            let dict = pyo3::types::PyDict::new(py);
-            dict.set_item("symbol", &pos.symbol)?;
-            dict.set_item("quantity", pos.quantity)?;
-            dict.set_item("entry_price", pos.entry_price)?;
-            dict.set_item("current_price", pos.current_price)?;
-            dict.set_item("multiplier", pos.multiplier)?;
-            dict.set_item("unrealized_pnl", pos.unrealized_pnl())?;
-            result.push(dict.into());
+    /// Check whether the last price has crossed a symbol's trailing stop
+    ///
+    /// False for symbols with no trailing stop configured.
+    pub fn trailing_stop_hit(&self, symbol: &str) -> bool {
+        let pos = match self.positions.get(symbol) {
+            Some(pos) => pos,
+            None => return false,
+        };
+
+        match pos.trailing_stop_level() {
+            Some(level) if pos.quantity > 0.0 => pos.current_price <= level,
+            Some(level) => pos.current_price >= level,
+            None => false,
         }
-        
-        Ok(result)
     }
 
-    /// Reset for new trading day
-    pub fn reset_daily(&mut self) {
-        self.realized_pnl = 0.0;
-        // Note: positions are NOT cleared - they carry over
+    /// Set static stop-loss and take-profit prices for a position
+    ///
+    /// Levels are checked against the last price by `check_levels`,
+    /// respecting direction: a long stops out at or below `stop` and
+    /// takes profit at or above `target`, a short the reverse. Levels
+    /// are dropped when the position is removed or flips side.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol (must have an open position)
+    /// * `stop` - Stop-loss price (None to clear)
+    /// * `target` - Take-profit price (None to clear)
+    #[pyo3(signature = (symbol, stop=None, target=None))]
+    pub fn set_levels(
+        &mut self,
+        symbol: &str,
+        stop: Option<f64>,
+        target: Option<f64>,
+    ) -> Result<(), Error> {
+        for level in stop.into_iter().chain(target) {
+            if !level.is_finite() {
+                return Err(Error::InvalidArgument(format!(
+                    "Exit level must be finite, got {}",
+                    level
+                )));
+            }
+        }
+
+        let pos = self
+            .positions
+            .get_mut(symbol)
+            .ok_or_else(|| Error::UnknownSymbol(symbol.to_string()))?;
+        pos.stop_level = stop;
+        pos.target_level = target;
+        Ok(())
     }
 
-    /// Clear all positions (for emergency flatten)
-    pub fn clear_positions(&mut self) {
-        self.positions.clear();
+    /// Get a symbol's (stop, target) levels (None if not held)
+    pub fn get_levels(&self, symbol: &str) -> Option<(Option<f64>, Option<f64>)> {
+        let pos = self.positions.get(symbol)?;
+        Some((pos.stop_level, pos.target_level))
     }
 
-    /// Get the daily loss limit
-    pub fn get_max_daily_loss(&self) -> f64 {
-        self.max_daily_loss
+    /// Check whether the last price has reached a symbol's stop or target
+    ///
+    /// None if neither level is reached, no levels are set, or the symbol
+    /// is not held.
+    pub fn check_levels(&self, symbol: &str) -> Option<LevelHit> {
+        self.positions.get(symbol)?.level_hit()
     }
 
-    /// Update the daily loss limit
-    pub fn set_max_daily_loss(&mut self, limit: f64) {
-        self.max_daily_loss = limit.abs();
+    /// Update current bid/ask quotes for a position
+    ///
+    /// Used by the "mid" and "conservative" mark sources.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `bid` - Best bid (None if unavailable)
+    /// * `ask` - Best ask (None if unavailable)
+    pub fn update_quote(&mut self, symbol: &str, bid: Option<f64>, ask: Option<f64>) {
+        if let Some(pos) = self.positions.get_mut(symbol) {
+            pos.bid = bid;
+            pos.ask = ask;
+        }
+        self.track_equity();
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Set which price marks positions for unrealized P&L
+    ///
+    /// * "last" - last traded price (default)
+    /// * "mid" - bid/ask midpoint
+    /// * "conservative" - bid for longs, ask for shorts
+    ///
+    /// Positions missing the required quotes fall back to the last price.
+    pub fn set_mark_source(&mut self, mode: &str) -> Result<(), Error> {
+        self.mark_source = mode.parse()?;
+        self.track_equity();
+        Ok(())
+    }
 
-    #[test]
-    fn test_new_calculator() {
-        let calc = RiskCalculator::new(500.0);
-        assert_eq!(calc.position_count(), 0);
-        assert_eq!(calc.total_pnl(), 0.0);
-        assert!(!calc.is_daily_loss_breached());
+    /// Get the current mark source
+    pub fn get_mark_source(&self) -> &'static str {
+        self.mark_source.as_str()
     }
 
-    #[test]
-    fn test_add_position() {
-        let mut calc = RiskCalculator::new(500.0);
-        
-        calc.update_position("MES".to_string(), 1, 5000.0, 5.0);
-        
-        assert_eq!(calc.position_count(), 1);
-        assert!(calc.has_position("MES"));
-        assert_eq!(calc.get_quantity("MES"), 1);
+    /// Add or replace a multi-leg spread (calendar, butterfly, ...)
+    ///
+    /// Spreads are tracked separately from single positions; their legs
+    /// are marked together and reported as one net P&L.
+    ///
+    /// # Arguments
+    /// * `name` - Spread identifier (e.g., "MES_CAL_H5M5")
+    /// * `legs` - List of (symbol, ratio, entry_price, multiplier) where
+    ///   ratio is signed (positive=long, negative=short) and multiplier
+    ///   must be > 0
+    pub fn add_spread(&mut self, name: String, legs: Vec<(String, f64, f64, f64)>) -> Result<(), Error> {
+        for &(_, _, _, multiplier) in &legs {
+            validate_multiplier(multiplier)?;
+        }
+
+        let legs = legs
+            .into_iter()
+            .map(|(symbol, ratio, entry_price, multiplier)| SpreadLeg {
+                symbol,
+                ratio,
+                entry_price,
+                current_price: entry_price,
+                multiplier,
+            })
+            .collect();
+
+        self.spreads.insert(name, legs);
+        Ok(())
+    }
+
+    /// Update current market prices for the legs of a spread
+    ///
+    /// Legs whose symbol is missing from `prices` keep their last price.
+    ///
+    /// # Arguments
+    /// * `name` - Spread identifier
+    /// * `prices` - Mapping of leg symbol to current market price
+    pub fn update_spread_prices(&mut self, name: &str, prices: HashMap<String, f64>) {
+        if let Some(legs) = self.spreads.get_mut(name) {
+            for leg in legs.iter_mut() {
+                if let Some(&price) = prices.get(&leg.symbol) {
+                    leg.current_price = price;
+                }
+            }
+        }
+    }
+
+    /// Get net unrealized P&L across all legs of a spread (None if unknown)
+    pub fn spread_pnl(&self, name: &str) -> Option<f64> {
+        self.spreads
+            .get(name)
+            .map(|legs| legs.iter().map(|l| l.unrealized_pnl()).sum())
+    }
+
+    /// Check if a specific spread exists
+    pub fn has_spread(&self, name: &str) -> bool {
+        self.spreads.contains_key(name)
+    }
+
+    /// Remove a spread
+    pub fn remove_spread(&mut self, name: &str) {
+        self.spreads.remove(name);
+    }
+
+    /// Set the default commission charged per contract on each fill
+    pub fn set_commission(&mut self, per_contract: f64) {
+        self.commission_per_contract = per_contract.abs();
+    }
+
+    /// Override the per-contract commission for a single symbol
+    pub fn set_symbol_commission(&mut self, symbol: String, per_contract: f64) {
+        self.symbol_commissions.insert(symbol, per_contract.abs());
+    }
+
+    /// Get total commissions paid today
+    ///
+    /// Realized P&L is net of these; gross = realized + commissions.
+    pub fn total_commissions(&self) -> f64 {
+        self.total_commissions
+    }
+
+    /// Get total traded notional today (`|quantity| * price * multiplier`)
+    ///
+    /// Counts fills from `add_fill` and `close_quantity`, converted to the
+    /// base currency; `update_position` sets state and is not a trade.
+    pub fn total_turnover(&self) -> f64 {
+        self.turnover
+    }
+
+    /// Get the number of fills executed today
+    pub fn fill_count(&self) -> usize {
+        self.fill_count
+    }
+
+    /// Add realized P&L from a closed trade
+    /// 
+    /// Each call counts as one trade for win/loss statistics; a zero
+    /// P&L trade counts as neither.
+    ///
+    /// # Arguments
+    /// * `pnl` - Realized profit/loss amount
+    pub fn add_realized_pnl(&mut self, pnl: f64) {
+        self.record_trade(pnl);
+        self.track_equity();
+    }
+
+    /// Get the number of winning trades today
+    pub fn win_count(&self) -> usize {
+        self.win_count
+    }
+
+    /// Get the number of losing trades today
+    pub fn loss_count(&self) -> usize {
+        self.loss_count
+    }
+
+    /// Get wins / (wins + losses) (0 if no decided trades)
+    pub fn win_rate(&self) -> f64 {
+        let decided = self.win_count + self.loss_count;
+        if decided > 0 {
+            self.win_count as f64 / decided as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the average winning trade (0 if no wins)
+    pub fn avg_win(&self) -> f64 {
+        if self.win_count > 0 {
+            self.gross_wins / self.win_count as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the average losing trade as a positive amount (0 if no losses)
+    pub fn avg_loss(&self) -> f64 {
+        if self.loss_count > 0 {
+            self.gross_losses / self.loss_count as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Get gross wins / gross losses (None if there are no losses)
+    pub fn profit_factor(&self) -> Option<f64> {
+        if self.loss_count > 0 {
+            Some(self.gross_wins / self.gross_losses)
+        } else {
+            None
+        }
+    }
+
+    /// Add realized P&L from a closed trade in a specific symbol
+    ///
+    /// Counts toward both the aggregate and the symbol's realized P&L.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `pnl` - Realized profit/loss amount
+    pub fn add_realized_pnl_for(&mut self, symbol: String, pnl: f64) {
+        *self.realized_by_symbol.entry(symbol).or_insert(0.0) += pnl;
+        self.add_realized_pnl(pnl);
+    }
+
+    /// Get total unrealized P&L across all positions, in the base currency
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.positions
+            .values()
+            .map(|p| p.unrealized_pnl(self.mark_source) * self.fx_rate_for(&p.symbol))
+            .sum()
+    }
+
+    /// Get one position's unrealized P&L, in the base currency
+    ///
+    /// Uses the same mark as `unrealized_pnl`. Returns None if the symbol
+    /// is not held.
+    pub fn unrealized_pnl_for(&self, symbol: &str) -> Option<f64> {
+        let pos = self.positions.get(symbol)?;
+        Some(pos.unrealized_pnl(self.mark_source) * self.fx_rate_for(symbol))
+    }
+
+    /// Get probability-weighted expected unrealized P&L for a position,
+    /// in the base currency
+    ///
+    /// Evaluates the position at each scenario price without mutating
+    /// state. Returns 0.0 if the symbol is not held.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `scenarios` - List of (price, probability) pairs; probabilities
+    ///   must be non-negative and sum to 1
+    pub fn expected_pnl(&self, symbol: &str, scenarios: Vec<(f64, f64)>) -> Result<f64, Error> {
+        if scenarios.iter().any(|&(_, p)| p < 0.0 || p.is_nan()) {
+            return Err(Error::InvalidArgument(
+                "Scenario probabilities must be non-negative".to_string(),
+            ));
+        }
+
+        let total_prob: f64 = scenarios.iter().map(|&(_, p)| p).sum();
+        if (total_prob - 1.0).abs() > 1e-6 {
+            return Err(Error::InvalidArgument(format!(
+                "Scenario probabilities must sum to 1, got {}",
+                total_prob
+            )));
+        }
+
+        let pos = match self.positions.get(symbol) {
+            Some(pos) => pos,
+            None => return Ok(0.0),
+        };
+
+        let expected: f64 = scenarios
+            .iter()
+            .map(|&(price, p)| {
+                p * (price - pos.entry_price) * pos.quantity * pos.multiplier
+            })
+            .sum();
+        Ok(expected * self.fx_rate_for(symbol))
+    }
+
+    /// Get total P&L at each hypothetical price of one symbol as a numpy array
+    ///
+    /// The symbol is marked at each grid price while every other position
+    /// keeps its current mark; realized P&L is included. State is not
+    /// mutated. A symbol not held gives a flat curve at `total_pnl()`.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol to move
+    /// * `prices` - Grid of hypothetical prices for `symbol`
+    pub fn pnl_curve<'py>(
+        &self,
+        py: Python<'py>,
+        symbol: &str,
+        prices: PyReadonlyArray1<'py, f64>,
+    ) -> Bound<'py, PyArray1<f64>> {
+        let curve = self.pnl_curve_values(symbol, prices.as_array().iter().copied());
+        PyArray1::from_vec(py, curve)
+    }
+
+    /// Get total unrealized P&L under per-symbol percentage price shocks,
+    /// in the base currency
+    ///
+    /// Each position's last price is moved by its symbol's shock; symbols
+    /// without a shock stay put. State is not mutated.
+    ///
+    /// # Arguments
+    /// * `shocks` - Map of symbol -> percent move (e.g., -5.0 = down 5%)
+    pub fn stress_test_vector(&self, shocks: HashMap<String, f64>) -> f64 {
+        self.positions
+            .values()
+            .map(|pos| {
+                let shock = shocks.get(&pos.symbol).copied().unwrap_or(0.0);
+                let price = pos.current_price * (1.0 + shock / 100.0);
+                let pnl = (price - pos.entry_price) * pos.quantity * pos.multiplier;
+                pnl * self.fx_rate_for(&pos.symbol)
+            })
+            .sum()
+    }
+
+    /// Get parametric (normal) value-at-risk of the book as a positive loss
+    ///
+    /// Each position's exposure is its signed base-currency notional times
+    /// its return stddev; correlations are ignored, so exposures add in quadrature:
+    /// `VaR = z(confidence) * sqrt(Σ (notional_i * vol_i)²) * sqrt(horizon)`.
+    ///
+    /// Raises KeyError if a held symbol has no volatility.
+    ///
+    /// # Arguments
+    /// * `vols` - Map of symbol -> per-period return stddev
+    /// * `confidence` - Confidence level in (0, 1) (e.g., 0.99)
+    /// * `horizon_scaling` - Horizon in vol periods (e.g., 10 for a
+    ///   10-day VaR from daily vols); scales by its square root
+    pub fn value_at_risk(
+        &self,
+        vols: HashMap<String, f64>,
+        confidence: f64,
+        horizon_scaling: f64,
+    ) -> Result<f64, Error> {
+        let sigma = self.portfolio_sigma(&vols, confidence, horizon_scaling)?;
+        Ok(normal_quantile(confidence) * sigma)
+    }
+
+    /// Get parametric (normal) expected shortfall as a positive loss
+    ///
+    /// The mean loss beyond the VaR at the same confidence:
+    /// `ES = sigma * pdf(z) / (1 - confidence)`, with sigma as in
+    /// `value_at_risk`.
+    ///
+    /// # Arguments
+    /// * `vols` - Map of symbol -> per-period return stddev
+    /// * `confidence` - Confidence level in (0, 1) (e.g., 0.99)
+    /// * `horizon_scaling` - Horizon in vol periods
+    pub fn expected_shortfall(
+        &self,
+        vols: HashMap<String, f64>,
+        confidence: f64,
+        horizon_scaling: f64,
+    ) -> Result<f64, Error> {
+        let sigma = self.portfolio_sigma(&vols, confidence, horizon_scaling)?;
+        let z = normal_quantile(confidence);
+        let pdf = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+        Ok(sigma * pdf / (1.0 - confidence))
+    }
+
+    /// Get realized P&L for the day
+    pub fn get_realized_pnl(&self) -> f64 {
+        self.realized_pnl
+    }
+
+    /// Get realized P&L booked against one symbol (0 if none)
+    ///
+    /// Only symbol-tagged P&L counts: fills, commissions and
+    /// `add_realized_pnl_for`, but not plain `add_realized_pnl`.
+    pub fn get_realized_pnl_by_symbol(&self, symbol: &str) -> f64 {
+        self.realized_by_symbol.get(symbol).copied().unwrap_or(0.0)
+    }
+
+    /// Get realized P&L for every symbol with booked P&L as a dict
+    pub fn get_all_realized_pnl(&self) -> HashMap<String, f64> {
+        self.realized_by_symbol.clone()
+    }
+
+    /// Get total P&L (realized + unrealized)
+    pub fn total_pnl(&self) -> f64 {
+        self.realized_pnl + self.unrealized_pnl()
+    }
+
+    /// Check if daily loss limit is breached
+    ///
+    /// Once a profit lock is engaged the limit is the locked-in level
+    /// (see `effective_loss_limit`).
+    pub fn is_daily_loss_breached(&self) -> bool {
+        self.total_pnl() <= self.effective_loss_limit()
+    }
+
+    /// Lock in profit once total P&L reaches `trigger`
+    ///
+    /// After the session's peak total P&L reaches `trigger`, the loss
+    /// limit tightens to `peak - giveback` (never looser than the daily
+    /// loss limit), so giving back more than `giveback` from the peak
+    /// counts as a breach.
+    ///
+    /// # Arguments
+    /// * `trigger` - Total P&L at which the lock engages
+    /// * `giveback` - Max decline from the peak once locked (>= 0)
+    pub fn set_profit_lock(&mut self, trigger: f64, giveback: f64) -> Result<(), Error> {
+        validate_profit_lock(trigger, giveback)?;
+        self.profit_lock = Some((trigger, giveback));
+        self.track_equity();
+        Ok(())
+    }
+
+    /// Remove the profit lock
+    pub fn clear_profit_lock(&mut self) {
+        self.profit_lock = None;
+        self.track_equity();
+    }
+
+    /// Get the profit lock as (trigger, giveback) (None if not set)
+    pub fn get_profit_lock(&self) -> Option<(f64, f64)> {
+        self.profit_lock
+    }
+
+    /// Get the total P&L level at which the daily loss limit is breached
+    ///
+    /// `-max_daily_loss` until a profit lock engages, then
+    /// `peak_equity - giveback`.
+    pub fn effective_loss_limit(&self) -> f64 {
+        let floor = -self.max_daily_loss;
+        match self.profit_lock {
+            Some((trigger, giveback)) if self.peak_equity >= trigger => {
+                floor.max(self.peak_equity - giveback)
+            }
+            _ => floor,
+        }
+    }
+
+    /// Register a callable fired once when the daily loss limit is breached
+    ///
+    /// `cb(total_pnl)` is called on the update that first takes total P&L
+    /// to or below `effective_loss_limit()`: `-max_daily_loss`, or the
+    /// tighter profit-lock floor once the lock engages. It does not fire
+    /// again until P&L recovers above the limit and breaches it anew.
+    /// Exceptions raised by the callback are reported as a
+    /// `RuntimeWarning` and swallowed.
+    /// The calculator is busy while the callback runs, so the callback
+    /// should use its argument rather than call back into the calculator.
+    pub fn set_breach_callback(&mut self, cb: Py<PyAny>) {
+        self.set_breach_hook(Arc::new(move |total_pnl| {
+            Python::attach(|py| {
+                if let Err(err) = cb.call1(py, (total_pnl,)) {
+                    let msg = CString::new(format!("Breach callback raised: {}", err))
+                        .unwrap_or_default();
+                    let category = py.get_type::<PyRuntimeWarning>();
+                    // Nothing sensible to do if the warning itself raises
+                    let _ = PyErr::warn(py, &category, &msg, 1);
+                }
+            })
+        }));
+    }
+
+    /// Remove the breach callback
+    pub fn clear_breach_callback(&mut self) {
+        self.breach_hook = BreachHookSlot::default();
+    }
+
+    /// Set the fraction of the daily loss limit that raises a warning
+    ///
+    /// # Arguments
+    /// * `frac` - Fraction in (0, 1) (e.g., 0.8 = warn at 80% of the limit)
+    pub fn set_warning_fraction(&mut self, frac: f64) -> Result<(), Error> {
+        validate_warning_fraction(frac)?;
+        self.warning_fraction = Some(frac);
+        Ok(())
+    }
+
+    /// Get the warning fraction (None if not set)
+    pub fn get_warning_fraction(&self) -> Option<f64> {
+        self.warning_fraction
+    }
+
+    /// Classify total P&L against the daily loss limit
+    ///
+    /// `Warning` once the loss exceeds `frac * max_daily_loss`, `Breached`
+    /// once the limit itself is hit. Never `Warning` if no fraction is set.
+    pub fn risk_state(&self) -> RiskState {
+        if self.is_daily_loss_breached() {
+            return RiskState::Breached;
+        }
+
+        match self.warning_fraction {
+            Some(frac) if self.total_pnl() < -frac * self.max_daily_loss => RiskState::Warning,
+            _ => RiskState::Ok,
+        }
+    }
+
+    /// Set a loss limit for a single symbol
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `limit` - Maximum loss allowed on this symbol (positive number)
+    pub fn set_symbol_loss_limit(&mut self, symbol: String, limit: f64) {
+        self.symbol_loss_limits.insert(symbol, limit.abs());
+    }
+
+    /// Get the loss limit for a symbol (None if not configured)
+    pub fn get_symbol_loss_limit(&self, symbol: &str) -> Option<f64> {
+        self.symbol_loss_limits.get(symbol).copied()
+    }
+
+    /// Check if a symbol's realized + unrealized P&L breaches its limit
+    ///
+    /// Symbols without a configured limit never report a breach.
+    pub fn is_symbol_loss_breached(&self, symbol: &str) -> bool {
+        match self.symbol_loss_limits.get(symbol) {
+            Some(&limit) => self.symbol_pnl(symbol) <= -limit,
+            None => false,
+        }
+    }
+
+    /// Set a profit target for a symbol
+    ///
+    /// The target is checked against the symbol's realized + unrealized
+    /// P&L, so booked gains count toward it.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `target` - Combined P&L at which the symbol should be closed
+    pub fn set_profit_target(&mut self, symbol: String, target: f64) {
+        self.profit_targets.insert(symbol, target);
+    }
+
+    /// Get symbols whose realized + unrealized P&L has reached their target
+    ///
+    /// Returned in alphabetical order.
+    pub fn at_profit_target(&self) -> Vec<String> {
+        let mut hit: Vec<String> = self
+            .profit_targets
+            .iter()
+            .filter(|(symbol, &target)| self.symbol_pnl(symbol) >= target)
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        hit.sort();
+        hit
+    }
+
+    /// Get remaining risk budget before circuit breaker
+    pub fn remaining_risk(&self) -> f64 {
+        self.total_pnl() - self.effective_loss_limit()
+    }
+
+    /// Get the sum of absolute notional across all positions
+    ///
+    /// Like all exposure figures, in the base currency.
+    pub fn gross_exposure(&self) -> f64 {
+        self.positions.values().map(|p| self.base_notional(p).abs()).sum()
+    }
+
+    /// Get the signed sum of notional across all positions (long - short)
+    pub fn net_exposure(&self) -> f64 {
+        self.positions.values().map(|p| self.base_notional(p)).sum()
+    }
+
+    /// Get the book's net exposure in reference-instrument terms
+    ///
+    /// Sums `beta * notional` over positions, with notionals in the base
+    /// currency and beta each symbol's
+    /// sensitivity to the reference instrument. Dividing by one reference
+    /// contract's notional gives the number of contracts to trade (with
+    /// the opposite sign) to flatten directional risk.
+    ///
+    /// Raises KeyError if a held symbol has no beta, so a missing hedge
+    /// ratio is never silently treated as 1.
+    ///
+    /// # Arguments
+    /// * `betas` - Map of symbol -> beta to the reference (1.0 for the
+    ///   reference itself)
+    pub fn net_delta(&self, betas: HashMap<String, f64>) -> Result<f64, Error> {
+        self.positions
+            .values()
+            .map(|pos| {
+                let beta = betas
+                    .get(&pos.symbol)
+                    .ok_or_else(|| Error::UnknownSymbol(pos.symbol.clone()))?;
+                Ok(beta * self.base_notional(pos))
+            })
+            .sum()
+    }
+
+    /// Get the notional of long positions (>= 0)
+    pub fn long_exposure(&self) -> f64 {
+        self.positions
+            .values()
+            .map(|p| self.base_notional(p))
+            .filter(|n| *n > 0.0)
+            .sum()
+    }
+
+    /// Get the absolute notional of short positions (>= 0)
+    pub fn short_exposure(&self) -> f64 {
+        self.positions
+            .values()
+            .map(|p| self.base_notional(p))
+            .filter(|n| *n < 0.0)
+            .map(f64::abs)
+            .sum()
+    }
+
+    /// Get each position's contribution to P&L and exposure
+    ///
+    /// Returns a list of dicts with `symbol`, `unrealized_pnl`, signed
+    /// `notional` (both in the base currency) and `pct_of_gross` (share
+    /// of `gross_exposure`, 0-100), sorted by absolute notional, largest
+    /// first.
+    pub fn risk_breakdown(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let rows = pyo3::types::PyList::empty(py);
+        for (symbol, pnl, notional, pct) in self.risk_breakdown_rows() {
+            let dict = pyo3::types::PyDict::new(py);
+            dict.set_item("symbol", symbol)?;
+            dict.set_item("unrealized_pnl", pnl)?;
+            dict.set_item("notional", notional)?;
+            dict.set_item("pct_of_gross", pct)?;
+            rows.append(dict)?;
+        }
+        Ok(rows.into_any().unbind())
+    }
+
+    /// Get held symbols in the order they should be closed when unwinding
+    ///
+    /// With `by="pnl"` the worst unrealized P&L (base currency) comes
+    /// first; with `by="notional"` the largest absolute base-currency
+    /// notional does.
+    /// Ties are broken by symbol. Read-only: feeds an order scheduler
+    /// during an emergency flatten.
+    ///
+    /// # Arguments
+    /// * `by` - Sort key, "pnl" (default) or "notional"
+    #[pyo3(signature = (by="pnl"))]
+    pub fn positions_by_unwind_priority(&self, by: &str) -> Result<Vec<String>, Error> {
+        let key: Box<dyn Fn(&Position) -> f64> = match by {
+            "pnl" => Box::new(|p| p.unrealized_pnl(self.mark_source) * self.fx_rate_for(&p.symbol)),
+            "notional" => Box::new(|p| -self.base_notional(p).abs()),
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "Unknown unwind priority '{}', expected 'pnl' or 'notional'",
+                    by
+                )))
+            }
+        };
+
+        let mut keyed: Vec<(f64, &String)> =
+            self.positions.values().map(|p| (key(p), &p.symbol)).collect();
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+        Ok(keyed.into_iter().map(|(_, symbol)| symbol.clone()).collect())
+    }
+
+    /// Get the largest single-position fraction of gross exposure
+    ///
+    /// Returns 0 when there is no exposure.
+    pub fn max_position_weight(&self) -> f64 {
+        max_weight(self.positions.values().map(|p| self.base_notional(p).abs()))
+    }
+
+    /// Set the max fraction of gross exposure any one position may hold
+    ///
+    /// # Arguments
+    /// * `limit` - Fraction in (0, 1] (e.g., 0.5 = 50%)
+    pub fn set_max_concentration(&mut self, limit: f64) -> Result<(), Error> {
+        validate_concentration(limit)?;
+        self.max_concentration = Some(limit);
+        Ok(())
+    }
+
+    /// Get the concentration limit (None if not set)
+    pub fn get_max_concentration(&self) -> Option<f64> {
+        self.max_concentration
+    }
+
+    /// Check whether a proposed order would push any position above the
+    /// concentration limit
+    ///
+    /// The order is added to any existing position in `symbol`, which is
+    /// then valued at `price` (in the symbol's currency). Always false
+    /// when no limit is set.
+    ///
+    /// # Arguments
+    /// * `symbol` - Instrument symbol
+    /// * `quantity` - Signed order size
+    /// * `price` - Expected fill price
+    /// * `multiplier` - Contract multiplier
+    pub fn would_breach_concentration(
+        &self,
+        symbol: &str,
+        quantity: f64,
+        price: f64,
+        multiplier: f64,
+    ) -> bool {
+        let limit = match self.max_concentration {
+            Some(limit) => limit,
+            None => return false,
+        };
+
+        let proposed = (self.get_quantity(symbol) + quantity) * price * multiplier
+            * self.fx_rate_for(symbol);
+        let others = self
+            .positions
+            .values()
+            .filter(|p| p.symbol != symbol)
+            .map(|p| self.base_notional(p).abs());
+
+        max_weight(others.chain(std::iter::once(proposed.abs()))) > limit
+    }
+
+    /// Cap the number of simultaneously open positions
+    ///
+    /// Opening a new symbol beyond the cap raises RuntimeError; updates
+    /// to held positions and closes are always allowed.
+    ///
+    /// # Arguments
+    /// * `max` - Max open positions (None removes the cap)
+    #[pyo3(signature = (max))]
+    pub fn set_max_positions(&mut self, max: Option<usize>) {
+        self.max_positions = max;
+    }
+
+    /// Get the position count cap (None if not set)
+    pub fn get_max_positions(&self) -> Option<usize> {
+        self.max_positions
+    }
+
+    /// Check whether no further symbols can be opened under the cap
+    pub fn is_position_limit_reached(&self) -> bool {
+        self.max_positions.is_some_and(|max| self.positions.len() >= max)
+    }
+
+    /// Size a position so a stop-out loses at most `risk_amount`
+    ///
+    /// Returns the largest contract count with
+    /// `abs(entry - stop) * multiplier * qty <= risk_amount`, positive
+    /// (long) when the stop is below entry and negative (short) when it
+    /// is above. Returns 0 if the stop distance or risk budget is zero.
+    ///
+    /// # Arguments
+    /// * `entry` - Planned entry price
+    /// * `stop` - Stop-loss price
+    /// * `multiplier` - Contract multiplier
+    /// * `risk_amount` - Max loss in account currency
+    pub fn size_for_risk(&self, entry: f64, stop: f64, multiplier: f64, risk_amount: f64) -> i32 {
+        let risk_per_contract = (entry - stop).abs() * multiplier.abs();
+        if risk_per_contract <= 0.0 || risk_amount <= 0.0 {
+            return 0;
+        }
+
+        // Tolerance keeps e.g. a 0.2 stop that prints as 0.20000000001
+        // from rounding an exact fit down by a whole contract
+        let contracts = (risk_amount / risk_per_contract * (1.0 + 1e-9)).floor() as i32;
+        if stop < entry {
+            contracts
+        } else {
+            -contracts
+        }
+    }
+
+    /// Start recording total P&L after every P&L-changing operation
+    ///
+    /// Keeps the most recent `max_points` as (seconds since epoch,
+    /// total P&L). Any existing log is discarded.
+    pub fn enable_equity_log(&mut self, max_points: usize) -> Result<(), Error> {
+        validate_equity_log_len(max_points)?;
+
+        self.equity_log = Some(VecDeque::with_capacity(max_points));
+        self.equity_log_len = max_points;
+        Ok(())
+    }
+
+    /// Stop recording total P&L and free the log
+    pub fn disable_equity_log(&mut self) {
+        self.equity_log = None;
+        self.equity_log_len = 0;
+    }
+
+    /// Get the recorded equity curve as (time, total P&L), oldest first
+    ///
+    /// Empty if the log is disabled.
+    pub fn get_equity_curve(&self) -> Vec<(f64, f64)> {
+        self.equity_log
+            .as_ref()
+            .map(|log| log.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the highest total P&L seen since the day started
+    pub fn peak_equity(&self) -> f64 {
+        self.peak_equity
+    }
+
+    /// Get the current decline of total P&L from its peak (>= 0)
+    pub fn current_drawdown(&self) -> f64 {
+        (self.peak_equity - self.total_pnl()).max(0.0)
+    }
+
+    /// Get the largest peak-to-trough decline in total P&L since the day started
+    pub fn max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+
+    /// Set a drawdown limit, measured from the session's peak total P&L
+    ///
+    /// Coexists with the daily loss limit. Unrealized swings count both
+    /// toward the peak and toward the drawdown.
+    ///
+    /// # Arguments
+    /// * `amount` - Max decline from the peak (positive number)
+    pub fn set_max_drawdown(&mut self, amount: f64) -> Result<(), Error> {
+        validate_drawdown_limit(amount)?;
+        self.drawdown_limit = Some(amount);
+        Ok(())
+    }
+
+    /// Remove the drawdown limit
+    pub fn clear_max_drawdown(&mut self) {
+        self.drawdown_limit = None;
+    }
+
+    /// Get the drawdown limit (None if not set)
+    pub fn get_max_drawdown_limit(&self) -> Option<f64> {
+        self.drawdown_limit
+    }
+
+    /// Check if the current drawdown has reached the drawdown limit
+    ///
+    /// Always false when no limit is set.
+    pub fn is_drawdown_breached(&self) -> bool {
+        self.drawdown_limit.is_some_and(|limit| self.current_drawdown() >= limit)
+    }
+
+    /// Check the daily loss, drawdown and per-symbol loss limits together
+    pub fn is_any_limit_breached(&self) -> bool {
+        self.is_daily_loss_breached()
+            || self.is_drawdown_breached()
+            || self
+                .symbol_loss_limits
+                .keys()
+                .any(|symbol| self.is_symbol_loss_breached(symbol))
+    }
+
+    /// Get seconds elapsed since total P&L was last at its peak
+    ///
+    /// Returns 0 while equity is at a peak.
+    pub fn drawdown_duration_secs(&self) -> f64 {
+        if self.current_drawdown() > 0.0 {
+            (self.now() - self.peak_time).max(0.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Pin the calculator's clock to `now_secs` (e.g., for backtests)
+    ///
+    /// Pass None to go back to wall-clock time.
+    pub fn set_clock(&mut self, now_secs: Option<f64>) {
+        self.clock = now_secs;
+    }
+
+    /// Get number of open positions
+    pub fn position_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Check if a specific position exists
+    pub fn has_position(&self, symbol: &str) -> bool {
+        self.positions.contains_key(symbol)
+    }
+
+    /// Get position quantity for a symbol (0 if no position)
+    pub fn get_quantity(&self, symbol: &str) -> f64 {
+        self.positions.get(symbol).map(|p| p.quantity).unwrap_or(0.0)
+    }
+
+    /// Compare positions against an external (e.g., broker) snapshot
+    ///
+    /// Returns a dict of symbol -> (internal, external) quantity for
+    /// every symbol where the two differ. A symbol missing on either side
+    /// counts as flat. An empty dict means the books agree.
+    ///
+    /// # Arguments
+    /// * `external` - Map of symbol -> signed quantity
+    pub fn reconcile(&self, external: HashMap<String, f64>) -> HashMap<String, (f64, f64)> {
+        let mut discrepancies = HashMap::new();
+
+        for (symbol, &theirs) in &external {
+            let ours = self.get_quantity(symbol);
+            if !is_flat(ours - theirs) {
+                discrepancies.insert(symbol.clone(), (ours, theirs));
+            }
+        }
+
+        for pos in self.positions.values() {
+            if !external.contains_key(&pos.symbol) && !is_flat(pos.quantity) {
+                discrepancies.insert(pos.symbol.clone(), (pos.quantity, 0.0));
+            }
+        }
+
+        discrepancies
+    }
+
+    /// Get snapshots of all open positions
+    ///
+    /// Use `Position.to_dict()` for the mapping form.
+    pub fn get_positions(&self) -> Vec<Position> {
+        self.positions
+            .values()
+            .map(|pos| Position {
+                mark_source: self.mark_source,
+                ..pos.clone()
+            })
+            .collect()
+    }
+
+    /// Serialize the full calculator state to a dict
+    ///
+    /// Intended for checkpointing; restore with `RiskCalculator.from_dict`.
+    /// Holds the same state as `to_json` (positions with their lots and
+    /// quotes, mark source, realized P&L by symbol, commissions,
+    /// currencies, limits and statistics), so a round trip is exact. A
+    /// breach callback is not included.
+    pub fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let json = py.import("json")?;
+        Ok(json.call_method1("loads", (self.to_json()?,))?.unbind())
+    }
+
+    /// Rebuild a calculator from a dict produced by `to_dict`
+    ///
+    /// Raises ValueError if the dict is not a valid state. Older
+    /// checkpoints, which list positions as dicts of `symbol`,
+    /// `quantity`, `entry_price`, `current_price` and `multiplier`, are
+    /// still accepted: they restore positions, realized P&L and the loss
+    /// limit only, raising KeyError if a key is missing.
+    #[staticmethod]
+    pub fn from_dict(data: &Bound<'_, pyo3::types::PyDict>) -> PyResult<Self> {
+        let legacy = data
+            .get_item("positions")?
+            .is_some_and(|positions| positions.is_instance_of::<pyo3::types::PyList>());
+        if legacy {
+            return Self::from_legacy_dict(data);
+        }
+
+        let json = data.py().import("json")?;
+        let state: String = json.call_method1("dumps", (data,))?.extract()?;
+        Ok(Self::from_json(&state)?)
+    }
+
+    /// Dump the full calculator state as JSON (e.g., for incident logs)
+    ///
+    /// Covers positions and their lots, limits, realized P&L and
+    /// statistics. A breach callback is not included. Infinite limits
+    /// are written as strings. Raises ValueError for a state `from_json`
+    /// would reject, such as a non-finite P&L.
+    pub fn to_json(&self) -> Result<String, Error> {
+        self.check_state()?;
+        serde_json::to_string(self)
+            .map_err(|err| Error::InvalidArgument(format!("Cannot serialize state: {}", err)))
+    }
+
+    /// Rebuild a calculator from a `to_json` dump
+    ///
+    /// Raises ValueError if the JSON is not a valid state dump or
+    /// describes a calculator the setters would reject.
+    #[staticmethod]
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let calc: Self = serde_json::from_str(json)
+            .map_err(|err| Error::InvalidArgument(format!("Invalid state JSON: {}", err)))?;
+        calc.check_state()?;
+        Ok(calc)
+    }
+
+    /// Combine this calculator with another into an account-level view
+    ///
+    /// Neither input is modified. The roll-up rules are:
+    /// * positions in one calculator carry over unchanged; for shared
+    ///   symbols the other side's lots are applied as fills, so same-side
+    ///   positions add up at the blended entry price and opposing ones
+    ///   net out FIFO (realizing the P&L locked in between them)
+    /// * realized P&L, trade statistics, commissions and turnover are
+    ///   summed
+    /// * the daily loss limit is the sum of both limits
+    /// * per-symbol limits and targets, registered instruments, FX rates
+    ///   and spreads come from this calculator, with the other's filling
+    ///   in symbols this one lacks
+    /// * all other settings come from this calculator; drawdown tracking
+    ///   starts fresh from the merged equity, and no breach callback is
+    ///   installed
+    ///
+    /// Raises ValueError if a shared symbol has different multipliers.
+    pub fn merge(&self, other: &RiskCalculator) -> Result<RiskCalculator, Error> {
+        for (symbol, theirs) in &other.positions {
+            if let Some(ours) = self.positions.get(symbol) {
+                if ours.multiplier != theirs.multiplier {
+                    return Err(Error::InvalidArgument(format!(
+                        "Cannot merge {}: multipliers {} and {} differ",
+                        symbol, ours.multiplier, theirs.multiplier
+                    )));
+                }
+            }
+        }
+
+        let mut merged = self.clone();
+        merged.max_daily_loss += other.max_daily_loss;
+        merged.realized_pnl += other.realized_pnl;
+        for (symbol, pnl) in &other.realized_by_symbol {
+            *merged.realized_by_symbol.entry(symbol.clone()).or_insert(0.0) += pnl;
+        }
+        merged.win_count += other.win_count;
+        merged.loss_count += other.loss_count;
+        merged.gross_wins += other.gross_wins;
+        merged.gross_losses += other.gross_losses;
+        merged.total_commissions += other.total_commissions;
+        merged.turnover += other.turnover;
+        merged.fill_count += other.fill_count;
+
+        fill_missing(&mut merged.symbol_loss_limits, &other.symbol_loss_limits);
+        fill_missing(&mut merged.profit_targets, &other.profit_targets);
+        fill_missing(&mut merged.instruments, &other.instruments);
+        fill_missing(&mut merged.fx_rates, &other.fx_rates);
+        fill_missing(&mut merged.currencies, &other.currencies);
+        fill_missing(&mut merged.spreads, &other.spreads);
+
+        for (symbol, theirs) in &other.positions {
+            let pos = match merged.positions.get_mut(symbol) {
+                Some(pos) => pos,
+                None => {
+                    merged.positions.insert(symbol.clone(), theirs.clone());
+                    continue;
+                }
+            };
+
+            let realized: f64 = theirs
+                .lots
+                .iter()
+                .filter_map(|lot| pos.apply_fill(lot.quantity, lot.price))
+                .sum();
+            if is_flat(pos.quantity) {
+                merged.positions.remove(symbol);
+            }
+            if realized != 0.0 {
+                let realized = realized * merged.fx_rate_for(symbol);
+                merged.realized_pnl += realized;
+                *merged.realized_by_symbol.entry(symbol.clone()).or_insert(0.0) += realized;
+            }
+        }
+
+        merged.peak_equity = merged.total_pnl();
+        merged.peak_time = merged.now();
+        merged.max_drawdown = 0.0;
+        if let Some(log) = merged.equity_log.as_mut() {
+            log.clear();
+        }
+        merged.breach_armed = !merged.is_daily_loss_breached();
+        merged.breaker = CircuitBreaker::Active;
+        Ok(merged)
+    }
+
+    /// Reset for new trading day
+    ///
+    /// Realized P&L and today's statistics are cleared; positions carry
+    /// over. By default they keep their entry prices, so P&L accrued on
+    /// them before the reset still counts toward today's total and loss
+    /// limit (continuous mark).
+    ///
+    /// With `reprice_entries=True`, every carried position (and each of
+    /// its lots) is re-based to its last price: yesterday's unrealized
+    /// P&L is treated as settled and today starts flat on those
+    /// positions. That P&L is not booked anywhere in this calculator,
+    /// and later realized P&L is measured from the new entry prices, so
+    /// record the prior day's marks before resetting if they are needed.
+    ///
+    /// # Arguments
+    /// * `reprice_entries` - Re-base carried positions to their last price
+    #[pyo3(signature = (reprice_entries=false))]
+    pub fn reset_daily(&mut self, reprice_entries: bool) {
+        if reprice_entries {
+            for pos in self.positions.values_mut() {
+                pos.entry_price = pos.current_price;
+                for lot in pos.lots.iter_mut() {
+                    lot.price = pos.current_price;
+                }
+            }
+        }
+
+        self.realized_pnl = 0.0;
+        self.realized_by_symbol.clear();
+        self.total_commissions = 0.0;
+        self.turnover = 0.0;
+        self.fill_count = 0;
+        self.win_count = 0;
+        self.loss_count = 0;
+        self.gross_wins = 0.0;
+        self.gross_losses = 0.0;
+        // Note: positions are NOT cleared - they carry over
+
+        // Drawdown is measured from today's starting equity
+        self.peak_equity = self.total_pnl();
+        self.peak_time = self.now();
+        self.max_drawdown = 0.0;
+        self.breach_armed = !self.is_daily_loss_breached();
+        self.breaker = CircuitBreaker::Active;
+    }
+
+    /// Close every position at its current mark and realize its P&L
+    ///
+    /// Each position's unrealized P&L is booked as a closed trade in its
+    /// symbol and the position removed, so `total_pnl()` is unchanged by
+    /// the flatten. Unlike `clear_positions`, nothing is lost.
+    ///
+    /// Returns the total P&L realized by the flatten.
+    pub fn flatten_all(&mut self) -> f64 {
+        let mut closed: Vec<(String, f64)> = self
+            .positions
+            .values()
+            .map(|p| {
+                let pnl = p.unrealized_pnl(self.mark_source) * self.fx_rate_for(&p.symbol);
+                (p.symbol.clone(), pnl)
+            })
+            .collect();
+        closed.sort_by(|a, b| a.0.cmp(&b.0));
+        self.positions.clear();
+
+        // Book everything before tracking equity so the flatten never
+        // shows up as a drawdown
+        let mut total = 0.0;
+        for (symbol, pnl) in closed {
+            *self.realized_by_symbol.entry(symbol).or_insert(0.0) += pnl;
+            self.record_trade(pnl);
+            total += pnl;
+        }
+        self.track_equity();
+        self.cool_down();
+        total
+    }
+
+    /// Clear all positions (for emergency flatten)
+    ///
+    /// Drops open P&L; use `flatten_all` to realize it instead.
+    pub fn clear_positions(&mut self) {
+        self.positions.clear();
+        self.track_equity();
+        self.cool_down();
+    }
+
+    /// Get the circuit breaker state
+    ///
+    /// * `Active` - trading allowed
+    /// * `Tripped` - the daily loss limit was hit while active
+    /// * `Cooldown` - positions were flattened (`flatten_all` or
+    ///   `clear_positions`) after the trip
+    ///
+    /// Only `arm` or `reset_daily` return the breaker to `Active`, so a
+    /// strategy cannot drift back into the market right after a breach.
+    pub fn breaker_state(&self) -> CircuitBreaker {
+        self.breaker
+    }
+
+    /// Manually return the circuit breaker to `Active`
+    ///
+    /// This is an override: the loss limit is not re-checked, and the
+    /// breaker only trips again on a new breach after recovering.
+    pub fn arm(&mut self) {
+        self.breaker = CircuitBreaker::Active;
+    }
+
+    /// Get the daily loss limit
+    pub fn get_max_daily_loss(&self) -> f64 {
+        self.max_daily_loss
+    }
+
+    /// Update the daily loss limit
+    ///
+    /// Takes effect immediately: tightening the limit below the current
+    /// P&L trips the circuit breaker and fires the breach callback.
+    pub fn set_max_daily_loss(&mut self, limit: f64) {
+        self.max_daily_loss = limit.abs();
+        self.track_equity();
+    }
+}
+
+/// Reject contract multipliers that would zero out or invert P&L
+fn validate_multiplier(multiplier: f64) -> Result<(), Error> {
+    if multiplier.is_finite() && multiplier > 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "Multiplier must be > 0, got {}",
+            multiplier
+        )))
+    }
+}
+
+fn validate_fx_rate(rate_to_base: f64) -> Result<(), Error> {
+    if !rate_to_base.is_finite() || rate_to_base <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "FX rate must be > 0, got {}",
+            rate_to_base
+        )));
+    }
+    Ok(())
+}
+
+fn validate_trailing_distance(distance: f64) -> Result<(), Error> {
+    if !distance.is_finite() || distance <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "Trailing stop distance must be > 0, got {}",
+            distance
+        )));
+    }
+    Ok(())
+}
+
+fn validate_profit_lock(trigger: f64, giveback: f64) -> Result<(), Error> {
+    if !trigger.is_finite() {
+        return Err(Error::InvalidArgument(format!(
+            "Profit lock trigger must be finite, got {}",
+            trigger
+        )));
+    }
+    if !giveback.is_finite() || giveback < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "Profit lock giveback must be >= 0, got {}",
+            giveback
+        )));
+    }
+    Ok(())
+}
+
+fn validate_warning_fraction(frac: f64) -> Result<(), Error> {
+    if frac.is_nan() || frac <= 0.0 || frac >= 1.0 {
+        return Err(Error::InvalidArgument(format!(
+            "Warning fraction must be in (0, 1), got {}",
+            frac
+        )));
+    }
+    Ok(())
+}
+
+fn validate_concentration(limit: f64) -> Result<(), Error> {
+    if limit.is_nan() || limit <= 0.0 || limit > 1.0 {
+        return Err(Error::InvalidArgument(format!(
+            "Concentration limit must be in (0, 1], got {}",
+            limit
+        )));
+    }
+    Ok(())
+}
+
+fn validate_equity_log_len(max_points: usize) -> Result<(), Error> {
+    if max_points == 0 {
+        return Err(Error::InvalidArgument("Equity log length must be > 0".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_drawdown_limit(amount: f64) -> Result<(), Error> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "Drawdown limit must be > 0, got {}",
+            amount
+        )));
+    }
+    Ok(())
+}
+
+/// Largest share of the total among absolute notionals (0 if total is 0)
+fn max_weight(notionals: impl Iterator<Item = f64>) -> f64 {
+    let (gross, largest) = notionals.fold((0.0, 0.0_f64), |(gross, largest), n| {
+        (gross + n, largest.max(n))
+    });
+    if gross > 0.0 {
+        largest / gross
+    } else {
+        0.0
+    }
+}
+
+/// Inverse of the standard normal CDF for `p` in (0, 1)
+///
+/// Acklam's rational approximation (relative error < 1.2e-9).
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// Extract a required dict entry, raising KeyError if it is missing
+fn required<'py, T>(dict: &Bound<'py, pyo3::types::PyDict>, key: &str) -> PyResult<T>
+where
+    T: pyo3::conversion::FromPyObjectOwned<'py>,
+{
+    match dict.get_item(key)? {
+        Some(value) => value.extract().map_err(Into::into),
+        None => Err(pyo3::exceptions::PyKeyError::new_err(key.to_string())),
+    }
+}
+
+impl RiskCalculator {
+    /// Update current market price for a position, ignoring unknown symbols
+    ///
+    /// Lenient regardless of strict mode; see the Python-facing
+    /// `update_price`.
+    pub fn update_price(&mut self, symbol: &str, price: f64) {
+        if let Some(pos) = self.positions.get_mut(symbol) {
+            pos.current_price = price;
+            pos.trail();
+        }
+        self.track_equity();
+    }
+
+    /// Add or update a position with an explicit multiplier
+    ///
+    /// See the Python-facing `update_position` for argument details.
+    pub fn update_position(
+        &mut self,
+        symbol: String,
+        quantity: f64,
+        entry_price: f64,
+        multiplier: f64,
+    ) {
+        if is_flat(quantity) {
+            self.positions.remove(&symbol);
+        } else if let Some(pos) = self.positions.get_mut(&symbol) {
+            // Keep the latest market data for an existing position
+            if side(pos.quantity) != side(quantity) {
+                pos.clear_exits();
+            }
+            pos.quantity = quantity;
+            pos.entry_price = entry_price;
+            pos.multiplier = multiplier;
+            pos.lots = VecDeque::from([Lot { quantity, price: entry_price }]);
+        } else {
+            self.positions.insert(
+                symbol.clone(),
+                Position::new(symbol, quantity, entry_price, multiplier),
+            );
+        }
+        self.track_equity();
+    }
+
+    /// Horizon-scaled stddev of book P&L, assuming uncorrelated returns
+    fn portfolio_sigma(
+        &self,
+        vols: &HashMap<String, f64>,
+        confidence: f64,
+        horizon_scaling: f64,
+    ) -> Result<f64, Error> {
+        if confidence.is_nan() || confidence <= 0.0 || confidence >= 1.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Confidence must be in (0, 1), got {}",
+                confidence
+            )));
+        }
+        if horizon_scaling.is_nan() || horizon_scaling < 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Horizon scaling must be >= 0, got {}",
+                horizon_scaling
+            )));
+        }
+
+        let mut variance = 0.0;
+        for pos in self.positions.values() {
+            let vol = vols
+                .get(&pos.symbol)
+                .ok_or_else(|| Error::UnknownSymbol(pos.symbol.clone()))?;
+            let risk = self.base_notional(pos) * vol;
+            variance += risk * risk;
+        }
+
+        Ok((variance * horizon_scaling).sqrt())
+    }
+
+    /// Fail if opening `symbol` would exceed the position count cap
+    fn check_position_limit(&self, symbol: &str) -> Result<(), Error> {
+        if self.positions.contains_key(symbol) || !self.is_position_limit_reached() {
+            return Ok(());
+        }
+        Err(Error::LimitExceeded(format!(
+            "Cannot open {}: position limit of {} reached",
+            symbol,
+            self.max_positions.unwrap_or(0)
+        )))
+    }
+
+    /// Fail if flips are forbidden and taking `symbol` to `new_quantity`
+    /// would reverse its position
+    fn check_flip(&self, symbol: &str, new_quantity: f64) -> Result<(), Error> {
+        let held = match self.positions.get(symbol) {
+            Some(pos) if !self.allow_flips => pos.quantity,
+            _ => return Ok(()),
+        };
+        if is_flat(new_quantity) || side(new_quantity) == side(held) {
+            return Ok(());
+        }
+        Err(Error::LimitExceeded(format!(
+            "Cannot flip {} from {} to {}: close the position first",
+            symbol, held, new_quantity
+        )))
+    }
+
+    /// Look up a registered multiplier, failing for unknown symbols
+    fn registered_multiplier(&self, symbol: &str) -> Result<f64, Error> {
+        self.get_instrument_multiplier(symbol)
+            .ok_or_else(|| Error::UnknownSymbol(symbol.to_string()))
+    }
+
+    /// Rebuild a calculator from a pre-full-state `to_dict` checkpoint
+    fn from_legacy_dict(data: &Bound<'_, pyo3::types::PyDict>) -> PyResult<Self> {
+        let mut positions = Vec::new();
+        for item in required::<Bound<pyo3::types::PyList>>(data, "positions")?.iter() {
+            let pos = item.cast::<pyo3::types::PyDict>()?;
+            let mut position = Position::new(
+                required(pos, "symbol")?,
+                required(pos, "quantity")?,
+                required(pos, "entry_price")?,
+                required(pos, "multiplier")?,
+            );
+            position.current_price = required(pos, "current_price")?;
+            positions.push(position);
+        }
+
+        let calc = Self::from_parts(
+            required(data, "max_daily_loss")?,
+            required(data, "realized_pnl")?,
+            positions,
+        );
+        calc.check_state()?;
+        Ok(calc)
+    }
+
+    /// Build a calculator holding restored positions and realized P&L
+    ///
+    /// Drawdown tracking starts fresh from the restored equity.
+    fn from_parts(
+        max_daily_loss: f64,
+        realized_pnl: f64,
+        positions: impl IntoIterator<Item = Position>,
+    ) -> Self {
+        let mut calc = Self::new(max_daily_loss);
+        calc.realized_pnl = realized_pnl;
+        for position in positions {
+            calc.positions.insert(position.symbol.clone(), position);
+        }
+        calc.peak_equity = calc.total_pnl();
+        calc
+    }
+
+    /// Validate a deserialized calculator
+    ///
+    /// Applies the setter checks to the limits and settings, then checks
+    /// that each position's lots add up to its quantity and that every
+    /// price, P&L and statistic is a finite number.
+    fn check_state(&self) -> Result<(), Error> {
+        let invalid = |what: String| -> Result<(), Error> {
+            Err(Error::InvalidArgument(format!("Invalid state: {}", what)))
+        };
+        let is_limit = |limit: f64| !limit.is_nan() && limit >= 0.0;
+
+        if !is_limit(self.max_daily_loss) {
+            return invalid(format!("daily loss limit {}", self.max_daily_loss));
+        }
+        for (symbol, &limit) in &self.symbol_loss_limits {
+            if !is_limit(limit) {
+                return invalid(format!("{} loss limit {}", symbol, limit));
+            }
+        }
+        if let Some((symbol, _)) = self.profit_targets.iter().find(|(_, t)| t.is_nan()) {
+            return invalid(format!("{} profit target is NaN", symbol));
+        }
+        let commissions = self.symbol_commissions.values();
+        if !std::iter::once(&self.commission_per_contract)
+            .chain(commissions)
+            .all(|&c| c.is_finite() && c >= 0.0)
+        {
+            return invalid("commissions must be >= 0".to_string());
+        }
+        for &multiplier in self.instruments.values() {
+            validate_multiplier(multiplier)?;
+        }
+        for &rate in self.fx_rates.values() {
+            validate_fx_rate(rate)?;
+        }
+        for (symbol, currency) in &self.currencies {
+            if !self.fx_rates.contains_key(currency) {
+                return invalid(format!("{} has no FX rate for '{}'", symbol, currency));
+            }
+        }
+        if let Some(limit) = self.max_concentration {
+            validate_concentration(limit)?;
+        }
+        if let Some(frac) = self.warning_fraction {
+            validate_warning_fraction(frac)?;
+        }
+        if let Some((trigger, giveback)) = self.profit_lock {
+            validate_profit_lock(trigger, giveback)?;
+        }
+        if let Some(amount) = self.drawdown_limit {
+            validate_drawdown_limit(amount)?;
+        }
+        if let Some(log) = &self.equity_log {
+            validate_equity_log_len(self.equity_log_len)?;
+            if log.len() > self.equity_log_len {
+                return invalid("equity log longer than its length".to_string());
+            }
+        }
+
+        let totals = [
+            self.realized_pnl,
+            self.gross_wins,
+            self.gross_losses,
+            self.total_commissions,
+            self.turnover,
+            self.peak_equity,
+            self.peak_time,
+            self.max_drawdown,
+            self.clock.unwrap_or(0.0),
+        ];
+        if !totals.iter().chain(self.realized_by_symbol.values()).all(|x| x.is_finite()) {
+            return invalid("P&L and statistics must be finite".to_string());
+        }
+
+        for (symbol, pos) in &self.positions {
+            if *symbol != pos.symbol {
+                return invalid(format!("position {} filed under {}", pos.symbol, symbol));
+            }
+            validate_multiplier(pos.multiplier)?;
+            let levels = [pos.bid, pos.ask, pos.stop_level, pos.target_level];
+            let prices_finite = [pos.quantity, pos.entry_price, pos.current_price]
+                .iter()
+                .chain(levels.iter().flatten())
+                .all(|x| x.is_finite());
+            if !prices_finite {
+                return invalid(format!("{} quantity and prices must be finite", symbol));
+            }
+            let lots_valid = pos.lots.iter().all(|lot| {
+                lot.price.is_finite() && side(lot.quantity) == side(pos.quantity)
+            });
+            let lot_total: f64 = pos.lots.iter().map(|lot| lot.quantity).sum();
+            if !lots_valid || !is_flat(lot_total - pos.quantity) {
+                return invalid(format!("{} lots do not add up to its quantity", symbol));
+            }
+            if let Some(stop) = &pos.trailing_stop {
+                validate_trailing_distance(stop.distance)?;
+                if !stop.best.is_finite() {
+                    return invalid(format!("{} trailing stop price", symbol));
+                }
+            }
+        }
+
+        for (name, legs) in &self.spreads {
+            for leg in legs {
+                validate_multiplier(leg.multiplier)?;
+                if ![leg.ratio, leg.entry_price, leg.current_price].iter().all(|x| x.is_finite()) {
+                    return invalid(format!("{} leg {} must be finite", name, leg.symbol));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Install the breach hook (armed if not currently breached)
+    fn set_breach_hook(&mut self, hook: BreachHook) {
+        self.breach_hook = BreachHookSlot(Some(hook));
+        self.breach_armed = !self.is_daily_loss_breached();
+    }
+
+    /// Current time in seconds since epoch (pinned clock if set)
+    fn now(&self) -> f64 {
+        self.clock.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or(0.0)
+        })
+    }
+
+    /// Count a fill and add its notional to the turnover
+    fn record_fill(&mut self, symbol: &str, quantity: f64, price: f64, multiplier: f64) {
+        self.fill_count += 1;
+        self.turnover += (quantity * price * multiplier).abs() * self.fx_rate_for(symbol);
+    }
+
+    /// Deduct the commission for a fill from realized P&L
+    ///
+    /// Does not track equity: the fill path does that once the fill is
+    /// fully applied, so the commission-only state is never logged.
+    fn charge_commission(&mut self, symbol: &str, quantity: f64) {
+        let rate = self
+            .symbol_commissions
+            .get(symbol)
+            .copied()
+            .unwrap_or(self.commission_per_contract);
+        let commission = rate * quantity.abs();
+        if commission == 0.0 {
+            return;
+        }
+
+        self.total_commissions += commission;
+        self.realized_pnl -= commission;
+        *self.realized_by_symbol.entry(symbol.to_string()).or_insert(0.0) -= commission;
+    }
+
+    /// Rate converting a symbol's P&L to the base currency
+    ///
+    /// `set_symbol_currency` only accepts currencies with a rate, so a
+    /// tagged symbol always has one.
+    fn fx_rate_for(&self, symbol: &str) -> f64 {
+        self.currencies
+            .get(symbol)
+            .and_then(|currency| self.fx_rates.get(currency))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Signed notional of a position converted to the base currency
+    fn base_notional(&self, pos: &Position) -> f64 {
+        pos.notional() * self.fx_rate_for(&pos.symbol)
+    }
+
+    /// (symbol, unrealized P&L, notional, % of gross) rows for
+    /// `risk_breakdown`, largest absolute notional first
+    fn risk_breakdown_rows(&self) -> Vec<(String, f64, f64, f64)> {
+        let gross = self.gross_exposure();
+        let mut rows: Vec<_> = self
+            .positions
+            .values()
+            .map(|p| {
+                let notional = self.base_notional(p);
+                let pct = if gross > 0.0 { notional.abs() / gross * 100.0 } else { 0.0 };
+                let pnl = p.unrealized_pnl(self.mark_source) * self.fx_rate_for(&p.symbol);
+                (p.symbol.clone(), pnl, notional, pct)
+            })
+            .collect();
+        rows.sort_by(|a, b| b.2.abs().total_cmp(&a.2.abs()).then_with(|| a.0.cmp(&b.0)));
+        rows
+    }
+
+    /// Total P&L with `symbol` marked at each of `prices`
+    fn pnl_curve_values(&self, symbol: &str, prices: impl Iterator<Item = f64>) -> Vec<f64> {
+        // Everything but the moved position is fixed across the grid
+        let base = self.realized_pnl
+            + self
+                .positions
+                .values()
+                .filter(|p| p.symbol != symbol)
+                .map(|p| p.unrealized_pnl(self.mark_source) * self.fx_rate_for(&p.symbol))
+                .sum::<f64>();
+
+        match self.positions.get(symbol) {
+            Some(pos) => {
+                let scale = pos.quantity * pos.multiplier * self.fx_rate_for(symbol);
+                prices.map(|price| base + (price - pos.entry_price) * scale).collect()
+            }
+            None => prices.map(|_| base).collect(),
+        }
+    }
+
+    /// Apply a signed fill to an existing position and book realized P&L
+    fn apply_fill(&mut self, symbol: String, quantity: f64, fill_price: f64) -> f64 {
+        let (realized, flat) = match self.positions.get_mut(&symbol) {
+            Some(pos) => (pos.apply_fill(quantity, fill_price), is_flat(pos.quantity)),
+            None => return 0.0,
+        };
+        let realized = realized.map(|pnl| pnl * self.fx_rate_for(&symbol));
+
+        if flat {
+            self.positions.remove(&symbol);
+        }
+
+        match realized {
+            Some(pnl) => {
+                self.add_realized_pnl_for(symbol, pnl);
+                pnl
+            }
+            None => {
+                self.track_equity();
+                0.0
+            }
+        }
+    }
+
+    /// Realized + unrealized P&L for one symbol
+    fn symbol_pnl(&self, symbol: &str) -> f64 {
+        let realized = self.get_realized_pnl_by_symbol(symbol);
+        realized + self.unrealized_pnl_for(symbol).unwrap_or(0.0)
+    }
+
+    /// Book a closed trade's P&L and update win/loss statistics
+    fn record_trade(&mut self, pnl: f64) {
+        self.realized_pnl += pnl;
+        if pnl > 0.0 {
+            self.win_count += 1;
+            self.gross_wins += pnl;
+        } else if pnl < 0.0 {
+            self.loss_count += 1;
+            self.gross_losses -= pnl;
+        }
+    }
+
+    /// Update peak equity and max drawdown after a P&L change
+    fn track_equity(&mut self) {
+        let equity = self.total_pnl();
+        if equity >= self.peak_equity {
+            self.peak_equity = equity;
+            self.peak_time = self.now();
+        }
+        if self.equity_log.is_some() {
+            let now = self.now();
+            if let Some(log) = self.equity_log.as_mut() {
+                if log.len() == self.equity_log_len {
+                    log.pop_front();
+                }
+                log.push_back((now, equity));
+            }
+        }
+        self.max_drawdown = self.max_drawdown.max(self.peak_equity - equity);
+
+        if !self.is_daily_loss_breached() {
+            self.breach_armed = true;
+        } else if self.breach_armed {
+            self.breach_armed = false;
+            if self.breaker == CircuitBreaker::Active {
+                self.breaker = CircuitBreaker::Tripped;
+            }
+            if let Some(hook) = &self.breach_hook.0 {
+                hook(equity);
+            }
+        }
+    }
+
+    /// Move a tripped breaker into cooldown once positions are flat
+    fn cool_down(&mut self) {
+        if self.breaker == CircuitBreaker::Tripped {
+            self.breaker = CircuitBreaker::Cooldown;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_calculator() {
+        let calc = RiskCalculator::new(500.0);
+        assert_eq!(calc.position_count(), 0);
+        assert_eq!(calc.total_pnl(), 0.0);
+        assert!(!calc.is_daily_loss_breached());
+    }
+
+    #[test]
+    fn test_add_position() {
+        let mut calc = RiskCalculator::new(500.0);
+        
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        
+        assert_eq!(calc.position_count(), 1);
+        assert!(calc.has_position("MES"));
+        assert_eq!(calc.get_quantity("MES"), 1.0);
+    }
+
+    #[test]
+    fn test_registered_multiplier() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.register_instrument("MES".to_string(), 5.0).unwrap();
+
+        calc.py_update_position("MES".to_string(), 2.0, 5000.0, None).unwrap();
+        calc.update_price("MES", 5001.0);
+        assert_eq!(calc.unrealized_pnl(), 10.0);
+
+        // An explicit multiplier still wins
+        calc.py_update_position("MNQ".to_string(), 1.0, 17000.0, Some(2.0)).unwrap();
+        assert_eq!(calc.get_instrument_multiplier("MNQ"), None);
+
+        assert_eq!(
+            calc.py_update_position("M2K".to_string(), 1.0, 2000.0, None),
+            Err(Error::UnknownSymbol("M2K".to_string()))
+        );
+        assert!(!calc.has_position("M2K"));
+
+        calc.py_update_position("MNQ".to_string(), 0.0, 0.0, None).unwrap();
+        assert!(!calc.has_position("MNQ"));
+    }
+
+    #[test]
+    fn test_unrealized_pnl_long() {
+        let mut calc = RiskCalculator::new(500.0);
+        
+        // Long 1 MES @ 5000
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        
+        // Price moves to 5010 (+10 points * $5 = +$50)
+        calc.update_price("MES", 5010.0);
+        
+        assert!((calc.unrealized_pnl() - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_unrealized_pnl_short() {
+        let mut calc = RiskCalculator::new(500.0);
+        
+        // Short 1 MES @ 5000
+        calc.update_position("MES".to_string(), -1.0, 5000.0, 5.0);
+        
+        // Price moves to 4990 (-10 points * -1 * $5 = +$50)
+        calc.update_price("MES", 4990.0);
+        
+        assert!((calc.unrealized_pnl() - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_unrealized_pnl_for() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -2.0, 17000.0, 2.0);
+        calc.update_price("MES", 5004.0);
+        calc.update_price("MNQ", 17010.0);
+
+        assert_eq!(calc.unrealized_pnl_for("MES"), Some(20.0));
+        assert_eq!(calc.unrealized_pnl_for("MNQ"), Some(-40.0));
+        assert_eq!(calc.unrealized_pnl_for("M2K"), None);
+        assert_eq!(calc.unrealized_pnl(), -20.0);
+    }
+
+    #[test]
+    fn test_multi_currency_pnl() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert!(calc.set_symbol_currency("FDAX".to_string(), "EUR".to_string()).is_err());
+        assert!(calc.set_fx_rate("EUR".to_string(), 0.0).is_err());
+
+        calc.set_fx_rate("EUR".to_string(), 1.1).unwrap();
+        calc.set_symbol_currency("FDAX".to_string(), "EUR".to_string()).unwrap();
+        assert_eq!(calc.get_symbol_currency("FDAX"), Some("EUR".to_string()));
+        assert_eq!(calc.get_symbol_currency("MES"), None);
+
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_position("FDAX".to_string(), 1.0, 18000.0, 1.0);
+        calc.update_price("MES", 5010.0); // +50 USD
+        calc.update_price("FDAX", 18100.0); // +100 EUR
+        assert!((calc.unrealized_pnl() - (50.0 + 110.0)).abs() < 1e-9);
+
+        // Rate moves re-value open P&L
+        calc.set_fx_rate("EUR".to_string(), 1.2).unwrap();
+        assert!((calc.total_pnl() - (50.0 + 120.0)).abs() < 1e-9);
+
+        // Realized P&L is booked in base at the rate at close
+        let realized = calc.add_fill("FDAX".to_string(), -1.0, 18100.0, 1.0).unwrap();
+        assert!((realized - 120.0).abs() < 1e-9);
+        assert!((calc.get_realized_pnl_by_symbol("FDAX") - 120.0).abs() < 1e-9);
+        calc.set_fx_rate("EUR".to_string(), 1.0).unwrap();
+        assert!((calc.total_pnl() - (50.0 + 120.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_multi_currency_exposure_and_risk() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.set_fx_rate("JPY".to_string(), 0.01).unwrap();
+        calc.set_symbol_currency("NK".to_string(), "JPY".to_string()).unwrap();
+
+        // MES: 2 * 5000 * 5 = 50000 USD; NK: -1 * 40000 * 100 JPY = -40000 USD
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("NK".to_string(), -1.0, 40000.0, 100.0);
+        assert!((calc.gross_exposure() - 90000.0).abs() < 1e-9);
+        assert!((calc.net_exposure() - 10000.0).abs() < 1e-9);
+        assert!((calc.long_exposure() - 50000.0).abs() < 1e-9);
+        assert!((calc.short_exposure() - 40000.0).abs() < 1e-9);
+        assert!((calc.max_position_weight() - 50000.0 / 90000.0).abs() < 1e-12);
+
+        calc.set_max_concentration(0.6).unwrap();
+        // Doubling NK makes it 80000 of 130000 USD
+        assert!(calc.would_breach_concentration("NK", -1.0, 40000.0, 100.0));
+        // NK at 48000 of 98000 USD stays inside
+        assert!(!calc.would_breach_concentration("NK", -0.2, 40000.0, 100.0));
+
+        // 1% on NK moves 40000 JPY = 400 USD against the short
+        let shocks = HashMap::from([("NK".to_string(), 1.0)]);
+        assert!((calc.stress_test_vector(shocks) + 400.0).abs() < 1e-9);
+        let expected = calc.expected_pnl("NK", vec![(39900.0, 0.5), (40100.0, 0.5)]).unwrap();
+        assert!(expected.abs() < 1e-9);
+        let expected = calc.expected_pnl("NK", vec![(39900.0, 1.0)]).unwrap();
+        assert!((expected - 100.0).abs() < 1e-9);
+
+        // Sigma in USD: sqrt((50000 * 0.01)^2 + (40000 * 0.01)^2)
+        let vols = HashMap::from([("MES".to_string(), 0.01), ("NK".to_string(), 0.01)]);
+        let sigma = calc.portfolio_sigma(&vols, 0.99, 1.0).unwrap();
+        assert!((sigma - (500.0f64.powi(2) + 400.0f64.powi(2)).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_strict_update_price() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+
+        // Lenient by default
+        assert!(!calc.is_strict());
+        calc.py_update_price("MNQ", 17000.0).unwrap();
+
+        calc.set_strict(true);
+        assert_eq!(
+            calc.py_update_price("MNQ", 17000.0),
+            Err(Error::UnknownSymbol("MNQ".to_string()))
+        );
+        calc.py_update_price("MES", 5010.0).unwrap();
+        assert_eq!(calc.unrealized_pnl(), 50.0);
+    }
+
+    #[test]
+    fn test_disallow_flips() {
+        let mut calc = RiskCalculator::new(500.0);
+        assert!(calc.allows_flips());
+        calc.py_update_position("MES".to_string(), 2.0, 5000.0, Some(5.0)).unwrap();
+        calc.py_update_position("MES".to_string(), -1.0, 5000.0, Some(5.0)).unwrap();
+        assert_eq!(calc.get_quantity("MES"), -1.0);
+
+        calc.set_allow_flips(false);
+        assert!(matches!(
+            calc.py_update_position("MES".to_string(), 1.0, 5000.0, Some(5.0)),
+            Err(Error::LimitExceeded(_))
+        ));
+        assert!(matches!(
+            calc.add_fill("MES".to_string(), 3.0, 5000.0, 5.0),
+            Err(Error::LimitExceeded(_))
+        ));
+        assert!(matches!(
+            calc.close_quantity("MES".to_string(), 2.0, 5000.0),
+            Err(Error::LimitExceeded(_))
+        ));
+        assert_eq!(calc.get_quantity("MES"), -1.0);
+        assert_eq!(calc.fill_count(), 0);
+
+        // Adding, reducing, closing and opening fresh are all fine
+        calc.add_fill("MES".to_string(), -1.0, 5000.0, 5.0).unwrap();
+        calc.close_quantity("MES".to_string(), 1.0, 5000.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap();
+        assert_eq!(calc.get_quantity("MES"), 0.0);
+        calc.py_update_position("MES".to_string(), 1.0, 5000.0, Some(5.0)).unwrap();
+        calc.py_update_position("MES".to_string(), 0.0, 5000.0, None).unwrap();
+        calc.py_update_position("MES".to_string(), -1.0, 5000.0, Some(5.0)).unwrap();
+        assert_eq!(calc.get_quantity("MES"), -1.0);
+    }
+
+    #[test]
+    fn test_flatten_all() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_realized_pnl(25.0);
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+        calc.update_price("MES", 5010.0); // +100
+        calc.update_price("MNQ", 17020.0); // -40
+
+        let total = calc.total_pnl();
+        let drawdown = calc.max_drawdown();
+        assert!((calc.flatten_all() - 60.0).abs() < 1e-9);
+
+        assert_eq!(calc.position_count(), 0);
+        assert!((calc.total_pnl() - total).abs() < 1e-9);
+        assert_eq!(calc.max_drawdown(), drawdown);
+        assert!((calc.get_realized_pnl_by_symbol("MES") - 100.0).abs() < 1e-9);
+        assert!((calc.get_realized_pnl_by_symbol("MNQ") + 40.0).abs() < 1e-9);
+        assert_eq!((calc.win_count(), calc.loss_count()), (2, 1));
+
+        // Nothing left to flatten
+        assert_eq!(calc.flatten_all(), 0.0);
+    }
+
+    #[test]
+    fn test_invalid_multiplier_rejected() {
+        let mut calc = RiskCalculator::new(500.0);
+        for multiplier in [0.0, -5.0, f64::NAN] {
+            assert!(calc
+                .py_update_position("MES".to_string(), 1.0, 5000.0, Some(multiplier))
+                .is_err());
+            assert!(calc.add_fill("MES".to_string(), 1.0, 5000.0, multiplier).is_err());
+            assert!(calc.register_instrument("MES".to_string(), multiplier).is_err());
+        }
+        assert!(calc
+            .add_spread("CAL".to_string(), vec![("MESH5".to_string(), 1.0, 5000.0, 0.0)])
+            .is_err());
+        assert_eq!(calc.position_count(), 0);
+        assert!(!calc.has_spread("CAL"));
+
+        // Removing a position does not need a real multiplier
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.py_update_position("MES".to_string(), 0.0, 0.0, Some(0.0)).unwrap();
+        assert!(!calc.has_position("MES"));
+    }
+
+    #[test]
+    fn test_equity_log() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.set_clock(Some(100.0));
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert!(calc.get_equity_curve().is_empty());
+        assert!(calc.enable_equity_log(0).is_err());
+
+        calc.enable_equity_log(3).unwrap();
+        for (t, price) in [(101.0, 5002.0), (102.0, 5004.0), (103.0, 4998.0), (104.0, 5010.0)] {
+            calc.set_clock(Some(t));
+            calc.update_price("MES", price);
+        }
+
+        // Ring buffer keeps the latest 3 points
+        assert_eq!(
+            calc.get_equity_curve(),
+            vec![(102.0, 20.0), (103.0, -10.0), (104.0, 50.0)]
+        );
+
+        calc.disable_equity_log();
+        calc.update_price("MES", 5000.0);
+        assert!(calc.get_equity_curve().is_empty());
+    }
+
+    #[test]
+    fn test_equity_log_one_point_per_fill() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.set_clock(Some(100.0));
+        calc.set_commission(2.0);
+        calc.enable_equity_log(10).unwrap();
+
+        // Open, add, close partly and close out: one point each, net of
+        // commission, with no commission-only intermediate state
+        calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap();
+        calc.close_quantity("MES".to_string(), 1.0, 5010.0).unwrap();
+        calc.add_fill("MES".to_string(), -1.0, 5010.0, 5.0).unwrap();
+        let equity: Vec<f64> = calc.get_equity_curve().iter().map(|&(_, pnl)| pnl).collect();
+        assert_eq!(equity, vec![-2.0, -4.0, 44.0, 92.0]);
+        assert_eq!(calc.peak_equity(), 92.0);
+    }
+
+    #[test]
+    fn test_update_prices_batch() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+
+        calc.update_prices(HashMap::from([
+            ("MES".to_string(), 5010.0),
+            ("MNQ".to_string(), 16990.0),
+            ("M2K".to_string(), 2000.0),
+        ]));
+
+        // 10 * 5 + 10 * 2
+        assert_eq!(calc.unrealized_pnl(), 70.0);
+        assert!(!calc.has_position("M2K"));
+        assert_eq!(calc.peak_equity(), 70.0);
+    }
+
+    #[test]
+    fn test_daily_loss_limit() {
+        let mut calc = RiskCalculator::new(500.0);
+        
+        // Add losing trade
+        calc.add_realized_pnl(-300.0);
+        assert!(!calc.is_daily_loss_breached());
+        assert_eq!(calc.remaining_risk(), 200.0);
+        
+        // Add another losing trade
+        calc.add_realized_pnl(-250.0);
+        assert!(calc.is_daily_loss_breached());
+    }
+
+    #[test]
+    fn test_risk_state() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_realized_pnl(-450.0);
+
+        // No warning level configured
+        assert_eq!(calc.risk_state(), RiskState::Ok);
+
+        calc.set_warning_fraction(0.8).unwrap();
+        assert_eq!(calc.risk_state(), RiskState::Warning);
+
+        calc.add_realized_pnl(100.0);
+        assert_eq!(calc.risk_state(), RiskState::Ok);
+
+        // Exactly at the warning level is not yet a warning
+        calc.add_realized_pnl(-50.0);
+        assert_eq!(calc.risk_state(), RiskState::Ok);
+
+        calc.add_realized_pnl(-100.0);
+        assert_eq!(calc.risk_state(), RiskState::Breached);
+
+        assert!(calc.set_warning_fraction(1.0).is_err());
+        assert!(calc.set_warning_fraction(0.0).is_err());
+        assert_eq!(calc.get_warning_fraction(), Some(0.8));
+    }
+
+    #[test]
+    fn test_profit_lock() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.set_profit_lock(1000.0, 300.0).unwrap();
+        calc.update_position("MES".to_string(), 10.0, 5000.0, 5.0);
+        assert_eq!(calc.effective_loss_limit(), -500.0);
+
+        // +900: not yet triggered
+        calc.update_price("MES", 5018.0);
+        assert_eq!(calc.effective_loss_limit(), -500.0);
+
+        // +1250 engages the lock at the peak
+        calc.update_price("MES", 5025.0);
+        assert_eq!(calc.effective_loss_limit(), 950.0);
+        assert!((calc.remaining_risk() - 300.0).abs() < 1e-9);
+
+        // Back to +1000: still inside the giveback
+        calc.update_price("MES", 5020.0);
+        assert!(!calc.is_daily_loss_breached());
+
+        // +900 gives back 350 from the peak
+        calc.update_price("MES", 5018.0);
+        assert!(calc.is_daily_loss_breached());
+        assert_eq!(calc.risk_state(), RiskState::Breached);
+
+        calc.clear_profit_lock();
+        assert!(!calc.is_daily_loss_breached());
+        assert_eq!(calc.effective_loss_limit(), -500.0);
+
+        assert!(calc.set_profit_lock(1000.0, -1.0).is_err());
+        assert!(calc.set_profit_lock(f64::NAN, 100.0).is_err());
+    }
+
+    #[test]
+    fn test_breach_hook_fires_once_per_crossing() {
+        use std::sync::Mutex;
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut calc = RiskCalculator::new(500.0);
+        let sink = Arc::clone(&fired);
+        calc.set_breach_hook(Arc::new(move |pnl| sink.lock().unwrap().push(pnl)));
+
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_price("MES", 4950.0);
+        assert!(fired.lock().unwrap().is_empty());
+
+        // Tightening the limit below the current loss breaches at once
+        calc.set_max_daily_loss(200.0);
+        assert_eq!(*fired.lock().unwrap(), vec![-250.0]);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Tripped);
+        calc.set_max_daily_loss(500.0);
+        calc.arm();
+        fired.lock().unwrap().clear();
+
+        // Crosses the limit, then gets worse: one call
+        calc.update_price("MES", 4899.0);
+        calc.update_price("MES", 4850.0);
+        assert_eq!(*fired.lock().unwrap(), vec![-505.0]);
+
+        // Recovers above the limit and breaches again
+        calc.update_price("MES", 4950.0);
+        calc.add_realized_pnl(-300.0);
+        assert_eq!(*fired.lock().unwrap(), vec![-505.0, -550.0]);
+    }
+
+    #[test]
+    fn test_clone_drops_breach_hook() {
+        use std::sync::Mutex;
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut calc = RiskCalculator::new(500.0);
+        let sink = Arc::clone(&fired);
+        calc.set_breach_hook(Arc::new(move |pnl| sink.lock().unwrap().push(pnl)));
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+
+        // A what-if fork that breaches leaves the original's callback alone
+        let mut fork = calc.clone();
+        fork.update_price("MES", 4800.0);
+        assert!(fork.is_daily_loss_breached());
+        assert!(fired.lock().unwrap().is_empty());
+
+        calc.update_price("MES", 4800.0);
+        assert_eq!(*fired.lock().unwrap(), vec![-1000.0]);
+    }
+
+    #[test]
+    fn test_symbol_loss_limit() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.set_symbol_loss_limit("MES".to_string(), -100.0);
+        assert_eq!(calc.get_symbol_loss_limit("MES"), Some(100.0));
+        assert_eq!(calc.get_symbol_loss_limit("MNQ"), None);
+
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), 1.0, 18000.0, 2.0);
+
+        // MES: -60 realized, -25 unrealized = -85
+        calc.add_realized_pnl_for("MES".to_string(), -60.0);
+        calc.update_price("MES", 4995.0);
+        assert!(!calc.is_symbol_loss_breached("MES"));
+
+        // MES: -60 realized, -50 unrealized = -110
+        calc.update_price("MES", 4990.0);
+        assert!(calc.is_symbol_loss_breached("MES"));
+
+        // Book-level limit is fine, and MNQ (-200) has no limit at all
+        calc.update_price("MNQ", 17900.0);
+        assert!(!calc.is_daily_loss_breached());
+        assert!(!calc.is_symbol_loss_breached("MNQ"));
+
+        // Symbol-tagged realized P&L still counts toward the aggregate
+        assert_eq!(calc.get_realized_pnl(), -60.0);
+    }
+
+    #[test]
+    fn test_add_fill_scaling_in() {
+        let mut calc = RiskCalculator::new(500.0);
+
+        // Flat -> long 1 @ 5000
+        assert_eq!(calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap(), 0.0);
+        assert_eq!(calc.get_quantity("MES"), 1.0);
+
+        // Long -> larger: buy 3 more @ 5010, basis = (5000 + 3*5010) / 4
+        assert_eq!(calc.add_fill("MES".to_string(), 3.0, 5010.0, 5.0).unwrap(), 0.0);
+        assert_eq!(calc.get_quantity("MES"), 4.0);
+
+        // At 5010 the blended position is up 4 * (5010 - 5007.5) * 5 = 50
+        calc.update_price("MES", 5010.0);
+        assert!((calc.unrealized_pnl() - 50.0).abs() < 0.01);
+        assert_eq!(calc.get_realized_pnl(), 0.0);
+    }
+
+    #[test]
+    fn test_add_fill_rejects_multiplier_change() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap();
+        calc.update_price("MES", 5010.0);
+
+        assert!(matches!(
+            calc.add_fill("MES".to_string(), 1.0, 5010.0, 50.0),
+            Err(Error::InvalidArgument(_))
+        ));
+        // Nothing changed: P&L is still at the original multiplier
+        assert_eq!(calc.get_quantity("MES"), 1.0);
+        assert_eq!(calc.unrealized_pnl(), 50.0);
+        assert_eq!(calc.fill_count(), 1);
+
+        // Once flat, the symbol can reopen with any multiplier
+        calc.add_fill("MES".to_string(), -1.0, 5010.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5010.0, 50.0).unwrap();
+        assert_eq!(calc.get_quantity("MES"), 1.0);
+    }
+
+    #[test]
+    fn test_add_fill_reduce_close_and_flip() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_fill("MES".to_string(), 4.0, 5000.0, 5.0).unwrap();
+
+        // Long -> smaller: sell 1 @ 5020 realizes 20 * 5 = 100
+        let realized = calc.add_fill("MES".to_string(), -1.0, 5020.0, 5.0).unwrap();
+        assert!((realized - 100.0).abs() < 0.01);
+        assert_eq!(calc.get_quantity("MES"), 3.0);
+
+        // Remainder keeps its 5000 basis
+        calc.update_price("MES", 5020.0);
+        assert!((calc.unrealized_pnl() - 300.0).abs() < 0.01);
+
+        // Long -> short: sell 5 @ 4990 closes 3 (-150) and opens short 2 @ 4990
+        let realized = calc.add_fill("MES".to_string(), -5.0, 4990.0, 5.0).unwrap();
+        assert!((realized + 150.0).abs() < 0.01);
+        assert_eq!(calc.get_quantity("MES"), -2.0);
+        assert!((calc.get_realized_pnl() + 50.0).abs() < 0.01);
+
+        calc.update_price("MES", 4980.0);
+        assert!((calc.unrealized_pnl() - 100.0).abs() < 0.01);
+
+        // Short -> flat: buy 2 @ 4980 realizes +100
+        let realized = calc.add_fill("MES".to_string(), 2.0, 4980.0, 5.0).unwrap();
+        assert!((realized - 100.0).abs() < 0.01);
+        assert!(!calc.has_position("MES"));
+        assert!((calc.get_realized_pnl() - 50.0).abs() < 0.01);
+        assert!((calc.total_pnl() - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_fifo_partial_close() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 2.0, 5010.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5020.0, 5.0).unwrap();
+        assert_eq!(
+            calc.get_lots("MES"),
+            vec![(2.0, 5000.0), (2.0, 5010.0), (1.0, 5020.0)]
+        );
+
+        // Close 3 @ 5030: 2 from the 5000 lot, 1 from the 5010 lot
+        let realized = calc.close_quantity("MES".to_string(), 3.0, 5030.0).unwrap();
+        assert!((realized - (2.0 * 30.0 + 20.0) * 5.0).abs() < 0.01);
+        assert_eq!(calc.get_lots("MES"), vec![(1.0, 5010.0), (1.0, 5020.0)]);
+        assert_eq!(calc.get_quantity("MES"), 2.0);
+        assert!((calc.get_realized_pnl() - 400.0).abs() < 0.01);
+
+        // Remaining basis follows the lots left
+        calc.update_price("MES", 5030.0);
+        assert!((calc.unrealized_pnl() - (20.0 + 10.0) * 5.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_fifo_close_more_than_open_flips() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_fill("MES".to_string(), -2.0, 5000.0, 5.0).unwrap();
+
+        // Buy back 3 @ 4990: +100 realized, then long 1 @ 4990
+        let realized = calc.close_quantity("MES".to_string(), 3.0, 4990.0).unwrap();
+        assert!((realized - 100.0).abs() < 0.01);
+        assert_eq!(calc.get_quantity("MES"), 1.0);
+        assert_eq!(calc.get_lots("MES"), vec![(1.0, 4990.0)]);
+
+        // Exact close removes the position
+        calc.close_quantity("MES".to_string(), 1.0, 4995.0).unwrap();
+        assert!(!calc.has_position("MES"));
+        assert!(calc.get_lots("MES").is_empty());
+        assert!((calc.get_realized_pnl() - 125.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_fractional_quantities() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_fill("BTC".to_string(), 0.25, 60000.0, 1.0).unwrap();
+        calc.add_fill("BTC".to_string(), 0.1, 61000.0, 1.0).unwrap();
+        assert!((calc.get_quantity("BTC") - 0.35).abs() < 1e-12);
+
+        calc.update_price("BTC", 62000.0);
+        assert!((calc.unrealized_pnl() - (0.25 * 2000.0 + 0.1 * 1000.0)).abs() < 1e-6);
+
+        // Closes that leave only rounding dust remove the position
+        calc.close_quantity("BTC".to_string(), 0.15, 62000.0).unwrap();
+        calc.close_quantity("BTC".to_string(), 0.2, 62000.0).unwrap();
+        assert!(!calc.has_position("BTC"));
+        assert!((calc.get_realized_pnl() - 600.0).abs() < 1e-6);
+
+        // Setting an epsilon-sized quantity counts as flat too
+        calc.update_position("ETH".to_string(), 1.5, 3000.0, 1.0);
+        calc.update_position("ETH".to_string(), 1e-12, 3000.0, 1.0);
+        assert!(!calc.has_position("ETH"));
+    }
+
+    #[test]
+    fn test_close_quantity_errors() {
+        let mut calc = RiskCalculator::new(500.0);
+        assert_eq!(
+            calc.close_quantity("MES".to_string(), 1.0, 5000.0),
+            Err(Error::UnknownSymbol("MES".to_string()))
+        );
+
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert!(calc.close_quantity("MES".to_string(), 0.0, 5000.0).is_err());
+        assert!(calc.close_quantity("MES".to_string(), -1.0, 5000.0).is_err());
+        assert_eq!(calc.get_lots("MES"), vec![(1.0, 5000.0)]);
+    }
+
+    #[test]
+    fn test_profit_target_includes_realized() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.set_profit_target("MES".to_string(), 150.0);
+        calc.set_profit_target("MNQ".to_string(), 100.0);
+        assert!(calc.at_profit_target().is_empty());
+
+        // Book +100 on MES, keep 1 open
+        calc.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), -1.0, 5020.0, 5.0).unwrap();
+        calc.update_price("MES", 5009.0);
+
+        // +100 realized, +45 unrealized: not there yet
+        assert!(calc.at_profit_target().is_empty());
+
+        // +100 realized, +50 unrealized: target hit, though unrealized alone isn't
+        calc.update_price("MES", 5010.0);
+        assert_eq!(calc.at_profit_target(), vec!["MES".to_string()]);
+
+        calc.update_price("MES", 5005.0);
+        assert!(calc.at_profit_target().is_empty());
+    }
+
+    #[test]
+    fn test_commissions() {
+        let mut calc = RiskCalculator::new(100.0);
+        calc.set_commission(1.25);
+        calc.set_symbol_commission("MNQ".to_string(), 0.5);
+
+        // Open 4 MES and close them flat: only commissions hit P&L
+        calc.add_fill("MES".to_string(), 4.0, 5000.0, 5.0).unwrap();
+        let realized = calc.close_quantity("MES".to_string(), 4.0, 5000.0).unwrap();
+        assert_eq!(realized, 0.0);
+        assert!((calc.total_commissions() - 10.0).abs() < 1e-9);
+        assert!((calc.get_realized_pnl() + 10.0).abs() < 1e-9);
+
+        // Per-symbol override
+        calc.add_fill("MNQ".to_string(), -2.0, 18000.0, 2.0).unwrap();
+        assert!((calc.total_commissions() - 11.0).abs() < 1e-9);
+
+        // Breach check uses net P&L: -89 trading loss + -11 commissions
+        calc.add_realized_pnl(-89.0);
+        assert!(calc.is_daily_loss_breached());
+
+        calc.reset_daily(false);
+        assert_eq!(calc.total_commissions(), 0.0);
+    }
+
+    #[test]
+    fn test_exposure() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert_eq!(calc.gross_exposure(), 0.0);
+
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+        calc.update_price("MES", 5010.0);
+
+        // MES: 2 * 5010 * 5 = 50100; MNQ: -1 * 17000 * 2 = -34000
+        assert!((calc.long_exposure() - 50100.0).abs() < 1e-9);
+        assert!((calc.short_exposure() - 34000.0).abs() < 1e-9);
+        assert!((calc.gross_exposure() - 84100.0).abs() < 1e-9);
+        assert!((calc.net_exposure() - 16100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_net_delta() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert_eq!(calc.net_delta(HashMap::new()), Ok(0.0));
+
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+
+        // MES: 1.0 * 50000; MNQ: 1.25 * -34000
+        let betas = HashMap::from([("MES".to_string(), 1.0), ("MNQ".to_string(), 1.25)]);
+        assert!((calc.net_delta(betas).unwrap() - 7500.0).abs() < 1e-9);
+
+        // Foreign-currency positions count at their base-currency notional
+        calc.set_fx_rate("JPY".to_string(), 0.01).unwrap();
+        calc.set_symbol_currency("NK".to_string(), "JPY".to_string()).unwrap();
+        calc.update_position("NK".to_string(), 1.0, 40000.0, 100.0); // 40000 USD
+        let betas = HashMap::from([
+            ("MES".to_string(), 1.0),
+            ("MNQ".to_string(), 1.25),
+            ("NK".to_string(), 0.5),
+        ]);
+        assert!((calc.net_delta(betas).unwrap() - 27500.0).abs() < 1e-9);
+
+        // A held symbol without a beta is an error
+        let partial = HashMap::from([("MES".to_string(), 1.0), ("NK".to_string(), 0.5)]);
+        assert_eq!(
+            calc.net_delta(partial),
+            Err(Error::UnknownSymbol("MNQ".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_positions_by_unwind_priority() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert_eq!(calc.positions_by_unwind_priority("pnl"), Ok(vec![]));
+
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+        calc.update_position("M2K".to_string(), 1.0, 2000.0, 5.0);
+        calc.update_price("MES", 4990.0); // -100
+        calc.update_price("MNQ", 17010.0); // -20
+        calc.update_price("M2K", 2004.0); // +20
+
+        assert_eq!(
+            calc.positions_by_unwind_priority("pnl").unwrap(),
+            vec!["MES", "MNQ", "M2K"]
+        );
+        // Notional: MNQ 34020, MES 49900, M2K 10020
+        assert_eq!(
+            calc.positions_by_unwind_priority("notional").unwrap(),
+            vec!["MES", "MNQ", "M2K"]
+        );
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert_eq!(
+            calc.positions_by_unwind_priority("notional").unwrap(),
+            vec!["MNQ", "MES", "M2K"]
+        );
+
+        // A JPY position ranks by its base-currency notional (20000)
+        calc.set_fx_rate("JPY".to_string(), 0.01).unwrap();
+        calc.set_symbol_currency("NK".to_string(), "JPY".to_string()).unwrap();
+        calc.update_position("NK".to_string(), 1.0, 20000.0, 100.0);
+        assert_eq!(
+            calc.positions_by_unwind_priority("notional").unwrap(),
+            vec!["MNQ", "MES", "NK", "M2K"]
+        );
+
+        assert!(calc.positions_by_unwind_priority("size").is_err());
+    }
+
+    #[test]
+    fn test_concentration() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert_eq!(calc.max_position_weight(), 0.0);
+
+        // Small book: 30000 long + 20000 short notional
+        calc.update_position("A".to_string(), 3.0, 100.0, 100.0);
+        calc.update_position("B".to_string(), -2.0, 100.0, 100.0);
+        assert!((calc.max_position_weight() - 0.6).abs() < 1e-12);
+
+        // No limit set: never flagged
+        assert!(!calc.would_breach_concentration("C", 100.0, 100.0, 100.0));
+
+        calc.update_position("C".to_string(), 1.0, 100.0, 100.0);
+        calc.set_max_concentration(0.5).unwrap();
+        assert!(!calc.would_breach_concentration("C", 1.0, 100.0, 100.0));
+
+        // Large addition would be 101 of 106 contracts
+        assert!(calc.would_breach_concentration("C", 100.0, 100.0, 100.0));
+
+        // Growing the short leg: |-2 - 2| = 4 of 8 is at, not above, the cap
+        assert!(!calc.would_breach_concentration("B", -2.0, 100.0, 100.0));
+        assert!(calc.would_breach_concentration("B", -3.0, 100.0, 100.0));
+
+        assert!(calc.set_max_concentration(0.0).is_err());
+        assert!(calc.set_max_concentration(1.5).is_err());
+        assert_eq!(calc.get_max_concentration(), Some(0.5));
     }
 
     #[test]
-    fn test_unrealized_pnl_long() {
+    fn test_max_positions() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert!(!calc.is_position_limit_reached());
+
+        calc.set_max_positions(Some(2));
+        calc.py_update_position("MES".to_string(), 1.0, 5000.0, Some(5.0)).unwrap();
+        calc.add_fill("MNQ".to_string(), -1.0, 17000.0, 2.0).unwrap();
+        assert!(calc.is_position_limit_reached());
+
+        // New symbols are refused; held positions can still change
+        assert!(matches!(
+            calc.py_update_position("M2K".to_string(), 1.0, 2000.0, Some(5.0)),
+            Err(Error::LimitExceeded(_))
+        ));
+        assert!(calc.add_fill("M2K".to_string(), 1.0, 2000.0, 5.0).is_err());
+        assert!(!calc.has_position("M2K"));
+        calc.py_update_position("MES".to_string(), 3.0, 5000.0, Some(5.0)).unwrap();
+        calc.add_fill("MNQ".to_string(), -1.0, 17010.0, 2.0).unwrap();
+
+        // Closing frees a slot
+        calc.py_update_position("MES".to_string(), 0.0, 0.0, None).unwrap();
+        assert!(!calc.is_position_limit_reached());
+        calc.add_fill("M2K".to_string(), 1.0, 2000.0, 5.0).unwrap();
+
+        calc.set_max_positions(None);
+        assert!(!calc.is_position_limit_reached());
+        assert_eq!(calc.get_max_positions(), None);
+    }
+
+    #[test]
+    fn test_size_for_risk() {
+        let calc = RiskCalculator::new(1000.0);
+
+        // Long: 4-point stop on MES ($20 per contract), $100 budget
+        assert_eq!(calc.size_for_risk(5000.0, 4996.0, 5.0, 100.0), 5);
+        // Rounds down
+        assert_eq!(calc.size_for_risk(5000.0, 4996.0, 5.0, 119.0), 5);
+        // Short: stop above entry
+        assert_eq!(calc.size_for_risk(5000.0, 5004.0, 5.0, 100.0), -5);
+
+        // Inexact decimal stop distance still fits exactly
+        assert_eq!(calc.size_for_risk(5000.3, 5000.1, 5.0, 1.0), 1);
+
+        // Budget smaller than one contract's risk
+        assert_eq!(calc.size_for_risk(5000.0, 4990.0, 5.0, 49.0), 0);
+
+        assert_eq!(calc.size_for_risk(5000.0, 5000.0, 5.0, 100.0), 0);
+        assert_eq!(calc.size_for_risk(5000.0, 4996.0, 5.0, 0.0), 0);
+    }
+
+    #[test]
+    fn test_restore_from_parts() {
+        let mut calc = RiskCalculator::new(750.0);
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+        calc.update_price("MES", 5012.25);
+        calc.update_price("MNQ", 16990.5);
+        calc.add_realized_pnl(-123.45);
+
+        let restored = RiskCalculator::from_parts(
+            calc.get_max_daily_loss(),
+            calc.get_realized_pnl(),
+            calc.positions.values().cloned(),
+        );
+
+        assert_eq!(restored.get_max_daily_loss(), 750.0);
+        assert_eq!(restored.position_count(), 2);
+        assert_eq!(restored.get_quantity("MNQ"), -1.0);
+        assert_eq!(restored.unrealized_pnl(), calc.unrealized_pnl());
+        assert_eq!(restored.total_pnl(), calc.total_pnl());
+        assert_eq!(restored.current_drawdown(), 0.0);
+    }
+
+    #[test]
+    fn test_trade_statistics() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert_eq!(calc.win_rate(), 0.0);
+        assert_eq!(calc.profit_factor(), None);
+
+        calc.add_realized_pnl(100.0);
+        calc.add_realized_pnl(50.0);
+        calc.add_realized_pnl(-30.0);
+        calc.add_realized_pnl(0.0);
+        assert_eq!(calc.profit_factor(), Some(5.0));
+
+        calc.add_realized_pnl(-90.0);
+        assert_eq!(calc.win_count(), 2);
+        assert_eq!(calc.loss_count(), 2);
+        assert_eq!(calc.win_rate(), 0.5);
+        assert_eq!(calc.avg_win(), 75.0);
+        assert_eq!(calc.avg_loss(), 60.0);
+        assert_eq!(calc.profit_factor(), Some(1.25));
+
+        // Fill-driven closes count too; commissions do not
+        calc.set_commission(1.0);
+        calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), -1.0, 5002.0, 5.0).unwrap();
+        assert_eq!(calc.win_count(), 3);
+        assert_eq!(calc.loss_count(), 2);
+
+        calc.reset_daily(false);
+        assert_eq!(calc.win_count(), 0);
+        assert_eq!(calc.loss_count(), 0);
+        assert_eq!(calc.avg_win(), 0.0);
+        assert_eq!(calc.profit_factor(), None);
+    }
+
+    #[test]
+    fn test_realized_pnl_by_symbol() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.add_realized_pnl_for("MES".to_string(), 120.0);
+        calc.add_realized_pnl_for("MNQ".to_string(), -40.0);
+        calc.add_realized_pnl_for("MES".to_string(), -20.0);
+        calc.add_realized_pnl(15.0);
+
+        assert_eq!(calc.get_realized_pnl_by_symbol("MES"), 100.0);
+        assert_eq!(calc.get_realized_pnl_by_symbol("MNQ"), -40.0);
+        assert_eq!(calc.get_realized_pnl_by_symbol("M2K"), 0.0);
+        assert_eq!(calc.get_realized_pnl(), 75.0);
+
+        let all = calc.get_all_realized_pnl();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all["MES"], 100.0);
+
+        calc.reset_daily(false);
+        assert!(calc.get_all_realized_pnl().is_empty());
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+
+        let matching = HashMap::from([("MES".to_string(), 2.0), ("MNQ".to_string(), -1.0)]);
+        assert!(calc.reconcile(matching).is_empty());
+
+        // Missed fill on MES; broker flat M2K is not a discrepancy
+        let external = HashMap::from([
+            ("MES".to_string(), 3.0),
+            ("MNQ".to_string(), -1.0),
+            ("M2K".to_string(), 0.0),
+        ]);
+        let diff = calc.reconcile(external);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff["MES"], (2.0, 3.0));
+
+        // Symbols missing on either side count as flat
+        let external = HashMap::from([("MES".to_string(), 2.0), ("MYM".to_string(), 1.0)]);
+        let diff = calc.reconcile(external);
+        assert_eq!(diff.len(), 2);
+        assert_eq!(diff["MNQ"], (-1.0, 0.0));
+        assert_eq!(diff["MYM"], (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_get_positions_snapshots() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), -2.0, 5000.0, 5.0);
+        calc.update_quote("MES", Some(4990.0), Some(4991.0));
+        calc.set_mark_source("conservative").unwrap();
+
+        let positions = calc.get_positions();
+        assert_eq!(positions.len(), 1);
+        let pos = &positions[0];
+        assert_eq!(pos.py_symbol(), "MES");
+        assert_eq!(pos.py_quantity(), -2.0);
+        assert_eq!(pos.py_entry_price(), 5000.0);
+        assert_eq!(pos.py_multiplier(), 5.0);
+
+        // Marked at the ask for a short, matching the calculator
+        assert_eq!(pos.py_unrealized_pnl(), 90.0);
+        assert_eq!(pos.py_unrealized_pnl(), calc.unrealized_pnl());
+
+        // Snapshots do not track later updates
+        calc.update_price("MES", 4980.0);
+        assert_eq!(pos.py_current_price(), 5000.0);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut calc = RiskCalculator::new(750.0);
+        calc.set_clock(Some(1_000.0));
+        calc.set_commission(1.25);
+        calc.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5010.0, 5.0).unwrap();
+        calc.add_fill("MNQ".to_string(), -1.0, 17000.0, 2.0).unwrap();
+        calc.close_quantity("MES".to_string(), 1.0, 5020.0).unwrap();
+        calc.set_trailing_stop("MNQ", 25.0).unwrap();
+        calc.set_max_positions(Some(3));
+        calc.update_price("MNQ", 16990.0);
+
+        let restored = RiskCalculator::from_json(&calc.to_json().unwrap()).unwrap();
+        assert_eq!(restored.total_pnl(), calc.total_pnl());
+        assert_eq!(restored.get_realized_pnl(), calc.get_realized_pnl());
+        assert_eq!(restored.get_lots("MES"), calc.get_lots("MES"));
+        assert_eq!(restored.get_trailing_stop("MNQ"), calc.get_trailing_stop("MNQ"));
+        assert_eq!(restored.get_max_positions(), Some(3));
+        assert_eq!(restored.win_count(), calc.win_count());
+        assert_eq!(restored.peak_equity(), calc.peak_equity());
+
+        assert!(RiskCalculator::from_json("{\"positions\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip_keeps_marks() {
+        let mut calc = RiskCalculator::new(750.0);
+        calc.set_clock(Some(1_000.0));
+        calc.set_commission(1.25);
+        calc.set_fx_rate("JPY".to_string(), 0.0067).unwrap();
+        calc.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5010.0, 5.0).unwrap();
+        calc.add_fill("NK".to_string(), -1.0, 38000.0, 100.0).unwrap();
+        calc.set_symbol_currency("NK".to_string(), "JPY".to_string()).unwrap();
+        calc.close_quantity("MES".to_string(), 1.0, 5020.0).unwrap();
+        calc.update_quote("MES", Some(5011.0), Some(5012.0));
+        calc.update_quote("NK", Some(37950.0), Some(37960.0));
+
+        for mode in ["mid", "conservative"] {
+            calc.set_mark_source(mode).unwrap();
+            let restored = RiskCalculator::from_json(&calc.to_json().unwrap()).unwrap();
+            assert_eq!(restored.get_mark_source(), mode);
+            assert_eq!(restored.unrealized_pnl(), calc.unrealized_pnl());
+            assert_eq!(restored.total_pnl(), calc.total_pnl());
+            assert_eq!(restored.get_lots("MES"), calc.get_lots("MES"));
+            assert_eq!(
+                restored.get_realized_pnl_by_symbol("MES"),
+                calc.get_realized_pnl_by_symbol("MES")
+            );
+            assert_eq!(restored.total_commissions(), calc.total_commissions());
+            assert_eq!(restored.get_symbol_currency("NK"), Some("JPY".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip_infinite_limits() {
+        let mut calc = RiskCalculator::new(f64::INFINITY);
+        calc.set_symbol_loss_limit("MES".to_string(), f64::INFINITY);
+        calc.set_profit_target("MES".to_string(), f64::NEG_INFINITY);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+
+        let restored = RiskCalculator::from_json(&calc.to_json().unwrap()).unwrap();
+        assert_eq!(restored.get_max_daily_loss(), f64::INFINITY);
+        assert_eq!(restored.get_symbol_loss_limit("MES"), Some(f64::INFINITY));
+        assert_eq!(restored.at_profit_target(), vec!["MES".to_string()]);
+
+        // A non-finite P&L cannot be saved
+        calc.add_realized_pnl(f64::NAN);
+        assert!(matches!(calc.to_json(), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_from_json_rejects_invalid_state() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5010.0, 5.0).unwrap();
+        let state: serde_json::Value = serde_json::from_str(&calc.to_json().unwrap()).unwrap();
+
+        let mutations: [(&str, serde_json::Value); 4] = [
+            ("/positions/MES/multiplier", 0.0.into()),
+            ("/positions/MES/quantity", 5.0.into()),
+            ("/max_daily_loss", (-100.0).into()),
+            ("/drawdown_limit", (-1.0).into()),
+        ];
+        for (pointer, value) in mutations {
+            let mut bad = state.clone();
+            *bad.pointer_mut(pointer).unwrap() = value;
+            let result = RiskCalculator::from_json(&bad.to_string());
+            assert!(matches!(result, Err(Error::InvalidArgument(_))), "{}", pointer);
+        }
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.add_realized_pnl(50.0);
+
+        let mut fork = calc.py_clone();
+        fork.update_price("MES", 4990.0);
+        fork.add_fill("MES".to_string(), 1.0, 4990.0, 5.0).unwrap();
+        fork.add_realized_pnl(-20.0);
+
+        assert_eq!(calc.get_quantity("MES"), 1.0);
+        assert_eq!(calc.unrealized_pnl(), 0.0);
+        assert_eq!(calc.get_realized_pnl(), 50.0);
+        assert_eq!(calc.get_lots("MES").len(), 1);
+
+        assert_eq!(fork.get_quantity("MES"), 2.0);
+        assert_eq!(fork.get_realized_pnl(), 30.0);
+    }
+
+    #[test]
+    fn test_remove_position() {
         let mut calc = RiskCalculator::new(500.0);
         
-        // Long 1 MES @ 5000
-        calc.update_position("MES".to_string(), 1, 5000.0, 5.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert!(calc.has_position("MES"));
         
-        // Price moves to 5010 (+10 points * $5 = +$50)
+        // Setting quantity to 0 removes position
+        calc.update_position("MES".to_string(), 0.0, 0.0, 0.0);
+        assert!(!calc.has_position("MES"));
+    }
+
+    #[test]
+    fn test_expected_pnl() {
+        let mut calc = RiskCalculator::new(500.0);
+
+        // Long 2 MES @ 5000, currently 5010
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
         calc.update_price("MES", 5010.0);
-        
-        assert!((calc.unrealized_pnl() - 50.0).abs() < 0.01);
+
+        // Symmetric +/-20 around the current price: expectation is the current P&L
+        let scenarios = vec![(4990.0, 0.5), (5030.0, 0.5)];
+        let expected = calc.expected_pnl("MES", scenarios).unwrap();
+        assert!((expected - calc.unrealized_pnl()).abs() < 0.01);
+        assert!((expected - 100.0).abs() < 0.01);
+
+        // Skewed distribution
+        let scenarios = vec![(4990.0, 0.25), (5030.0, 0.75)];
+        let expected = calc.expected_pnl("MES", scenarios).unwrap();
+        assert!((expected - 200.0).abs() < 0.01);
+
+        // State is untouched
+        assert!((calc.unrealized_pnl() - 100.0).abs() < 0.01);
+
+        // Unheld symbol has no P&L
+        assert_eq!(calc.expected_pnl("MNQ", vec![(1.0, 1.0)]).unwrap(), 0.0);
     }
 
     #[test]
-    fn test_unrealized_pnl_short() {
+    fn test_expected_pnl_invalid_probabilities() {
         let mut calc = RiskCalculator::new(500.0);
-        
-        // Short 1 MES @ 5000
-        calc.update_position("MES".to_string(), -1, 5000.0, 5.0);
-        
-        // Price moves to 4990 (-10 points * -1 * $5 = +$50)
-        calc.update_price("MES", 4990.0);
-        
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+
+        assert!(calc.expected_pnl("MES", vec![(5010.0, 0.5), (4990.0, 0.4)]).is_err());
+        assert!(calc.expected_pnl("MES", vec![(5010.0, 1.5), (4990.0, -0.5)]).is_err());
+        assert!(calc.expected_pnl("MES", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_pnl_curve_values() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+        calc.update_price("MNQ", 16990.0);
+        calc.add_realized_pnl(-30.0);
+
+        // Base: realized -30 + MNQ +20; MES 2 * 5 per point
+        let grid = [4990.0, 5000.0, 5010.0];
+        let curve = calc.pnl_curve_values("MES", grid.into_iter());
+        assert_eq!(curve, vec![-110.0, -10.0, 90.0]);
+
+        // Matches repricing for real, without mutating state
+        let mut moved = calc.clone();
+        moved.update_price("MES", 5010.0);
+        assert_eq!(moved.total_pnl(), curve[2]);
+        assert_eq!(calc.total_pnl(), -10.0);
+
+        // Symbols not held give a flat curve
+        assert_eq!(calc.pnl_curve_values("M2K", grid.into_iter()), vec![-10.0; 3]);
+    }
+
+    #[test]
+    fn test_risk_breakdown_rows() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert!(calc.risk_breakdown_rows().is_empty());
+
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 15000.0, 2.0);
+        calc.update_position("M2K".to_string(), 1.0, 2500.0, 2.0);
+        calc.update_price("MNQ", 15010.0);
+
+        // Notionals 25000, -30020, 5000 of gross 60020
+        let rows = calc.risk_breakdown_rows();
+        let symbols: Vec<&str> = rows.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(symbols, ["MNQ", "MES", "M2K"]);
+        assert_eq!(rows[0].1, -20.0);
+        assert_eq!(rows[0].2, -30020.0);
+        assert!((rows[0].3 - 30020.0 / 60020.0 * 100.0).abs() < 1e-12);
+
+        let total: f64 = rows.iter().map(|r| r.3).sum();
+        assert!((total - 100.0).abs() < 1e-9);
+
+        // A JPY position ranks by its base-currency notional
+        calc.set_fx_rate("JPY".to_string(), 0.01).unwrap();
+        calc.set_symbol_currency("NK".to_string(), "JPY".to_string()).unwrap();
+        calc.update_position("NK".to_string(), 1.0, 10000.0, 100.0); // 10000 USD
+        let rows = calc.risk_breakdown_rows();
+        let symbols: Vec<&str> = rows.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(symbols, ["MNQ", "MES", "NK", "M2K"]);
+        assert_eq!(rows[2].2, 10000.0);
+        assert!((rows[2].3 - 10000.0 / 70020.0 * 100.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stress_test_vector() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_position("MNQ".to_string(), -1.0, 20000.0, 2.0);
+        calc.update_position("M2K".to_string(), 2.0, 2000.0, 5.0);
+
+        // MES down 2%: -100 * 5 = -500; MNQ up 1% against the short:
+        // -200 * 2 = -400; M2K unshocked
+        let shocks = HashMap::from([
+            ("MES".to_string(), -2.0),
+            ("MNQ".to_string(), 1.0),
+            ("ZZZ".to_string(), 50.0),
+        ]);
+        assert!((calc.stress_test_vector(shocks) + 900.0).abs() < 1e-9);
+
+        // Opposing shocks on the long and the short both help
+        let shocks = HashMap::from([("MES".to_string(), 2.0), ("MNQ".to_string(), -1.0)]);
+        assert!((calc.stress_test_vector(shocks) - 900.0).abs() < 1e-9);
+
+        // State is untouched
+        assert_eq!(calc.unrealized_pnl(), 0.0);
+        assert_eq!(calc.stress_test_vector(HashMap::new()), 0.0);
+    }
+
+    #[test]
+    fn test_normal_quantile() {
+        assert!(normal_quantile(0.5).abs() < 1e-9);
+        assert!((normal_quantile(0.95) - 1.6448536269514722).abs() < 1e-8);
+        assert!((normal_quantile(0.99) - 2.3263478740408408).abs() < 1e-8);
+        assert!((normal_quantile(0.01) + 2.3263478740408408).abs() < 1e-8);
+        assert!((normal_quantile(0.999) - 3.090232306167813).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_value_at_risk_and_expected_shortfall() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0); // 50000 notional
+        calc.update_position("MNQ".to_string(), -1.0, 15000.0, 2.0); // -30000 notional
+
+        let vols = HashMap::from([("MES".to_string(), 0.012), ("MNQ".to_string(), 0.02)]);
+
+        // sigma = sqrt(600² + 600²)
+        let sigma = (2.0f64 * 600.0 * 600.0).sqrt();
+        let var = calc.value_at_risk(vols.clone(), 0.99, 1.0).unwrap();
+        assert!((var - 2.3263478740408408 * sigma).abs() < 1e-4);
+
+        // Square-root-of-time scaling
+        let var10 = calc.value_at_risk(vols.clone(), 0.99, 10.0).unwrap();
+        assert!((var10 - var * 10f64.sqrt()).abs() < 1e-6);
+
+        // ES exceeds VaR; at 97.5% it is ~2.338 sigma
+        let es = calc.expected_shortfall(vols.clone(), 0.975, 1.0).unwrap();
+        assert!((es / sigma - 2.3378).abs() < 1e-3);
+        assert!(calc.expected_shortfall(vols.clone(), 0.99, 1.0).unwrap() > var);
+
+        // Missing vol for a held symbol
+        let partial = HashMap::from([("MES".to_string(), 0.012)]);
+        assert_eq!(
+            calc.value_at_risk(partial, 0.99, 1.0),
+            Err(Error::UnknownSymbol("MNQ".to_string()))
+        );
+
+        assert!(calc.value_at_risk(vols.clone(), 1.0, 1.0).is_err());
+        assert!(calc.expected_shortfall(vols, 0.99, -1.0).is_err());
+    }
+
+    #[test]
+    fn test_trailing_stop_long() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert!(!calc.trailing_stop_hit("MES"));
+        assert!(calc.set_trailing_stop("MNQ", 5.0).is_err());
+        assert!(calc.set_trailing_stop("MES", 0.0).is_err());
+
+        calc.set_trailing_stop("MES", 5.0).unwrap();
+        assert_eq!(calc.get_trailing_stop("MES"), Some(4995.0));
+
+        // Ratchets up with new highs, never down
+        calc.update_price("MES", 5010.0);
+        calc.update_price("MES", 5007.0);
+        assert_eq!(calc.get_trailing_stop("MES"), Some(5005.0));
+        assert!(!calc.trailing_stop_hit("MES"));
+
+        calc.update_price("MES", 5005.0);
+        assert!(calc.trailing_stop_hit("MES"));
+    }
+
+    #[test]
+    fn test_trailing_stop_short_and_flip() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MNQ".to_string(), -2.0, 17000.0, 2.0);
+        calc.update_price("MNQ", 16990.0);
+        calc.set_trailing_stop("MNQ", 10.0).unwrap();
+        assert_eq!(calc.get_trailing_stop("MNQ"), Some(17000.0));
+
+        calc.update_price("MNQ", 16970.0);
+        calc.update_price("MNQ", 16975.0);
+        assert_eq!(calc.get_trailing_stop("MNQ"), Some(16980.0));
+        assert!(!calc.trailing_stop_hit("MNQ"));
+
+        calc.update_price("MNQ", 16981.0);
+        assert!(calc.trailing_stop_hit("MNQ"));
+
+        // Scaling in keeps the stop; flipping drops it
+        calc.add_fill("MNQ".to_string(), -1.0, 16981.0, 2.0).unwrap();
+        assert_eq!(calc.get_trailing_stop("MNQ"), Some(16980.0));
+        calc.add_fill("MNQ".to_string(), 4.0, 16981.0, 2.0).unwrap();
+        assert_eq!(calc.get_trailing_stop("MNQ"), None);
+        assert!(!calc.trailing_stop_hit("MNQ"));
+    }
+
+    #[test]
+    fn test_reset_daily_reprice_entries() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.add_fill("MES".to_string(), 1.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 1.0, 5010.0, 5.0).unwrap();
+        calc.update_price("MES", 4960.0);
+
+        // Continuous mark: yesterday's -450 still counts today
+        let mut continuous = calc.clone();
+        continuous.reset_daily(false);
+        assert_eq!(continuous.total_pnl(), -450.0);
+
+        calc.reset_daily(true);
+        assert_eq!(calc.total_pnl(), 0.0);
+        assert_eq!(calc.get_lots("MES"), vec![(1.0, 4960.0), (1.0, 4960.0)]);
+
+        // Today's P&L runs from the re-based entry
+        calc.update_price("MES", 4950.0);
+        assert_eq!(calc.total_pnl(), -100.0);
+        assert!(!calc.is_daily_loss_breached());
+    }
+
+    #[test]
+    fn test_turnover_and_fill_count() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MNQ".to_string(), 1.0, 17000.0, 2.0);
+        assert_eq!(calc.fill_count(), 0);
+
+        calc.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        calc.add_fill("MES".to_string(), 0.0, 5000.0, 5.0).unwrap();
+        calc.close_quantity("MES".to_string(), 1.0, 5010.0).unwrap();
+        assert_eq!(calc.fill_count(), 2);
+        assert_eq!(calc.total_turnover(), 50_000.0 + 25_050.0);
+
+        // Rejected fills do not count
+        assert!(calc.add_fill("MES".to_string(), 1.0, 5000.0, 0.0).is_err());
+        assert_eq!(calc.fill_count(), 2);
+
+        calc.reset_daily(false);
+        assert_eq!(calc.fill_count(), 0);
+        assert_eq!(calc.total_turnover(), 0.0);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = RiskCalculator::new(500.0);
+        a.add_fill("MES".to_string(), 2.0, 5000.0, 5.0).unwrap();
+        a.add_fill("MNQ".to_string(), 1.0, 17000.0, 2.0).unwrap();
+        a.add_realized_pnl(100.0);
+
+        let mut b = RiskCalculator::new(300.0);
+        b.add_fill("MES".to_string(), 1.0, 5006.0, 5.0).unwrap();
+        b.add_fill("MNQ".to_string(), -1.0, 17010.0, 2.0).unwrap();
+        b.add_fill("M2K".to_string(), 3.0, 2000.0, 5.0).unwrap();
+        b.add_realized_pnl(-40.0);
+
+        let merged = a.merge(&b).unwrap();
+        assert_eq!(merged.get_max_daily_loss(), 800.0);
+
+        // Same side: quantities add at the blended entry
+        let mes = &merged.positions["MES"];
+        assert_eq!(mes.quantity, 3.0);
+        assert!((mes.entry_price - 5002.0).abs() < 1e-9);
+
+        // Opposite sides net out, realizing the 10-point gap
+        assert!(!merged.positions.contains_key("MNQ"));
+        assert_eq!(merged.get_realized_pnl(), 100.0 - 40.0 + 20.0);
+
+        // Symbols held by one side carry over
+        assert_eq!(merged.get_quantity("M2K"), 3.0);
+
+        // Inputs are untouched
+        assert_eq!(a.position_count(), 2);
+        assert_eq!(b.position_count(), 3);
+
+        let mut c = RiskCalculator::new(100.0);
+        c.add_fill("MES".to_string(), 1.0, 5000.0, 50.0).unwrap();
+        assert!(a.merge(&c).is_err());
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut calc = RiskCalculator::new(500.0);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Active);
+
+        // Flattening without a trip keeps trading active
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.clear_positions();
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Active);
+
+        // Breach trips; the flatten moves to cooldown
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        calc.update_price("MES", 4940.0);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Tripped);
+        calc.update_price("MES", 4930.0);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Tripped);
+        assert!((calc.flatten_all() + 700.0).abs() < 1e-9);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Cooldown);
+
+        // Only a manual arm (or a new day) resumes trading
+        calc.update_position("MES".to_string(), 1.0, 4930.0, 5.0);
+        calc.update_price("MES", 4931.0);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Cooldown);
+        calc.arm();
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Active);
+
+        // A new day starts active even if yesterday ended tripped
+        calc.reset_daily(false);
+        calc.update_price("MES", 4800.0);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Tripped);
+        calc.reset_daily(false);
+        assert_eq!(calc.breaker_state(), CircuitBreaker::Active);
+    }
+
+    #[test]
+    fn test_static_levels() {
+        let mut calc = RiskCalculator::new(1000.0);
+        assert!(calc.set_levels("MES", Some(4990.0), None).is_err());
+
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert!(calc.set_levels("MES", Some(f64::NAN), None).is_err());
+        calc.set_levels("MES", Some(4990.0), Some(5020.0)).unwrap();
+        assert_eq!(calc.check_levels("MES"), None);
+
+        calc.update_price("MES", 5020.0);
+        assert_eq!(calc.check_levels("MES"), Some(LevelHit::Target));
+        calc.update_price("MES", 4989.0);
+        assert_eq!(calc.check_levels("MES"), Some(LevelHit::Stop));
+
+        // Short: stop above, target below
+        calc.update_position("MNQ".to_string(), -1.0, 17000.0, 2.0);
+        calc.set_levels("MNQ", Some(17010.0), Some(16950.0)).unwrap();
+        calc.update_price("MNQ", 17010.0);
+        assert_eq!(calc.check_levels("MNQ"), Some(LevelHit::Stop));
+        calc.update_price("MNQ", 16940.0);
+        assert_eq!(calc.check_levels("MNQ"), Some(LevelHit::Target));
+
+        // Flipping or removing the position drops its levels
+        calc.add_fill("MNQ".to_string(), 2.0, 16940.0, 2.0).unwrap();
+        assert_eq!(calc.get_levels("MNQ"), Some((None, None)));
+        calc.update_position("MES".to_string(), 0.0, 0.0, 5.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        assert_eq!(calc.get_levels("MES"), Some((None, None)));
+        assert_eq!(calc.check_levels("MES"), None);
+    }
+
+    #[test]
+    fn test_mark_source() {
+        let mut calc = RiskCalculator::new(500.0);
+        assert_eq!(calc.get_mark_source(), "last");
+
+        // Long 1 MES @ 5000, last 5010, quoted 5008 / 5012
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_price("MES", 5010.0);
+
+        // No quotes yet: every mode falls back to last
+        for mode in ["last", "mid", "conservative"] {
+            calc.set_mark_source(mode).unwrap();
+            assert!((calc.unrealized_pnl() - 50.0).abs() < 0.01);
+        }
+
+        calc.update_quote("MES", Some(5008.0), Some(5014.0));
+
+        calc.set_mark_source("last").unwrap();
         assert!((calc.unrealized_pnl() - 50.0).abs() < 0.01);
+
+        calc.set_mark_source("mid").unwrap();
+        assert!((calc.unrealized_pnl() - 55.0).abs() < 0.01);
+
+        // Long exits at the bid
+        calc.set_mark_source("conservative").unwrap();
+        assert!((calc.unrealized_pnl() - 40.0).abs() < 0.01);
+
+        // Short exits at the ask
+        calc.update_position("MES".to_string(), -1.0, 5000.0, 5.0);
+        assert!((calc.unrealized_pnl() + 70.0).abs() < 0.01);
+
+        // One-sided quote: mid falls back to last
+        calc.update_quote("MES", Some(5008.0), None);
+        calc.set_mark_source("mid").unwrap();
+        assert!((calc.unrealized_pnl() + 50.0).abs() < 0.01);
+
+        assert!(calc.set_mark_source("bogus").is_err());
+        assert_eq!(calc.get_mark_source(), "mid");
     }
 
     #[test]
-    fn test_daily_loss_limit() {
+    fn test_calendar_spread_pnl() {
         let mut calc = RiskCalculator::new(500.0);
-        
-        // Add losing trade
-        calc.add_realized_pnl(-300.0);
+
+        // Long front month, short back month
+        calc.add_spread(
+            "MES_CAL".to_string(),
+            vec![
+                ("MESH5".to_string(), 1.0, 5000.0, 5.0),
+                ("MESM5".to_string(), -1.0, 5030.0, 5.0),
+            ],
+        )
+        .unwrap();
+        assert!(calc.has_spread("MES_CAL"));
+        assert_eq!(calc.spread_pnl("MES_CAL"), Some(0.0));
+
+        // Front +10, back +4: spread widens by 6 points * $5 = +$30
+        let prices = HashMap::from([
+            ("MESH5".to_string(), 5010.0),
+            ("MESM5".to_string(), 5034.0),
+        ]);
+        calc.update_spread_prices("MES_CAL", prices);
+        assert!((calc.spread_pnl("MES_CAL").unwrap() - 30.0).abs() < 0.01);
+
+        // Only the back leg moves: -6 points * -1 * $5 = -$30 more
+        let prices = HashMap::from([("MESM5".to_string(), 5040.0)]);
+        calc.update_spread_prices("MES_CAL", prices);
+        assert!(calc.spread_pnl("MES_CAL").unwrap().abs() < 0.01);
+
+        // Spreads don't affect single-position P&L
+        assert_eq!(calc.unrealized_pnl(), 0.0);
+
+        calc.remove_spread("MES_CAL");
+        assert!(calc.spread_pnl("MES_CAL").is_none());
+    }
+
+    #[test]
+    fn test_drawdown_tracking() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+
+        // Run up to +100, fall to +25, recover to +75
+        calc.update_price("MES", 5020.0);
+        assert_eq!(calc.peak_equity(), 100.0);
+        assert_eq!(calc.current_drawdown(), 0.0);
+
+        calc.update_price("MES", 5005.0);
+        assert_eq!(calc.current_drawdown(), 75.0);
+        assert_eq!(calc.max_drawdown(), 75.0);
+
+        calc.update_price("MES", 5015.0);
+        assert_eq!(calc.current_drawdown(), 25.0);
+        assert_eq!(calc.max_drawdown(), 75.0);
+
+        // Realized loss deepens the drawdown
+        calc.add_realized_pnl(-100.0);
+        assert_eq!(calc.current_drawdown(), 125.0);
+        assert_eq!(calc.max_drawdown(), 125.0);
+
+        // New peak resets current drawdown but not the max
+        calc.add_realized_pnl(200.0);
+        assert_eq!(calc.peak_equity(), 175.0);
+        assert_eq!(calc.current_drawdown(), 0.0);
+        assert_eq!(calc.max_drawdown(), 125.0);
+    }
+
+    #[test]
+    fn test_drawdown_limit() {
+        let mut calc = RiskCalculator::new(1000.0);
+        calc.update_position("MES".to_string(), 2.0, 5000.0, 5.0);
+        assert!(!calc.is_drawdown_breached());
+        assert!(calc.set_max_drawdown(0.0).is_err());
+
+        calc.set_max_drawdown(150.0).unwrap();
+        assert_eq!(calc.get_max_drawdown_limit(), Some(150.0));
+
+        // Unrealized run-up sets the peak at +300
+        calc.update_price("MES", 5030.0);
+        calc.update_price("MES", 5020.0); // +200, drawdown 100
+        assert!(!calc.is_drawdown_breached());
+        assert!(!calc.is_any_limit_breached());
+
+        // +150: still up on the day but 150 off the peak
+        calc.update_price("MES", 5015.0);
+        assert!(calc.is_drawdown_breached());
         assert!(!calc.is_daily_loss_breached());
-        assert_eq!(calc.remaining_risk(), 200.0);
-        
-        // Add another losing trade
-        calc.add_realized_pnl(-250.0);
-        assert!(calc.is_daily_loss_breached());
+        assert!(calc.is_any_limit_breached());
+
+        calc.clear_max_drawdown();
+        assert!(!calc.is_any_limit_breached());
+
+        // Symbol limits count toward the combined check
+        calc.set_symbol_loss_limit("MES".to_string(), 50.0);
+        calc.update_price("MES", 4994.0);
+        assert!(calc.is_any_limit_breached());
     }
 
     #[test]
-    fn test_remove_position() {
+    fn test_drawdown_duration() {
         let mut calc = RiskCalculator::new(500.0);
-        
-        calc.update_position("MES".to_string(), 1, 5000.0, 5.0);
-        assert!(calc.has_position("MES"));
-        
-        // Setting quantity to 0 removes position
-        calc.update_position("MES".to_string(), 0, 0.0, 0.0);
-        assert!(!calc.has_position("MES"));
+        calc.set_clock(Some(1000.0));
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+
+        // New peak at t=1000
+        calc.update_price("MES", 5020.0);
+        assert_eq!(calc.drawdown_duration_secs(), 0.0);
+
+        // Underwater from t=1010 onwards, measured from the peak
+        calc.set_clock(Some(1010.0));
+        calc.update_price("MES", 5010.0);
+        assert_eq!(calc.drawdown_duration_secs(), 10.0);
+
+        calc.set_clock(Some(1060.0));
+        calc.update_price("MES", 5015.0);
+        assert_eq!(calc.drawdown_duration_secs(), 60.0);
+
+        // Duration keeps growing with time even without updates
+        calc.set_clock(Some(1090.0));
+        assert_eq!(calc.drawdown_duration_secs(), 90.0);
+
+        // New peak resets the clock
+        calc.update_price("MES", 5030.0);
+        assert_eq!(calc.drawdown_duration_secs(), 0.0);
+
+        calc.set_clock(Some(1100.0));
+        calc.update_price("MES", 5025.0);
+        assert_eq!(calc.drawdown_duration_secs(), 10.0);
+    }
+
+    #[test]
+    fn test_reset_daily_resets_peak_to_current_equity() {
+        let mut calc = RiskCalculator::new(500.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
+        calc.update_price("MES", 5020.0);
+        calc.add_realized_pnl(50.0);
+        calc.update_price("MES", 5010.0);
+        assert_eq!(calc.max_drawdown(), 50.0);
+
+        calc.reset_daily(false);
+
+        // Carried position is worth +50; that's the new peak, not zero
+        assert_eq!(calc.peak_equity(), 50.0);
+        assert_eq!(calc.max_drawdown(), 0.0);
+        assert_eq!(calc.current_drawdown(), 0.0);
     }
 
     #[test]
     fn test_reset_daily() {
         let mut calc = RiskCalculator::new(500.0);
         
-        calc.update_position("MES".to_string(), 1, 5000.0, 5.0);
+        calc.update_position("MES".to_string(), 1.0, 5000.0, 5.0);
         calc.add_realized_pnl(-100.0);
         
-        calc.reset_daily();
+        calc.reset_daily(false);
         
         // Realized P&L resets
         assert_eq!(calc.get_realized_pnl(), 0.0);
@@ -0,0 +1,475 @@
+//! Shifted-data rolling mean and variance
+//!
+//! The numerical core behind `ZScoreEngine`, as a plain Rust type with
+//! no Python bindings. Values are shifted by a reference value K before
+//! being summed, so the variance stays accurate for large price levels.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Rolling window with O(1) mean and sample variance
+///
+/// The caller decides the window policy: `push` adds the newest value
+/// and `pop` removes the oldest, so the same accumulator serves count
+/// windows and time windows alike. K is re-centered on the window mean
+/// once every value present when it was chosen has been popped, which
+/// keeps the shifted sums small without a per-update full pass.
+///
+/// Values may carry weights (e.g., bar volume) via `push_weighted`; a
+/// plain `push` has weight 1, and with all weights 1 the results are
+/// the ordinary equal-weighted statistics.
+///
+/// # Example
+/// ```
+/// use quant_scalper_rust::RollingStats;
+///
+/// let mut stats = RollingStats::new();
+/// for price in [5000.25, 5000.50, 5000.75] {
+///     stats.push(price);
+/// }
+/// stats.pop();
+/// assert_eq!(stats.mean(), Some(5000.625));
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[allow(non_snake_case)] // K/W/Ex/Ex2 follow the shifted-data algorithm's notation
+pub struct RollingStats {
+    values: VecDeque<f64>,
+    weights: VecDeque<f64>,
+    K: f64,   // Reference value for shifting
+    W: f64,   // Sum of w
+    W2: f64,  // Sum of w²
+    Ex: f64,  // Sum of w(x - K)
+    Ex2: f64, // Sum of w(x - K)²
+    Sx: f64,  // Unweighted sum of (x - K)
+    Sx2: f64, // Unweighted sum of (x - K)²
+    Sxy: f64, // Sum of (x[i-1] - K)(x[i] - K) over adjacent pairs
+    k_ttl: usize, // Pops left before K is re-centered
+}
+
+impl RollingStats {
+    /// Create an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty accumulator with room for `capacity` values
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(capacity),
+            weights: VecDeque::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Add a value at the back of the window
+    pub fn push(&mut self, x: f64) {
+        self.push_weighted(x, 1.0);
+    }
+
+    /// Add a value with weight `w` (> 0) at the back of the window
+    pub fn push_weighted(&mut self, x: f64, w: f64) {
+        // Initialize K on the first value for numerical stability
+        if self.values.is_empty() {
+            self.K = x;
+            self.k_ttl = 1;
+        }
+
+        let dx = x - self.K;
+        if let Some(&last) = self.values.back() {
+            self.Sxy += (last - self.K) * dx;
+        }
+        self.W += w;
+        self.W2 += w * w;
+        self.Ex += w * dx;
+        self.Ex2 += w * dx * dx;
+        self.Sx += dx;
+        self.Sx2 += dx * dx;
+        self.values.push_back(x);
+        self.weights.push_back(w);
+    }
+
+    /// Multiply every weight in the window by `factor` (> 0)
+    ///
+    /// Leaves the mean and variance unchanged; lets callers with growing
+    /// weights (e.g., exponential decay) keep them in range.
+    pub fn scale_weights(&mut self, factor: f64) {
+        for w in self.weights.iter_mut() {
+            *w *= factor;
+        }
+        self.W *= factor;
+        self.W2 *= factor * factor;
+        self.Ex *= factor;
+        self.Ex2 *= factor;
+    }
+
+    /// Remove and return the oldest value (None if empty)
+    pub fn pop(&mut self) -> Option<f64> {
+        let old = self.values.pop_front()?;
+        let w = self.weights.pop_front().unwrap_or(1.0);
+        let dx = old - self.K;
+        if let Some(&front) = self.values.front() {
+            self.Sxy -= dx * (front - self.K);
+        }
+        self.W -= w;
+        self.W2 -= w * w;
+        self.Ex -= w * dx;
+        self.Ex2 -= w * dx * dx;
+        self.Sx -= dx;
+        self.Sx2 -= dx * dx;
+
+        // Once every value that was in the window when K was chosen has
+        // been popped, re-center K on the current window
+        self.k_ttl = self.k_ttl.saturating_sub(1);
+        if self.k_ttl == 0 {
+            self.recenter();
+        }
+        Some(old)
+    }
+
+    /// Weighted mean of the window: K + Ex/W (None if empty)
+    pub fn mean(&self) -> Option<f64> {
+        if self.values.is_empty() {
+            return None;
+        }
+        Some(self.K + self.Ex / self.W)
+    }
+
+    /// Weighted sample variance of the window (None if fewer than 2 values)
+    ///
+    /// Uses the reliability-weights correction `W - W2/W`, which is
+    /// `n - 1` when all weights are 1 and does not depend on the scale
+    /// of the weights. Also None when one weight dwarfs the rest so far
+    /// that the correction rounds to 0.
+    pub fn variance(&self) -> Option<f64> {
+        if self.values.len() < 2 {
+            return None;
+        }
+
+        let denom = self.W - self.W2 / self.W;
+        if denom <= 0.0 {
+            return None;
+        }
+        Some(self.weighted_sq_dev() / denom)
+    }
+
+    /// Weighted population variance of the window, `Σw(x - mean)² / W`
+    /// (None if fewer than 2 values)
+    pub fn population_variance(&self) -> Option<f64> {
+        if self.values.len() < 2 {
+            return None;
+        }
+        Some(self.weighted_sq_dev() / self.W)
+    }
+
+    /// Weighted mean of the window without its newest value (None if
+    /// fewer than 2 values)
+    pub fn prior_mean(&self) -> Option<f64> {
+        if self.values.len() < 2 {
+            return None;
+        }
+
+        let dx = self.values.back()? - self.K;
+        let w = *self.weights.back()?;
+        Some(self.K + (self.Ex - w * dx) / (self.W - w))
+    }
+
+    /// Weighted mean and sample variance of the window without its newest
+    /// value, as (mean, variance)
+    ///
+    /// O(1): the newest value is taken back out of the sums. None with
+    /// fewer than 3 values.
+    pub fn prior_mean_variance(&self) -> Option<(f64, f64)> {
+        if self.values.len() < 3 {
+            return None;
+        }
+
+        let dx = self.values.back()? - self.K;
+        let w = *self.weights.back()?;
+        let total = self.W - w;
+        let ex = self.Ex - w * dx;
+        let ex2 = self.Ex2 - w * dx * dx;
+        let denom = total - (self.W2 - w * w) / total;
+        if denom <= 0.0 {
+            return None;
+        }
+        let variance = (ex2 - (ex * ex) / total) / denom;
+        Some((self.K + ex / total, variance.max(0.0)))
+    }
+
+    /// Least-squares AR(1) fit `x[i] = a + b * x[i-1]` as (a, b)
+    ///
+    /// Regresses each value on its predecessor over the adjacent pairs in
+    /// the window, ignoring weights. O(1): the lagged co-moment is kept
+    /// alongside the other shifted sums. None with fewer than 3 values or
+    /// when the variance of the lagged values is at or below
+    /// `variance_floor`.
+    pub fn ar1(&self, variance_floor: f64) -> Option<(f64, f64)> {
+        let n = self.values.len();
+        if n < 3 {
+            return None;
+        }
+
+        // Pair sums from the window sums: predecessors drop the newest
+        // value, successors the oldest
+        let first = self.values.front()? - self.K;
+        let last = self.values.back()? - self.K;
+        let m = (n - 1) as f64;
+        let sum_prev = self.Sx - last;
+        let sum_next = self.Sx - first;
+        let spp = (self.Sx2 - last * last) - sum_prev * sum_prev / m;
+        let spn = self.Sxy - sum_prev * sum_next / m;
+
+        if spp / m <= variance_floor {
+            return None;
+        }
+        let b = spn / spp;
+        let a = self.K + sum_next / m - b * (self.K + sum_prev / m);
+        Some((a, b))
+    }
+
+    /// Values in the window, oldest first
+    pub fn values(&self) -> &VecDeque<f64> {
+        &self.values
+    }
+
+    /// Sum of the weights in the window (the count when unweighted)
+    pub fn total_weight(&self) -> f64 {
+        self.W
+    }
+
+    /// Allocated heap memory of the window in bytes
+    pub fn heap_bytes(&self) -> usize {
+        (self.values.capacity() + self.weights.capacity()) * std::mem::size_of::<f64>()
+    }
+
+    /// Number of values in the window
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check whether the window is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Check that each value has a weight and every weight is finite and > 0
+    pub fn is_consistent(&self) -> bool {
+        self.values.len() == self.weights.len()
+            && self.weights.iter().all(|&w| w.is_finite() && w > 0.0)
+    }
+
+    /// Remove all values
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.weights.clear();
+        self.K = 0.0;
+        self.W = 0.0;
+        self.W2 = 0.0;
+        self.Ex = 0.0;
+        self.Ex2 = 0.0;
+        self.Sx = 0.0;
+        self.Sx2 = 0.0;
+        self.Sxy = 0.0;
+        self.k_ttl = 0;
+    }
+
+    /// Re-center K on the oldest value and rebuild the sums with a full
+    /// pass, discarding any accumulated floating-point drift
+    pub fn resync(&mut self) {
+        if let Some(&first) = self.values.front() {
+            self.K = first;
+            self.rebuild_sums();
+        }
+    }
+
+    /// Weighted sum of squared deviations from the mean, `Σw(x - mean)²`
+    fn weighted_sq_dev(&self) -> f64 {
+        // Handle numerical precision issues (tiny negative values possible)
+        (self.Ex2 - (self.Ex * self.Ex) / self.W).max(0.0)
+    }
+
+    /// Re-center K on the current mean and rebuild the shifted sums
+    ///
+    /// A full pass over the window (rather than an algebraic shift of the
+    /// old sums) discards any error accumulated against the previous K, and
+    /// centering on the mean keeps (x - K) small even if the front of the
+    /// window is an outlier tick. Runs once per window turnover, so the
+    /// cost is amortized O(1) per update.
+    fn recenter(&mut self) {
+        if let Some(mean) = self.mean() {
+            self.K = mean;
+            self.rebuild_sums();
+        }
+    }
+
+    /// Rebuild the shifted sums around the current K with a full pass
+    fn rebuild_sums(&mut self) {
+        self.W = 0.0;
+        self.W2 = 0.0;
+        self.Ex = 0.0;
+        self.Ex2 = 0.0;
+        self.Sx = 0.0;
+        self.Sx2 = 0.0;
+        self.Sxy = 0.0;
+        let mut prev: Option<f64> = None;
+        for (&x, &w) in self.values.iter().zip(&self.weights) {
+            let dx = x - self.K;
+            self.W += w;
+            self.W2 += w * w;
+            self.Ex += w * dx;
+            self.Ex2 += w * dx * dx;
+            self.Sx += dx;
+            self.Sx2 += dx * dx;
+            if let Some(prev) = prev.replace(dx) {
+                self.Sxy += prev * dx;
+            }
+        }
+
+        self.k_ttl = self.values.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let mut stats = RollingStats::new();
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.pop(), None);
+
+        stats.push(1.0);
+        assert_eq!(stats.mean(), Some(1.0));
+        assert_eq!(stats.variance(), None);
+
+        for x in [2.0, 3.0, 4.0] {
+            stats.push(x);
+        }
+        assert_eq!(stats.pop(), Some(1.0));
+        assert_eq!(stats.len(), 3);
+        assert!((stats.mean().unwrap() - 3.0).abs() < 1e-12);
+        assert!((stats.variance().unwrap() - 1.0).abs() < 1e-12);
+
+        stats.clear();
+        assert!(stats.is_empty());
+    }
+
+    #[test]
+    fn test_large_price_levels() {
+        let mut stats = RollingStats::with_capacity(21);
+        for i in 0..10_000 {
+            stats.push(1e9 + (i % 7) as f64 * 0.25);
+            if stats.len() > 20 {
+                stats.pop();
+            }
+        }
+
+        let values: Vec<f64> = stats.values().iter().copied().collect();
+        let mean = values.iter().sum::<f64>() / 20.0;
+        let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 19.0;
+        assert!((stats.mean().unwrap() - mean).abs() < 1e-6);
+        assert!((stats.variance().unwrap() - variance).abs() < 1e-9);
+
+        stats.resync();
+        assert!((stats.variance().unwrap() - variance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prior_mean_variance() {
+        let mut stats = RollingStats::new();
+        stats.push(1.0);
+        stats.push(3.0);
+        assert_eq!(stats.prior_mean_variance(), None);
+
+        stats.push(100.0);
+        assert_eq!(stats.prior_mean_variance(), Some((2.0, 2.0)));
+
+        // Weighted: matches a window holding only the prior values
+        let mut weighted = RollingStats::new();
+        let mut prior = RollingStats::new();
+        for (x, w) in [(10.0, 2.0), (12.0, 1.0), (11.0, 3.0)] {
+            weighted.push_weighted(x, w);
+            prior.push_weighted(x, w);
+        }
+        weighted.push_weighted(50.0, 4.0);
+        let (mean, variance) = weighted.prior_mean_variance().unwrap();
+        assert!((mean - prior.mean().unwrap()).abs() < 1e-12);
+        assert!((variance - prior.variance().unwrap()).abs() < 1e-12);
+
+        // Rescaling the weights changes nothing
+        let (mean, variance) = (prior.mean().unwrap(), prior.variance().unwrap());
+        prior.scale_weights(1e-3);
+        assert!((prior.mean().unwrap() - mean).abs() < 1e-12);
+        assert!((prior.variance().unwrap() - variance).abs() < 1e-12);
+        assert!((prior.total_weight() - 6e-3).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_ar1() {
+        let mut stats = RollingStats::new();
+        let mut x = 1e6 + 8.0;
+        let mut all = Vec::new();
+        for i in 0..1000 {
+            // x[i] = 1e6 * 0.2 + 0.8 * x[i-1] + noise
+            x = 0.2 * 1e6 + 0.8 * x + ((i * 7919) % 13) as f64 * 0.1 - 0.6;
+            stats.push(x);
+            all.push(x);
+            if stats.len() > 30 {
+                stats.pop();
+            }
+        }
+
+        // Reference least squares over the final window's pairs
+        let window = &all[all.len() - 30..];
+        let (prev, next) = (&window[..29], &window[1..]);
+        let mp = prev.iter().sum::<f64>() / 29.0;
+        let mn = next.iter().sum::<f64>() / 29.0;
+        let spn: f64 = prev.iter().zip(next).map(|(p, n)| (p - mp) * (n - mn)).sum();
+        let spp: f64 = prev.iter().map(|p| (p - mp).powi(2)).sum();
+        let b = spn / spp;
+
+        let (a_fit, b_fit) = stats.ar1(1e-10).unwrap();
+        assert!((b_fit - b).abs() < 1e-9, "{} vs {}", b_fit, b);
+        assert!((a_fit - (mn - b * mp)).abs() < 1e-3);
+
+        let mut flat = RollingStats::new();
+        for _ in 0..5 {
+            flat.push(3.0);
+        }
+        assert_eq!(flat.ar1(0.0), None);
+
+        // Pip-sized moves fit only under a floor below their variance
+        let mut tiny = RollingStats::new();
+        for x in [1.10000, 1.10001, 1.09999, 1.10000, 1.10001] {
+            tiny.push(x);
+        }
+        assert_eq!(tiny.ar1(1e-10), None);
+        assert!(tiny.ar1(1e-14).is_some());
+    }
+
+    #[test]
+    fn test_weighted() {
+        let mut stats = RollingStats::new();
+        stats.push_weighted(10.0, 1.0);
+        stats.push_weighted(20.0, 3.0);
+        assert_eq!(stats.total_weight(), 4.0);
+        assert!((stats.mean().unwrap() - 17.5).abs() < 1e-12);
+
+        // Σw(x - mean)² = 56.25 + 3 * 6.25 = 75, over 4 - 10/4 = 1.5
+        assert!((stats.variance().unwrap() - 50.0).abs() < 1e-12);
+
+        // Scaling all weights changes nothing
+        let mut scaled = RollingStats::new();
+        scaled.push_weighted(10.0, 100.0);
+        scaled.push_weighted(20.0, 300.0);
+        assert!((scaled.mean().unwrap() - 17.5).abs() < 1e-12);
+        assert!((scaled.variance().unwrap() - 50.0).abs() < 1e-9);
+
+        // Popping removes the value together with its weight
+        stats.push_weighted(30.0, 1.0);
+        stats.pop();
+        assert_eq!(stats.total_weight(), 4.0);
+        assert!((stats.mean().unwrap() - 22.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,188 @@
+//! Relative Strength Index with Wilder's smoothing
+//!
+//! Runs side-by-side with `ZScoreEngine`. Only bar-to-bar price changes
+//! enter the averages, so large price levels cost no precision beyond
+//! the subtraction of adjacent prices.
+
+use pyo3::prelude::*;
+
+use crate::error::Error;
+
+/// Incremental RSI engine using Wilder's smoothed average gain/loss
+///
+/// The first value needs `period + 1` prices: the averages are seeded
+/// with the simple mean of the first `period` changes, then smoothed as
+/// `avg = (avg * (period - 1) + change) / period`.
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import RsiEngine
+///
+/// rsi = RsiEngine(14)
+///
+/// for price in prices:
+///     value = rsi.update(price)
+///     if value is not None and value >= 70.0:
+///         print("Overbought signal!")
+/// ```
+#[pyclass]
+#[derive(Clone)]
+pub struct RsiEngine {
+    period: usize,
+    prev_price: Option<f64>,
+    changes: usize, // Price changes seen, capped once seeded
+    avg_gain: f64,
+    avg_loss: f64, // Average loss as a positive amount
+}
+
+#[pymethods]
+impl RsiEngine {
+    /// Create a new RSI engine with specified period
+    ///
+    /// # Arguments
+    /// * `period` - Number of price changes in the smoothing (e.g., 14)
+    #[new]
+    pub fn new(period: usize) -> Result<Self, Error> {
+        if period == 0 {
+            return Err(Error::InvalidArgument("Period must be > 0".to_string()));
+        }
+
+        Ok(Self {
+            period,
+            prev_price: None,
+            changes: 0,
+            avg_gain: 0.0,
+            avg_loss: 0.0,
+        })
+    }
+
+    /// Update with new price and return current RSI (0..100)
+    ///
+    /// Returns None if insufficient data (warming up period).
+    pub fn update(&mut self, price: f64) -> Option<f64> {
+        // The first price only seeds the previous price
+        let prev = self.prev_price.replace(price)?;
+
+        let change = price - prev;
+        let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+        let period = self.period as f64;
+
+        if self.changes < self.period {
+            // Seeding: accumulate a simple mean of the first changes
+            self.changes += 1;
+            self.avg_gain += gain / period;
+            self.avg_loss += loss / period;
+        } else {
+            self.avg_gain = (self.avg_gain * (period - 1.0) + gain) / period;
+            self.avg_loss = (self.avg_loss * (period - 1.0) + loss) / period;
+        }
+
+        self.get_rsi()
+    }
+
+    /// Get current RSI without adding new data
+    ///
+    /// 100 when there have been no losses, 50 when prices have been flat.
+    pub fn get_rsi(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        if self.avg_loss <= 0.0 {
+            return Some(if self.avg_gain <= 0.0 { 50.0 } else { 100.0 });
+        }
+
+        let rs = self.avg_gain / self.avg_loss;
+        Some(100.0 - 100.0 / (1.0 + rs))
+    }
+
+    /// Get Wilder's smoothed average gain (None during warmup)
+    pub fn get_avg_gain(&self) -> Option<f64> {
+        self.is_ready().then_some(self.avg_gain)
+    }
+
+    /// Get Wilder's smoothed average loss as a positive amount
+    /// (None during warmup)
+    pub fn get_avg_loss(&self) -> Option<f64> {
+        self.is_ready().then_some(self.avg_loss)
+    }
+
+    /// Reset the engine, clearing all data
+    pub fn reset(&mut self) {
+        self.prev_price = None;
+        self.changes = 0;
+        self.avg_gain = 0.0;
+        self.avg_loss = 0.0;
+    }
+
+    /// Check if engine has enough data for a valid RSI
+    pub fn is_ready(&self) -> bool {
+        self.changes >= self.period
+    }
+
+    /// Get the RSI period
+    pub fn period(&self) -> usize {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rsi_warmup_and_wilder_smoothing() {
+        let mut rsi = RsiEngine::new(3).unwrap();
+
+        // Needs period + 1 prices
+        for p in [10.0, 11.0, 10.0] {
+            assert_eq!(rsi.update(p), None);
+        }
+
+        // Changes +1, -1, +2: avg gain 1, avg loss 1/3
+        let value = rsi.update(12.0).unwrap();
+        assert!((value - 75.0).abs() < 1e-12);
+
+        // Change -3: gain 2/3, loss (2/3 + 3) / 3 = 11/9
+        let value = rsi.update(9.0).unwrap();
+        let rs = (2.0 / 3.0) / (11.0 / 9.0);
+        assert!((value - (100.0 - 100.0 / (1.0 + rs))).abs() < 1e-12);
+
+        rsi.reset();
+        assert!(!rsi.is_ready());
+        assert_eq!(rsi.get_rsi(), None);
+    }
+
+    #[test]
+    fn test_rsi_edge_cases() {
+        assert!(RsiEngine::new(0).is_err());
+
+        let mut rising = RsiEngine::new(2).unwrap();
+        for p in [1.0, 2.0, 3.0] {
+            rising.update(p);
+        }
+        assert_eq!(rising.get_rsi(), Some(100.0));
+
+        let mut flat = RsiEngine::new(2).unwrap();
+        for _ in 0..5 {
+            flat.update(100.0);
+        }
+        assert_eq!(flat.get_rsi(), Some(50.0));
+    }
+
+    #[test]
+    fn test_rsi_large_price_levels() {
+        let mut small = RsiEngine::new(14).unwrap();
+        let mut large = RsiEngine::new(14).unwrap();
+
+        for i in 0..1000 {
+            let delta = (i as f64 * 0.3).sin() * 2.0;
+            let a = small.update(100.0 + delta);
+            let b = large.update(1e9 + delta);
+            assert_eq!(a.is_some(), b.is_some());
+            if let (Some(a), Some(b)) = (a, b) {
+                assert!((a - b).abs() < 1e-4, "{} vs {}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Z-Score of a hedged two-leg spread
+//!
+//! Stat-arb primitive: forms `leg_a - beta * leg_b` and runs the
+//! shifted-data Z-Score over it. The hedge ratio is either fixed or
+//! re-estimated each bar by rolling regression of leg A on leg B.
+
+use pyo3::prelude::*;
+
+use crate::correlation::RollingCorrelation;
+use crate::error::Error;
+use crate::zscore::ZScoreEngine;
+
+/// Rolling Z-Score of the spread `leg_a - beta * leg_b`
+///
+/// With a fixed `beta` the spread is formed from the first bar. With
+/// `beta=None` the hedge ratio is the rolling regression slope of leg A
+/// on leg B over the same lookback, so the spread starts once the
+/// regression window is full.
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import SpreadZScoreEngine
+///
+/// engine = SpreadZScoreEngine(50, beta=2.5)  # or beta=None to estimate
+///
+/// for a, b in zip(prices_a, prices_b):
+///     zscore = engine.update(a, b)
+/// ```
+#[pyclass]
+pub struct SpreadZScoreEngine {
+    beta: Option<f64>,
+    regression: Option<RollingCorrelation>, // Rolling beta estimate (beta=None only)
+    spread: Option<f64>,
+    zscore: ZScoreEngine,
+}
+
+#[pymethods]
+impl SpreadZScoreEngine {
+    /// Create a new engine
+    ///
+    /// # Arguments
+    /// * `lookback` - Number of spread values for the Z-Score window (and
+    ///   of bars in the regression window when `beta` is estimated)
+    /// * `beta` - Fixed hedge ratio, or None to estimate it by rolling
+    ///   regression
+    #[new]
+    #[pyo3(signature = (lookback, beta=None))]
+    pub fn new(lookback: usize, beta: Option<f64>) -> Result<Self, Error> {
+        let regression = match beta {
+            Some(_) => None,
+            None => Some(RollingCorrelation::new(lookback)?),
+        };
+
+        Ok(Self {
+            beta,
+            regression,
+            spread: None,
+            zscore: ZScoreEngine::new(lookback)?,
+        })
+    }
+
+    /// Update with the latest prices of both legs and return the Z-Score
+    ///
+    /// Returns None until the hedge ratio and the Z-Score window are both
+    /// warmed up.
+    pub fn update(&mut self, leg_a: f64, leg_b: f64) -> Result<Option<f64>, Error> {
+        if let Some(regression) = self.regression.as_mut() {
+            regression.update(leg_b, leg_a);
+        }
+
+        match self.get_beta() {
+            Some(beta) => {
+                let spread = leg_a - beta * leg_b;
+                self.spread = Some(spread);
+                self.zscore.update(spread)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get the hedge ratio in use (None while it is still being estimated)
+    pub fn get_beta(&self) -> Option<f64> {
+        match &self.regression {
+            Some(regression) => regression.get_beta(),
+            None => self.beta,
+        }
+    }
+
+    /// Get the latest spread `leg_a - beta * leg_b`
+    pub fn get_spread(&self) -> Option<f64> {
+        self.spread
+    }
+
+    /// Get current Z-Score of the spread without adding new data
+    pub fn get_zscore(&self) -> Option<f64> {
+        self.zscore.get_zscore()
+    }
+
+    /// Get rolling mean of the spread
+    pub fn get_mean(&self) -> Option<f64> {
+        self.zscore.get_mean()
+    }
+
+    /// Get rolling standard deviation of the spread
+    pub fn get_std(&self) -> Option<f64> {
+        self.zscore.get_std()
+    }
+
+    /// Check if engine has enough data to generate signals
+    pub fn is_ready(&self) -> bool {
+        self.zscore.is_ready()
+    }
+
+    /// Reset the engine, clearing all data (a fixed beta is kept)
+    pub fn reset(&mut self) {
+        if let Some(regression) = self.regression.as_mut() {
+            regression.reset();
+        }
+        self.spread = None;
+        self.zscore.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_beta_spread() {
+        let mut engine = SpreadZScoreEngine::new(3, Some(2.0)).unwrap();
+        assert!(engine.update(210.0, 100.0).unwrap().is_none());
+        assert_eq!(engine.get_spread(), Some(10.0));
+        assert!(engine.update(212.0, 100.0).unwrap().is_none());
+
+        // Spreads 10, 12, 14: mean 12, std 2, Z-Score 1
+        let z = engine.update(214.0, 100.0).unwrap().unwrap();
+        assert!((z - 1.0).abs() < 1e-12);
+        assert!((engine.get_mean().unwrap() - 12.0).abs() < 1e-12);
+        assert!((engine.get_std().unwrap() - 2.0).abs() < 1e-12);
+        assert_eq!(engine.get_beta(), Some(2.0));
+
+        engine.reset();
+        assert!(!engine.is_ready());
+        assert_eq!(engine.get_spread(), None);
+        assert_eq!(engine.get_beta(), Some(2.0));
+    }
+
+    #[test]
+    fn test_estimated_beta() {
+        let lookback = 20;
+        let mut engine = SpreadZScoreEngine::new(lookback, None).unwrap();
+
+        let (mut a, mut b) = (0.0, 0.0);
+        for i in 0..500 {
+            b = 17000.0 + (i as f64 * 0.07).sin() * 40.0;
+            a = 1.5 * b + 300.0 + (i as f64 * 1.3).sin() * 0.5;
+            engine.update(a, b).unwrap();
+
+            // Regression fills first, then the spread window
+            if i < lookback - 1 {
+                assert_eq!(engine.get_beta(), None);
+            }
+            if i < 2 * lookback - 2 {
+                assert!(!engine.is_ready());
+            }
+        }
+
+        let beta = engine.get_beta().unwrap();
+        assert!((beta - 1.5).abs() < 0.01, "Beta {} not near 1.5", beta);
+        assert!((engine.get_spread().unwrap() - (a - beta * b)).abs() < 1e-9);
+        assert!(engine.get_zscore().is_some());
+    }
+}
@@ -0,0 +1,240 @@
+//! Rolling Z-Score using Welford's online algorithm
+//!
+//! Alternative backend to the shifted-data `ZScoreEngine`, useful as a
+//! cross-check. Welford's update tracks the mean and the sum of squared
+//! deviations (M2) directly. Values are offset by a recent window mean so
+//! large prices don't eat the mantissa bits the deviations need.
+
+use pyo3::prelude::*;
+use std::collections::VecDeque;
+
+use crate::error::Error;
+
+/// Z-Score engine backed by Welford's rolling mean/M2 updates
+///
+/// Exposes the same core API as `ZScoreEngine`.
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import WelfordZScoreEngine
+///
+/// engine = WelfordZScoreEngine(20)
+///
+/// for price in prices:
+///     zscore = engine.update(price)
+/// ```
+#[pyclass]
+pub struct WelfordZScoreEngine {
+    prices: VecDeque<f64>,
+    lookback: usize,
+    offset: f64, // Subtracted from prices before the Welford steps
+    mean: f64,   // Mean of (price - offset)
+    m2: f64,     // Sum of squared deviations from the mean
+    evictions: usize, // Evictions since mean/M2 were last rebuilt
+}
+
+#[pymethods]
+impl WelfordZScoreEngine {
+    /// Create a new engine with specified lookback period
+    ///
+    /// # Arguments
+    /// * `lookback` - Number of bars for rolling calculation (e.g., 20)
+    #[new]
+    pub fn new(lookback: usize) -> Result<Self, Error> {
+        if lookback < 2 {
+            return Err(Error::InvalidArgument("Lookback must be > 1".to_string()));
+        }
+
+        Ok(Self {
+            prices: VecDeque::with_capacity(lookback + 1),
+            lookback,
+            offset: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+            evictions: 0,
+        })
+    }
+
+    /// Update with new price and return current Z-Score
+    ///
+    /// Returns None if insufficient data (warming up period).
+    pub fn update(&mut self, price: f64) -> Option<f64> {
+        if self.prices.is_empty() {
+            self.offset = price;
+        }
+
+        self.prices.push_back(price);
+        let x = price - self.offset;
+        let n = self.prices.len() as f64;
+        let delta = x - self.mean;
+        self.mean += delta / n;
+        self.m2 += delta * (x - self.mean);
+
+        if self.prices.len() > self.lookback {
+            if let Some(old) = self.prices.pop_front() {
+                // Inverse Welford step: take `old` back out of mean and M2
+                let x = old - self.offset;
+                let n = self.prices.len() as f64;
+                let delta = x - self.mean;
+                self.mean -= delta / n;
+                self.m2 -= delta * (x - self.mean);
+                self.evictions += 1;
+            }
+
+            // Rebuild with an exact two-pass once per window turnover so
+            // rounding error from the inverse steps doesn't accumulate
+            if self.evictions >= self.lookback {
+                self.rebuild();
+            }
+        }
+
+        self.get_zscore()
+    }
+
+    /// Get current Z-Score without adding new data
+    pub fn get_zscore(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let variance = self.get_variance()?;
+        let current = *self.prices.back()?;
+
+        // If variance is essentially zero, return 0 (price at mean)
+        if variance < 1e-10 {
+            return Some(0.0);
+        }
+
+        Some((current - self.offset - self.mean) / variance.sqrt())
+    }
+
+    /// Get current rolling mean (None if fewer than 2 prices)
+    pub fn get_mean(&self) -> Option<f64> {
+        if self.prices.len() >= 2 {
+            Some(self.offset + self.mean)
+        } else {
+            None
+        }
+    }
+
+    /// Get current rolling standard deviation
+    pub fn get_std(&self) -> Option<f64> {
+        self.get_variance().map(f64::sqrt)
+    }
+
+    /// Get current rolling sample variance: M2 / (n-1)
+    pub fn get_variance(&self) -> Option<f64> {
+        let n = self.prices.len();
+        if n >= 2 {
+            // Rounding can leave M2 a hair below zero for flat windows
+            Some((self.m2 / (n - 1) as f64).max(0.0))
+        } else {
+            None
+        }
+    }
+
+    /// Reset the engine, clearing all data
+    pub fn reset(&mut self) {
+        self.prices.clear();
+        self.offset = 0.0;
+        self.mean = 0.0;
+        self.m2 = 0.0;
+        self.evictions = 0;
+    }
+
+    /// Check if engine has enough data for valid Z-Score
+    pub fn is_ready(&self) -> bool {
+        self.prices.len() >= self.lookback
+    }
+
+    /// Get number of prices currently in the window
+    pub fn count(&self) -> usize {
+        self.prices.len()
+    }
+
+    /// Get the lookback period
+    pub fn lookback(&self) -> usize {
+        self.lookback
+    }
+}
+
+impl WelfordZScoreEngine {
+    /// Re-center the offset and recompute mean and M2 with a two-pass sum
+    fn rebuild(&mut self) {
+        let n = self.prices.len() as f64;
+        self.offset = self.prices.iter().sum::<f64>() / n;
+        self.mean = self.prices.iter().map(|p| p - self.offset).sum::<f64>() / n;
+        self.m2 = self
+            .prices
+            .iter()
+            .map(|p| (p - self.offset - self.mean).powi(2))
+            .sum();
+        self.evictions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zscore::ZScoreEngine;
+
+    #[test]
+    fn test_welford_basic() {
+        let mut engine = WelfordZScoreEngine::new(5).unwrap();
+        for p in [1.0, 2.0, 3.0, 4.0] {
+            assert_eq!(engine.update(p), None);
+        }
+        engine.update(5.0).unwrap();
+        assert!((engine.get_mean().unwrap() - 3.0).abs() < 1e-12);
+        assert!((engine.get_variance().unwrap() - 2.5).abs() < 1e-12);
+
+        // Slide: window is 2..6
+        engine.update(6.0).unwrap();
+        assert!((engine.get_mean().unwrap() - 4.0).abs() < 1e-12);
+        assert!((engine.get_variance().unwrap() - 2.5).abs() < 1e-12);
+
+        engine.reset();
+        assert!(!engine.is_ready());
+        assert_eq!(engine.get_mean(), None);
+
+        assert!(WelfordZScoreEngine::new(1).is_err());
+    }
+
+    #[test]
+    fn test_welford_matches_shifted_engine_at_large_offset() {
+        let mut welford = WelfordZScoreEngine::new(50).unwrap();
+        let mut shifted = ZScoreEngine::new(50).unwrap();
+
+        // Stop mid-way between Welford's periodic rebuilds
+        let mut state: u64 = 42;
+        for i in 0..100_037 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let noise = (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5;
+            let price = 1e9 + (i as f64 * 0.01).sin() * 5.0 + noise;
+
+            let zw = welford.update(price);
+            let zs = shifted.update(price).unwrap();
+            assert_eq!(zw.is_some(), zs.is_some());
+        }
+
+        let (mw, ms) = (welford.get_mean().unwrap(), shifted.get_mean().unwrap());
+        let (sw, ss) = (welford.get_std().unwrap(), shifted.get_std().unwrap());
+        // Absolute: a relative tolerance on a mean near 1e9 would hide
+        // errors as large as the price swings themselves
+        assert!((mw - ms).abs() < 1e-6, "Means {} vs {}", mw, ms);
+        assert!((sw - ss).abs() / ss < 1e-9, "Stds {} vs {}", sw, ss);
+        assert!((welford.get_zscore().unwrap() - shifted.get_zscore().unwrap()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_welford_flat_window() {
+        let mut engine = WelfordZScoreEngine::new(3).unwrap();
+        for _ in 0..10 {
+            engine.update(100.0);
+        }
+        assert_eq!(engine.get_zscore(), Some(0.0));
+        assert!(engine.get_std().unwrap() < 1e-6);
+    }
+}
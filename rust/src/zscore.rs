@@ -7,8 +7,86 @@
 //! calculations around a reference value K (typically the first price),
 //! which dramatically improves numerical stability for large price values.
 
+use numpy::PyArray1;
 use pyo3::prelude::*;
+use pyo3::types::PyIterator;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::error::Error;
+use crate::rolling_stats::RollingStats;
+
+/// Default variance below which the Z-Score is reported as 0
+const DEFAULT_VARIANCE_FLOOR: f64 = 1e-10;
+
+/// Decay weight scale at which the window's weights are renormalized
+const DECAY_RESCALE_LIMIT: f64 = 1e100;
+
+/// How raw inputs to `ZScoreEngine::update` are turned into window values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputMode {
+    /// Feed prices directly
+    Price,
+    /// Feed log returns `ln(price / prev_price)`
+    LogReturn,
+    /// Feed absolute log returns `|ln(price / prev_price)|`, so the
+    /// Z-Score flags volatility spikes
+    AbsLogReturn,
+}
+
+impl std::str::FromStr for InputMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "price" => Ok(InputMode::Price),
+            "log_return" => Ok(InputMode::LogReturn),
+            "abs_log_return" => Ok(InputMode::AbsLogReturn),
+            _ => Err(Error::InvalidArgument(format!(
+                "Unknown input_mode '{}', expected 'price', 'log_return' or 'abs_log_return'",
+                s
+            ))),
+        }
+    }
+}
+
+impl InputMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InputMode::Price => "price",
+            InputMode::LogReturn => "log_return",
+            InputMode::AbsLogReturn => "abs_log_return",
+        }
+    }
+
+    /// Whether window values are returns, seeded by a first price
+    fn is_return(&self) -> bool {
+        *self != InputMode::Price
+    }
+}
+
+/// Mean-reversion position state produced by `ZScoreEngine::signal_hysteresis`
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Signal {
+    /// No position
+    Flat,
+    /// Long after the Z-Score fell to `-entry`
+    LongEntry,
+    /// Short after the Z-Score rose to `entry`
+    ShortEntry,
+}
+
+/// Threshold crossing reported by `ZScoreEngine::crossed`
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cross {
+    /// The Z-Score rose to `+threshold` or above this bar
+    Up,
+    /// The Z-Score fell to `-threshold` or below this bar
+    Down,
+}
 
 /// Z-Score calculation engine using numerically stable rolling window statistics
 ///
@@ -33,331 +111,3461 @@ use std::collections::VecDeque;
 ///         print("Overbought signal!")
 /// ```
 #[pyclass]
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)] // K/Ex/Ex2 follow the shifted-data algorithm's notation
 pub struct ZScoreEngine {
-    prices: VecDeque<f64>,
+    stats: RollingStats, // Window values with shifted-data sums
     lookback: usize,
-    K: f64,   // Reference value for shifting (first price)
-    n: f64,   // Current count of prices
-    Ex: f64,  // Sum of (x - K)
-    Ex2: f64, // Sum of (x - K)²
+    variance: Option<f64>, // Cached rolling variance, refreshed on update
+    seq: usize, // Total prices pushed (index of the next price)
+    max_deque: VecDeque<(usize, f64)>, // Monotonic (index, price), decreasing
+    min_deque: VecDeque<(usize, f64)>, // Monotonic (index, price), increasing
+    input_mode: InputMode,
+    prev_price: Option<f64>, // Last (smoothed) price (return modes only)
+    history: Option<VecDeque<f64>>, // Recent Z-Scores (opt-in)
+    history_len: usize,
+    window_ns: Option<i64>, // Time-window span (None = count window)
+    timestamps: VecDeque<i64>, // Timestamp of each window value (time window only)
+    last_timestamp: Option<i64>,
+    last_evicted: Option<f64>, // Value popped by the most recent update
+    variance_floor: f64, // Variance at or below which the window counts as flat
+    smoothing_alpha: Option<f64>, // EWMA weight applied to raw prices (None = off)
+    smoothed: Option<f64>, // Last EWMA-smoothed price
+    require_positive: bool, // Reject prices <= 0 as bad ticks
+    tick_size: Option<f64>, // Round raw prices to this grid (None = off)
+    exclude_current: bool, // Score the newest value against the prior values only
+    decay: Option<f64>, // Per-bar age decay of window weights (None = off)
+    decay_weight: f64,  // Scale of the next pushed weight, 1/decay per bar
+    #[serde(skip)]
+    timings: Option<VecDeque<u64>>, // Recent update durations in ns (opt-in)
+    #[serde(skip)]
+    timings_len: usize,
+    z_count: usize, // Z-Scores produced since the last reset
+    z_floors: Vec<(usize, f64)>,   // Suffix minima of Z (index, z), increasing
+    z_ceilings: Vec<(usize, f64)>, // Suffix maxima of Z (index, z), decreasing
+    signal_state: Signal, // State of signal_hysteresis
+    last_zscore: Option<f64>, // Z-Score from the most recent window update
+    prev_zscore: Option<f64>, // Z-Score from the update before it
+    baseline: Option<(f64, f64)>, // Fixed (mean, std) replacing the window
 }
 
-#[pymethods]
-impl ZScoreEngine {
-    /// Create a new Z-Score engine with specified lookback period
-    ///
-    /// # Arguments
-    /// * `lookback` - Number of bars for rolling calculation (e.g., 20)
-    #[new]
-    pub fn new(lookback: usize) -> Self {
-        assert!(lookback > 1, "Lookback must be > 1");
+#[pymethods]
+impl ZScoreEngine {
+    /// Create a new Z-Score engine with specified lookback period
+    ///
+    /// # Arguments
+    /// * `lookback` - Number of bars for rolling calculation (e.g., 20)
+    /// * `input_mode` - "price" (default), "log_return" to compute the
+    ///   Z-Score of `ln(price / prev_price)` instead of raw prices, or
+    ///   "abs_log_return" for `|ln(price / prev_price)|` (a volatility
+    ///   regime signal)
+    /// * `window_ns` - If set, the window spans this many nanoseconds
+    ///   instead of `lookback` bars and is fed with `update_at`;
+    ///   `lookback` is then the minimum number of values before the
+    ///   engine is ready
+    /// * `smoothing_alpha` - If set, raw prices are first smoothed with an
+    ///   EWMA `s = alpha * price + (1 - alpha) * s_prev`, in (0, 1], and
+    ///   the smoothed series feeds the window
+    /// * `require_positive` - If true, zero or negative prices are
+    ///   rejected with ValueError as bad ticks (leave off for spreads,
+    ///   which can legitimately go negative)
+    /// * `tick_size` - If set, each raw price is rounded to the nearest
+    ///   multiple of the tick (must be > 0) before anything else
+    /// * `exclude_current` - If true, each Z-Score compares the newest
+    ///   value with the mean/std of the `lookback` values before it; the
+    ///   window then holds `lookback + 1` values, so warmup takes one
+    ///   more bar
+    /// * `decay` - If set, the value `i` bars back in the window has
+    ///   weight `decay^i`, in (0, 1]; 1 is the equal-weight window
+    #[new]
+    #[pyo3(signature = (
+        lookback,
+        input_mode="price",
+        window_ns=None,
+        smoothing_alpha=None,
+        require_positive=false,
+        tick_size=None,
+        exclude_current=false,
+        decay=None
+    ))]
+    #[allow(clippy::too_many_arguments)] // Mirrors the Python keyword arguments
+    fn py_new(
+        lookback: usize,
+        input_mode: &str,
+        window_ns: Option<i64>,
+        smoothing_alpha: Option<f64>,
+        require_positive: bool,
+        tick_size: Option<f64>,
+        exclude_current: bool,
+        decay: Option<f64>,
+    ) -> Result<Self, Error> {
+        let mut engine = Self::with_input_mode(lookback, input_mode.parse()?)?
+            .with_require_positive(require_positive)
+            .with_exclude_current(exclude_current);
+        if let Some(window_ns) = window_ns {
+            engine = engine.with_time_window(window_ns)?;
+        }
+        if let Some(tick_size) = tick_size {
+            engine = engine.with_tick_size(tick_size)?;
+        }
+        if let Some(decay) = decay {
+            engine = engine.with_decay(decay)?;
+        }
+        match smoothing_alpha {
+            Some(alpha) => engine.with_smoothing(alpha),
+            None => Ok(engine),
+        }
+    }
+
+    /// Get an independent copy of the engine (e.g., to fork simulations)
+    #[pyo3(name = "clone")]
+    fn py_clone(&self) -> Self {
+        self.clone()
+    }
+
+    fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.clone()
+    }
+
+    /// Dump the full engine state as JSON (e.g., for incident logs)
+    ///
+    /// Covers the window, K, the shifted sums and all settings, so
+    /// `from_json` reproduces the engine exactly.
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self)
+            .map_err(|err| Error::InvalidArgument(format!("Cannot serialize state: {}", err)))
+    }
+
+    /// Rebuild an engine from a `to_json` dump
+    ///
+    /// Raises ValueError if the JSON is not a valid state dump or
+    /// describes an engine the constructor and setters would reject.
+    #[staticmethod]
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let engine: Self = serde_json::from_str(json)
+            .map_err(|err| Error::InvalidArgument(format!("Invalid state JSON: {}", err)))?;
+        engine.check_state()?;
+        Ok(engine)
+    }
+
+    /// Save the full engine state to a file in a compact binary format
+    ///
+    /// Same state as `to_json`. The file is written next to `path` and
+    /// renamed into place, so a crash mid-save leaves the previous
+    /// checkpoint intact. Raises OSError if the file cannot be written.
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let bytes = bincode::serialize(self)
+            .map_err(|err| Error::InvalidArgument(format!("Cannot serialize state: {}", err)))?;
+
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, bytes)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|err| Error::Io(format!("Cannot save state to '{}': {}", path, err)))
+    }
+
+    /// Rebuild an engine from a file written by `save`
+    ///
+    /// Raises OSError if the file cannot be read and ValueError if it is
+    /// not a valid state file or describes an invalid engine.
+    #[staticmethod]
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)
+            .map_err(|err| Error::Io(format!("Cannot load state from '{}': {}", path, err)))?;
+        let engine: Self = bincode::deserialize(&bytes)
+            .map_err(|err| Error::InvalidArgument(format!("Invalid state file: {}", err)))?;
+        engine.check_state()?;
+        Ok(engine)
+    }
+
+    /// Update with new price and return current Z-Score
+    ///
+    /// Returns None if insufficient data (warming up period). In the
+    /// return modes the first price only seeds the previous price, and
+    /// non-positive prices raise ValueError.
+    ///
+    /// # Arguments
+    /// * `price` - New price to add to the rolling window
+    pub fn update(&mut self, price: f64) -> Result<Option<f64>, Error> {
+        self.timed(|engine| engine.update_count_window(price, 1.0))
+    }
+
+    /// Update with a price carrying a weight (e.g., bar volume)
+    ///
+    /// Mean, std and Z-Score become weighted by the bars in the window,
+    /// turning the engine into a VWAP-deviation signal. The window still
+    /// holds `lookback` bars; a plain `update` counts as weight 1.
+    /// Order statistics (min/max, percentiles) stay unweighted.
+    ///
+    /// # Arguments
+    /// * `price` - New price to add to the rolling window
+    /// * `weight` - Bar weight such as traded volume (must be > 0)
+    pub fn update_weighted(&mut self, price: f64, weight: f64) -> Result<Option<f64>, Error> {
+        if !weight.is_finite() || weight <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Weight must be > 0, got {}",
+                weight
+            )));
+        }
+        self.timed(|engine| engine.update_count_window(price, weight))
+    }
+
+    /// Update with new price and return (zscore, mean, std)
+    ///
+    /// All three come from the same post-update state, so logged
+    /// components always match the signal. With `exclude_current` the
+    /// mean and std are those of the prior window the Z-Score is scored
+    /// against. Returns None during warmup.
+    ///
+    /// # Arguments
+    /// * `price` - New price to add to the rolling window
+    pub fn update_full(&mut self, price: f64) -> Result<Option<(f64, f64, f64)>, Error> {
+        Ok(self.update(price)?.and_then(|z| {
+            let (mean, std) = self.baseline.or_else(|| self.scoring_mean_std())?;
+            Some((z, mean, std))
+        }))
+    }
+
+    /// Update with new price and return the mean-reversion signal state
+    ///
+    /// Hysteresis against whipsaw: from `Flat`, a Z-Score `<= -entry`
+    /// enters `LongEntry` and `>= entry` enters `ShortEntry`. The state
+    /// is then held until the Z-Score crosses the exit band (`>= exit`
+    /// for a long, `<= -exit` for a short), so chatter around the entry
+    /// threshold cannot re-trigger entries. A bar that exits and is past
+    /// the opposite entry flips directly. The state is kept during warmup.
+    ///
+    /// # Arguments
+    /// * `price` - New price to add to the rolling window
+    /// * `entry` - Entry threshold in standard deviations (> 0)
+    /// * `exit` - Exit level, with `-entry < exit < entry` (e.g., 0 exits
+    ///   at the mean)
+    pub fn signal_hysteresis(&mut self, price: f64, entry: f64, exit: f64) -> Result<Signal, Error> {
+        if entry.is_nan() || entry <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Entry threshold must be > 0, got {}",
+                entry
+            )));
+        }
+        if exit.is_nan() || exit <= -entry || exit >= entry {
+            return Err(Error::InvalidArgument(format!(
+                "Exit level must be inside (-{}, {}), got {}",
+                entry, entry, exit
+            )));
+        }
+
+        let z = match self.update(price)? {
+            Some(z) => z,
+            None => return Ok(self.signal_state),
+        };
+
+        let exited = match self.signal_state {
+            Signal::LongEntry => z >= exit,
+            Signal::ShortEntry => z <= -exit,
+            Signal::Flat => true,
+        };
+        if exited {
+            self.signal_state = if z <= -entry {
+                Signal::LongEntry
+            } else if z >= entry {
+                Signal::ShortEntry
+            } else {
+                Signal::Flat
+            };
+        }
+        Ok(self.signal_state)
+    }
+
+    /// Get the state of `signal_hysteresis` without adding new data
+    pub fn current_signal_state(&self) -> Signal {
+        self.signal_state
+    }
+
+    /// Check whether the latest update crossed the `±threshold` band
+    ///
+    /// `Up` when the Z-Score went from below `threshold` to at or above
+    /// it, `Down` when it went from above `-threshold` to at or below
+    /// it, None otherwise. Needs a Z-Score on both this and the previous
+    /// update, so the first ready bar never reports a cross.
+    ///
+    /// # Arguments
+    /// * `threshold` - Band half-width in standard deviations (>= 0)
+    pub fn crossed(&self, threshold: f64) -> Result<Option<Cross>, Error> {
+        if !threshold.is_finite() || threshold < 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Threshold must be >= 0, got {}",
+                threshold
+            )));
+        }
+
+        let (prev, z) = match (self.prev_zscore, self.last_zscore) {
+            (Some(prev), Some(z)) => (prev, z),
+            _ => return Ok(None),
+        };
+        Ok(if prev < threshold && z >= threshold {
+            Some(Cross::Up)
+        } else if prev > -threshold && z <= -threshold {
+            Some(Cross::Down)
+        } else {
+            None
+        })
+    }
+
+    /// Update a time-window engine with a timestamped price
+    ///
+    /// Values older than `timestamp_ns - window_ns` are expired, so the
+    /// statistics cover a true rolling time window. Timestamps must be
+    /// non-decreasing; an earlier one raises ValueError.
+    ///
+    /// # Arguments
+    /// * `price` - New price to add to the rolling window
+    /// * `timestamp_ns` - Time of the price in nanoseconds
+    pub fn update_at(&mut self, price: f64, timestamp_ns: i64) -> Result<Option<f64>, Error> {
+        self.timed(|engine| engine.update_time_window(price, timestamp_ns))
+    }
+
+    /// Start recording how long each update takes, keeping the most
+    /// recent `max_samples` durations
+    ///
+    /// Covers `update`, `update_weighted` and `update_at` (and the batch
+    /// and stream methods built on `update`). Off by default: a disabled
+    /// engine only checks one flag per update. Any existing samples are
+    /// discarded. Timings are not part of `to_json` state.
+    pub fn enable_timing(&mut self, max_samples: usize) -> Result<(), Error> {
+        if max_samples == 0 {
+            return Err(Error::InvalidArgument("Timing sample count must be > 0".to_string()));
+        }
+
+        self.timings = Some(VecDeque::with_capacity(max_samples));
+        self.timings_len = max_samples;
+        Ok(())
+    }
+
+    /// Stop recording update durations and free the samples
+    pub fn disable_timing(&mut self) {
+        self.timings = None;
+        self.timings_len = 0;
+    }
+
+    /// Get the duration of the most recent update in nanoseconds
+    ///
+    /// None if timing is disabled or nothing has been recorded yet.
+    pub fn last_update_ns(&self) -> Option<u64> {
+        self.timings.as_ref()?.back().copied()
+    }
+
+    /// Get (p50, p90, p99, max) of the recorded update durations in
+    /// nanoseconds
+    ///
+    /// Nearest-rank percentiles over the retained samples. None if timing
+    /// is disabled or nothing has been recorded yet.
+    pub fn update_timing_percentiles(&self) -> Option<(u64, u64, u64, u64)> {
+        let timings = self.timings.as_ref()?;
+        if timings.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<u64> = timings.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = |p: f64| {
+            let idx = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[idx.clamp(1, sorted.len()) - 1]
+        };
+        Some((rank(50.0), rank(90.0), rank(99.0), sorted[sorted.len() - 1]))
+    }
+
+    /// Get the value pushed out of the window by the most recent update
+    ///
+    /// None if that update did not evict anything (window not yet full).
+    /// In a time window that expires several values at once, this is the
+    /// last one expired. Values are window values, i.e. returns in
+    /// "log_return" mode.
+    pub fn last_evicted(&self) -> Option<f64> {
+        self.last_evicted
+    }
+
+    /// Get the time-window span in nanoseconds (None for a bar-count window)
+    pub fn window_ns(&self) -> Option<i64> {
+        self.window_ns
+    }
+
+    /// Get current Z-Score without adding new data
+    pub fn get_zscore(&self) -> Option<f64> {
+        if let Some(&current) = self.stats.values().back() {
+            self.calculate_zscore(current)
+        } else {
+            None
+        }
+    }
+
+    /// Get the Z-Score a hypothetical price would have against the
+    /// current window, without adding it
+    ///
+    /// Uses the current mean and std: `(price - mean) / std`. Returns
+    /// None during warmup and 0 when the window is flat.
+    ///
+    /// # Arguments
+    /// * `hypothetical_price` - Price to evaluate (e.g., a planned limit)
+    pub fn zscore_of(&self, hypothetical_price: f64) -> Option<f64> {
+        self.calculate_zscore(hypothetical_price)
+    }
+
+    /// Get current rolling mean
+    ///
+    /// Uses shifted data formula: mean = K + Ex/n
+    /// where n is the window size
+    pub fn get_mean(&self) -> Option<f64> {
+        if self.stats.len() >= 2 {
+            self.stats.mean()
+        } else {
+            None
+        }
+    }
+
+    /// Get current rolling standard deviation
+    ///
+    /// Square root of the cached rolling variance (see `get_variance`).
+    pub fn get_std(&self) -> Option<f64> {
+        self.variance.map(f64::sqrt)
+    }
+
+    /// Set the variance at or below which the window counts as flat
+    ///
+    /// Flat windows report a Z-Score of 0 instead of dividing by a
+    /// near-zero std. The default of 1e-10 can swallow real signal on
+    /// tiny-tick instruments (e.g., FX quoted to 0.00001); lower it there.
+    /// The half-life estimates use the same floor for the lagged values
+    /// of their AR(1) fits.
+    ///
+    /// # Arguments
+    /// * `floor` - Variance threshold (>= 0)
+    pub fn set_variance_floor(&mut self, floor: f64) -> Result<(), Error> {
+        Self::check_variance_floor(floor)?;
+        self.variance_floor = floor;
+        self.variance = self.compute_variance();
+        Ok(())
+    }
+
+    /// Get the variance floor
+    pub fn get_variance_floor(&self) -> f64 {
+        self.variance_floor
+    }
+
+    /// Score updates against a fixed baseline instead of the window
+    ///
+    /// While set, updates return `(value - mean) / std` (values are
+    /// returns in "log_return" mode) and leave the rolling window, its
+    /// statistics and the Z-Score history untouched, so the getters keep
+    /// describing the last rolling state. `crossed` follows the returned
+    /// Z-Scores. Kept across `reset`.
+    ///
+    /// # Arguments
+    /// * `mean` - Reference mean (e.g., the session-open price)
+    /// * `std` - Reference standard deviation (> 0)
+    pub fn set_baseline(&mut self, mean: f64, std: f64) -> Result<(), Error> {
+        Self::check_baseline(mean, std)?;
+        self.baseline = Some((mean, std));
+        Ok(())
+    }
+
+    /// Return to scoring against the rolling window
+    pub fn clear_baseline(&mut self) {
+        self.baseline = None;
+    }
+
+    /// Get the baseline as (mean, std) (None in rolling mode)
+    pub fn get_baseline(&self) -> Option<(f64, f64)> {
+        self.baseline
+    }
+
+    /// Get current rolling variance
+    ///
+    /// Uses shifted data formula for variance:
+    /// variance = (Ex2 - Ex²/n) / (n-1)
+    ///
+    /// This is numerically stable because we work with small
+    /// values (differences from K) instead of large raw prices.
+    /// The value is computed once per update and cached, so repeated
+    /// queries between updates don't redo the math.
+    pub fn get_variance(&self) -> Option<f64> {
+        self.variance
+    }
+
+    /// Get rolling variance with an explicit delta degrees of freedom
+    ///
+    /// `ddof=1` is the sample variance (same as `get_variance`), `ddof=0`
+    /// the population variance. None if fewer than 2 values. For weighted
+    /// windows (`update_weighted`, `decay`) the divisors are the total
+    /// weight `W` and its reliability-weights correction `W - Σw²/W`.
+    ///
+    /// # Arguments
+    /// * `ddof` - 0 or 1; with unit weights the divisor is `n - ddof`
+    pub fn get_variance_with(&self, ddof: usize) -> Result<Option<f64>, Error> {
+        if ddof > 1 {
+            return Err(Error::InvalidArgument(format!(
+                "ddof must be 0 or 1, got {}",
+                ddof
+            )));
+        }
+
+        Ok(match ddof {
+            0 => self.stats.population_variance(),
+            _ => self.variance,
+        })
+    }
+
+    /// Get rolling standard deviation with an explicit ddof (0 or 1)
+    ///
+    /// See `get_variance_with`.
+    pub fn get_std_with(&self, ddof: usize) -> Result<Option<f64>, Error> {
+        Ok(self.get_variance_with(ddof)?.map(f64::sqrt))
+    }
+
+    /// Get the annualized Sharpe ratio of the window
+    ///
+    /// Treats the window values as per-period returns:
+    /// `(mean - risk_free) / std * sqrt(periods_per_year)`. Only
+    /// meaningful when the engine is fed returns (e.g., the `log_return`
+    /// input mode); on raw prices the result is not a Sharpe ratio.
+    ///
+    /// Returns None during warmup or when std is ~0.
+    ///
+    /// # Arguments
+    /// * `risk_free` - Risk-free return per period (same units as inputs)
+    /// * `periods_per_year` - Periods per year (e.g., 252 for daily bars)
+    pub fn get_sharpe(&self, risk_free: f64, periods_per_year: f64) -> Result<Option<f64>, Error> {
+        if !periods_per_year.is_finite() || periods_per_year <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "periods_per_year must be > 0, got {}",
+                periods_per_year
+            )));
+        }
+
+        if !self.is_ready() {
+            return Ok(None);
+        }
+
+        Ok(self.get_mean().zip(self.get_std()).and_then(|(mean, std)| {
+            if std * std <= self.variance_floor {
+                None
+            } else {
+                Some((mean - risk_free) / std * periods_per_year.sqrt())
+            }
+        }))
+    }
+
+    /// Get the annualized realized volatility of the window
+    ///
+    /// `std * sqrt(periods_per_year)`, treating the window values as
+    /// per-period returns. On raw prices this is not a volatility; use
+    /// the `log_return` input mode.
+    ///
+    /// Returns None during warmup.
+    ///
+    /// # Arguments
+    /// * `periods_per_year` - Periods per year (e.g., 252 for daily bars)
+    pub fn get_volatility(&self, periods_per_year: f64) -> Result<Option<f64>, Error> {
+        if !periods_per_year.is_finite() || periods_per_year <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "periods_per_year must be > 0, got {}",
+                periods_per_year
+            )));
+        }
+
+        if !self.is_ready() {
+            return Ok(None);
+        }
+
+        Ok(self.get_std().map(|std| std * periods_per_year.sqrt()))
+    }
+
+    /// Get sensitivity of the Z-Score to price (dz/dprice = 1/std)
+    ///
+    /// This is the marginal Z-Score change per unit of price, i.e. how
+    /// "twitchy" the current signal is.
+    ///
+    /// Returns None during warmup or when std is essentially zero
+    pub fn zscore_sensitivity(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let std = self.get_std()?;
+        if std * std <= self.variance_floor {
+            return None;
+        }
+
+        Some(1.0 / std)
+    }
+
+    /// Get Bollinger-style bands as (lower, mean, upper)
+    ///
+    /// Bands sit `num_std` standard deviations either side of the rolling
+    /// mean. Returns None during warmup.
+    ///
+    /// # Arguments
+    /// * `num_std` - Band width in standard deviations (must be > 0)
+    pub fn bands(&self, num_std: f64) -> Result<Option<(f64, f64, f64)>, Error> {
+        if !num_std.is_finite() || num_std <= 0.0 {
+            return Err(Error::InvalidArgument("num_std must be > 0".to_string()));
+        }
+
+        if !self.is_ready() {
+            return Ok(None);
+        }
+
+        Ok(self.get_mean().zip(self.get_std()).map(|(mean, std)| {
+            (mean - num_std * std, mean, mean + num_std * std)
+        }))
+    }
+
+    /// Get the projected next-bar range as (low, high) around the mean
+    ///
+    /// Same levels as the outer `bands`, framed as a forward range for
+    /// placing conditional orders. Returns None during warmup.
+    ///
+    /// # Arguments
+    /// * `num_std` - Range half-width in standard deviations (must be > 0)
+    pub fn expected_range(&self, num_std: f64) -> Result<Option<(f64, f64)>, Error> {
+        Ok(self.bands(num_std)?.map(|(lower, _, upper)| (lower, upper)))
+    }
+
+    /// Get the projected next-bar range as (low, high) around the latest price
+    ///
+    /// Like `expected_range`, but centered on where the market is now
+    /// rather than where it has averaged. Returns None during warmup.
+    ///
+    /// # Arguments
+    /// * `num_std` - Range half-width in standard deviations (must be > 0)
+    pub fn expected_range_from_price(&self, num_std: f64) -> Result<Option<(f64, f64)>, Error> {
+        Ok(self.bands(num_std)?.and_then(|(lower, mean, _)| {
+            let half_width = mean - lower;
+            self.stats
+                .values()
+                .back()
+                .map(|&price| (price - half_width, price + half_width))
+        }))
+    }
+
+    /// Get %B: where `price` sits within the bands (0 = lower, 1 = upper)
+    ///
+    /// Returns None during warmup or when the bands have zero width.
+    ///
+    /// # Arguments
+    /// * `price` - Price to locate within the bands
+    /// * `num_std` - Band width in standard deviations (default 2.0)
+    #[pyo3(signature = (price, num_std=2.0))]
+    pub fn percent_b(&self, price: f64, num_std: f64) -> Result<Option<f64>, Error> {
+        Ok(self.bands(num_std)?.and_then(|(lower, _, upper)| {
+            let width = upper - lower;
+            if width > 0.0 {
+                Some((price - lower) / width)
+            } else {
+                None
+            }
+        }))
+    }
+
+    /// Estimate the half-life of mean reversion from an AR(1) fit with
+    /// intercept, `x[t] = a + b * x[t-1]`
+    ///
+    /// Unlike `reversion_half_life`, the fit comes from sums maintained
+    /// incrementally with the window (including the lagged co-moment), so
+    /// each call is O(1). Returns `-ln(2) / ln(b)` in bars when
+    /// `0 < b < 1`, else None (also during warmup).
+    pub fn mean_reversion_halflife(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let (_, b) = self.stats.ar1(self.variance_floor)?;
+        if b > 0.0 && b < 1.0 {
+            Some(-std::f64::consts::LN_2 / b.ln())
+        } else {
+            None
+        }
+    }
+
+    /// Estimate the half-life of mean reversion over the window
+    ///
+    /// Fits an AR(1) model to the demeaned window,
+    /// `x[t] - mean = rho * (x[t-1] - mean)`, by least squares and returns
+    /// the Ornstein-Uhlenbeck half-life `-ln(2) / ln(rho)` in bars.
+    ///
+    /// Returns None during warmup or when `rho` is outside (0, 1), i.e.
+    /// the window is not mean-reverting.
+    pub fn reversion_half_life(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let mean = self.get_mean()?;
+        let mut cov = 0.0;
+        let mut var = 0.0;
+        let values = self.stats.values();
+        for (prev, curr) in values.iter().zip(values.iter().skip(1)) {
+            let dp = prev - mean;
+            cov += (curr - mean) * dp;
+            var += dp * dp;
+        }
+
+        if var / (values.len() - 1) as f64 <= self.variance_floor {
+            return None;
+        }
+
+        let rho = cov / var;
+        if rho > 0.0 && rho < 1.0 {
+            Some(-std::f64::consts::LN_2 / rho.ln())
+        } else {
+            None
+        }
+    }
+
+    /// Get a bias-corrected EWMA variance around the flat window mean
+    ///
+    /// With `d[i]` the deviation from the window mean of the price `i`
+    /// bars back (0 = latest) and `n` the window size:
+    ///
+    /// ```text
+    /// var = Σ alpha * (1 - alpha)^i * d[i]² / (1 - (1 - alpha)^n)
+    /// ```
+    ///
+    /// The denominator is the EWMA bias-correction factor: the weights
+    /// over a finite window sum to `1 - (1 - alpha)^n`, not 1.
+    ///
+    /// Returns None during warmup.
+    ///
+    /// # Arguments
+    /// * `alpha` - EWMA smoothing factor in (0, 1]
+    pub fn get_ewma_variance(&self, alpha: f64) -> Result<Option<f64>, Error> {
+        if alpha.is_nan() || alpha <= 0.0 || alpha > 1.0 {
+            return Err(Error::InvalidArgument(format!(
+                "alpha must be in (0, 1], got {}",
+                alpha
+            )));
+        }
+
+        if !self.is_ready() {
+            return Ok(None);
+        }
+
+        let mean = match self.get_mean() {
+            Some(mean) => mean,
+            None => return Ok(None),
+        };
+
+        let decay = 1.0 - alpha;
+        let mut weight = alpha;
+        let mut sum = 0.0;
+        for &x in self.stats.values().iter().rev() {
+            let d = x - mean;
+            sum += weight * d * d;
+            weight *= decay;
+        }
+
+        let correction = 1.0 - decay.powi(self.stats.len() as i32);
+        Ok(Some(sum / correction))
+    }
+
+    /// Get the Z-Score using the flat window mean and EWMA variance
+    ///
+    /// Hybrid estimator: the center is the stable equal-weight rolling
+    /// mean, the scale is `sqrt(get_ewma_variance(alpha))`, which adapts
+    /// faster to volatility changes.
+    ///
+    /// Returns None during warmup; 0 when the EWMA variance is ~0.
+    ///
+    /// # Arguments
+    /// * `alpha` - EWMA smoothing factor in (0, 1]
+    pub fn get_hybrid_ewma_zscore(&self, alpha: f64) -> Result<Option<f64>, Error> {
+        let variance = match self.get_ewma_variance(alpha)? {
+            Some(variance) => variance,
+            None => return Ok(None),
+        };
+
+        let (mean, current) = match (self.get_mean(), self.stats.values().back()) {
+            (Some(mean), Some(&current)) => (mean, current),
+            _ => return Ok(None),
+        };
+
+        if variance <= self.variance_floor {
+            return Ok(Some(0.0));
+        }
+
+        Ok(Some((current - mean) / variance.sqrt()))
+    }
+
+    /// Get rolling minimum of the window (None if empty)
+    ///
+    /// O(1): maintained incrementally with a monotonic deque.
+    pub fn get_min(&self) -> Option<f64> {
+        self.min_deque.front().map(|&(_, p)| p)
+    }
+
+    /// Get rolling maximum of the window (None if empty)
+    ///
+    /// O(1): maintained incrementally with a monotonic deque.
+    pub fn get_max(&self) -> Option<f64> {
+        self.max_deque.front().map(|&(_, p)| p)
+    }
+
+    /// Get the number of consecutive latest bars with `z >= threshold`
+    ///
+    /// 0 if the latest Z-Score is below the threshold (or during warmup).
+    /// Answered in O(log n) from a stack of Z-Score suffix minima kept on
+    /// each update, so any threshold can be queried without storing the
+    /// full Z-Score history. Raises ValueError for a non-finite threshold.
+    pub fn streak_above(&self, threshold: f64) -> Result<usize, Error> {
+        if !threshold.is_finite() {
+            return Err(Error::InvalidArgument(format!(
+                "Threshold must be finite, got {}",
+                threshold
+            )));
+        }
+        // Most recent bar with z < threshold is always a suffix minimum
+        let inside = self.z_floors.partition_point(|&(_, z)| z < threshold);
+        Ok(self.streak_since(inside.checked_sub(1).map(|i| self.z_floors[i].0)))
+    }
+
+    /// Get the number of consecutive latest bars with `z <= -threshold`
+    ///
+    /// 0 if the latest Z-Score is above `-threshold` (or during warmup).
+    /// Mirror image of `streak_above`, using Z-Score suffix maxima.
+    pub fn streak_below(&self, threshold: f64) -> Result<usize, Error> {
+        if !threshold.is_finite() {
+            return Err(Error::InvalidArgument(format!(
+                "Threshold must be finite, got {}",
+                threshold
+            )));
+        }
+        let inside = self.z_ceilings.partition_point(|&(_, z)| z > -threshold);
+        Ok(self.streak_since(inside.checked_sub(1).map(|i| self.z_ceilings[i].0)))
+    }
+
+    /// Get bars since the rolling minimum was set (0 = latest price)
+    ///
+    /// On ties the most recent occurrence counts. None if the window
+    /// holds no non-NaN prices.
+    pub fn get_min_age(&self) -> Option<usize> {
+        self.min_deque.front().map(|&(i, _)| self.seq - 1 - i)
+    }
+
+    /// Get bars since the rolling maximum was set (0 = latest price)
+    ///
+    /// On ties the most recent occurrence counts. None if the window
+    /// holds no non-NaN prices.
+    pub fn get_max_age(&self) -> Option<usize> {
+        self.max_deque.front().map(|&(i, _)| self.seq - 1 - i)
+    }
+
+    /// Get the fraction of distinct values in the window (0 if empty)
+    ///
+    /// A stalled feed that keeps repeating the last price drives this
+    /// towards `1 / n`.
+    pub fn fraction_unique(&self) -> f64 {
+        if self.stats.is_empty() {
+            return 0.0;
+        }
+        self.distinct_count() as f64 / self.stats.len() as f64
+    }
+
+    /// Check whether the window has fewer than `min_unique` distinct values
+    ///
+    /// Use to suppress signals on frozen data, where the Z-Score
+    /// collapses to 0 without meaning anything.
+    pub fn is_stale(&self, min_unique: usize) -> bool {
+        self.distinct_count() < min_unique
+    }
+
+    /// Get the fraction of window prices `<=` the latest price (0..1)
+    ///
+    /// Returns None during warmup.
+    pub fn percentile_rank(&self) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let current = *self.stats.values().back()?;
+        let at_or_below = self.stats.values().iter().filter(|&&p| p <= current).count();
+        Some(at_or_below as f64 / self.stats.len() as f64)
+    }
+
+    /// Get the q-quantile of the window, interpolating linearly between
+    /// the closest ranks
+    ///
+    /// Sorts a snapshot of the window, so costs O(n log n). Returns None
+    /// during warmup.
+    ///
+    /// # Arguments
+    /// * `q` - Quantile in [0, 1] (0.5 = median)
+    pub fn quantile(&self, q: f64) -> Result<Option<f64>, Error> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(Error::InvalidArgument(format!(
+                "q must be in [0, 1], got {}",
+                q
+            )));
+        }
+
+        if !self.is_ready() {
+            return Ok(None);
+        }
+
+        let mut sorted: Vec<f64> = self.stats.values().iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+
+        let pos = q * (sorted.len() - 1) as f64;
+        let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+        Ok(Some(sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)))
+    }
+
+    /// Reset the engine, clearing all data
+    pub fn reset(&mut self) {
+        self.stats.clear();
+        self.variance = None;
+        self.seq = 0;
+        self.max_deque.clear();
+        self.min_deque.clear();
+        self.prev_price = None;
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self.timestamps.clear();
+        self.last_timestamp = None;
+        self.last_evicted = None;
+        self.smoothed = None;
+        self.decay_weight = 1.0;
+        self.z_count = 0;
+        self.z_floors.clear();
+        self.z_ceilings.clear();
+        self.signal_state = Signal::Flat;
+        self.last_zscore = None;
+        self.prev_zscore = None;
+    }
+
+    /// Recompute the rolling statistics from the prices in the window
+    ///
+    /// Soft reset: K is re-centered on the oldest retained price and the
+    /// shifted sums are rebuilt with a full pass, discarding any
+    /// accumulated floating-point drift. The window itself is kept, so
+    /// no new warmup is needed and `get_mean`/`get_std` are unchanged to
+    /// within float precision.
+    pub fn resync(&mut self) {
+        if !self.stats.is_empty() {
+            self.stats.resync();
+            self.variance = self.compute_variance();
+        }
+    }
+
+    /// Check if engine has enough data to generate signals
+    pub fn is_ready(&self) -> bool {
+        self.stats.len() >= self.get_min_periods()
+    }
+
+    /// Check if the engine has some data but is not ready yet
+    ///
+    /// Distinguishes the three UI states: empty (neither this nor
+    /// `is_ready`), warming, and ready. A return-mode engine seeded with
+    /// its first price counts as warming.
+    pub fn is_warming(&self) -> bool {
+        !self.is_ready() && (!self.stats.is_empty() || self.prev_price.is_some())
+    }
+
+    /// Get the number of window values required before the engine is ready
+    ///
+    /// This is `lookback`, which for a time-window engine is the minimum
+    /// count rather than the window span, plus one with `exclude_current`.
+    pub fn get_min_periods(&self) -> usize {
+        self.lookback + usize::from(self.exclude_current)
+    }
+
+    /// Get the number of updates still needed before the engine is ready
+    ///
+    /// 0 once ready. In the return modes this includes the price that
+    /// seeds the first return.
+    pub fn bars_until_ready(&self) -> usize {
+        let seeding = self.input_mode.is_return() && self.prev_price.is_none();
+        self.get_min_periods().saturating_sub(self.stats.len()) + usize::from(seeding)
+    }
+
+    /// Get warmup progress as a fraction of the window filled (0..1)
+    ///
+    /// 1.0 once ready, for UIs showing a warmup bar.
+    pub fn warmup_progress(&self) -> f64 {
+        (self.stats.len() as f64 / self.get_min_periods() as f64).min(1.0)
+    }
+
+    /// Get number of prices currently in the window
+    pub fn count(&self) -> usize {
+        self.stats.len()
+    }
+
+    /// Get the lookback period
+    pub fn lookback(&self) -> usize {
+        self.lookback
+    }
+
+    /// Get all prices in the current window (for debugging)
+    pub fn get_prices(&self) -> Vec<f64> {
+        self.stats.values().iter().copied().collect()
+    }
+
+    /// Get each window price's deviation from the mean, oldest first
+    ///
+    /// Returns (price, price - mean, (price - mean)²) per window value,
+    /// showing which observations drive the variance when a Z-Score
+    /// spikes. Empty during warmup.
+    pub fn deviation_squares(&self) -> Vec<(f64, f64, f64)> {
+        let mean = match self.get_mean() {
+            Some(mean) if self.is_ready() => mean,
+            _ => return Vec::new(),
+        };
+
+        self.stats
+            .values()
+            .iter()
+            .map(|&price| {
+                let dev = price - mean;
+                (price, dev, dev * dev)
+            })
+            .collect()
+    }
+
+    /// Get all prices in the current window as a numpy array, oldest first
+    ///
+    /// Copies the window once into a buffer that numpy takes ownership
+    /// of, avoiding the extra list conversion of `get_prices`.
+    pub fn get_prices_numpy<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        PyArray1::from_vec(py, self.get_prices())
+    }
+
+    /// Get the oldest value in the window, next to expire (None if empty)
+    pub fn peek_oldest(&self) -> Option<f64> {
+        self.stats.values().front().copied()
+    }
+
+    /// Get the newest value in the window (None if empty)
+    pub fn peek_newest(&self) -> Option<f64> {
+        self.stats.values().back().copied()
+    }
+
+    /// Start recording the most recent `max_len` Z-Scores
+    ///
+    /// Any existing history is discarded.
+    pub fn enable_history(&mut self, max_len: usize) -> Result<(), Error> {
+        Self::check_history_len(max_len)?;
+
+        self.history = Some(VecDeque::with_capacity(max_len));
+        self.history_len = max_len;
+        Ok(())
+    }
+
+    /// Stop recording Z-Scores and free the history buffer
+    pub fn disable_history(&mut self) {
+        self.history = None;
+        self.history_len = 0;
+    }
+
+    /// Get recorded Z-Scores, oldest first (empty if history is disabled)
+    pub fn get_history(&self) -> Vec<f64> {
+        self.history
+            .as_ref()
+            .map(|h| h.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the autocorrelation of recorded Z-Scores at `lag`
+    ///
+    /// Uses the standard sample ACF over the history buffer. For a
+    /// well-specified model Z-Scores should look like white noise, so a
+    /// value far from 0 means the signal is predictable.
+    ///
+    /// Returns None if history is disabled, holds too few values for
+    /// `lag`, or has ~zero variance.
+    pub fn zscore_autocorr(&self, lag: usize) -> Option<f64> {
+        let history = self.history.as_ref()?;
+        let n = history.len();
+        if n < lag + 2 {
+            return None;
+        }
+
+        let mean = history.iter().sum::<f64>() / n as f64;
+        let denom: f64 = history.iter().map(|z| (z - mean) * (z - mean)).sum();
+        // Z-Scores are unitless, so the price-scale variance floor does
+        // not apply; a fixed epsilon only guards the division
+        if denom < 1e-10 {
+            return None;
+        }
+
+        let num: f64 = history
+            .iter()
+            .zip(history.iter().skip(lag))
+            .map(|(a, b)| (a - mean) * (b - mean))
+            .sum();
+
+        Some(num / denom)
+    }
+
+    /// Approximate heap + inline memory used by this engine in bytes
+    ///
+    /// Counts the struct itself plus the allocated capacity of the price
+    /// window and its weights, the min/max deques, the streak stacks, the
+    /// timestamps of a time window, the optional Z-Score history and the
+    /// optional update timings.
+    pub fn memory_bytes(&self) -> usize {
+        let f64_size = std::mem::size_of::<f64>();
+        let extreme_size = std::mem::size_of::<(usize, f64)>();
+
+        let history = self.history.as_ref().map(|h| h.capacity()).unwrap_or(0);
+        let timings = self.timings.as_ref().map(|t| t.capacity()).unwrap_or(0);
+        let extremes = self.max_deque.capacity()
+            + self.min_deque.capacity()
+            + self.z_floors.capacity()
+            + self.z_ceilings.capacity();
+
+        std::mem::size_of::<Self>()
+            + self.stats.heap_bytes()
+            + extremes * extreme_size
+            + history * f64_size
+            + self.timestamps.capacity() * std::mem::size_of::<i64>()
+            + timings * std::mem::size_of::<u64>()
+    }
+
+    /// Batch update with multiple prices, returns final Z-Score
+    ///
+    /// More efficient than calling update() in a loop from Python
+    pub fn update_batch(&mut self, prices: Vec<f64>) -> Result<Option<f64>, Error> {
+        let mut result = None;
+        for price in prices {
+            result = self.update(price)?;
+        }
+        Ok(result)
+    }
+
+    /// Lazily update with each price from an iterable, yielding Z-Scores
+    ///
+    /// Prices are pulled one at a time via the iterator protocol, so a
+    /// generator over a large tick file is processed in flat memory.
+    /// Each step is an `update` on this engine and yields the same
+    /// value (None during warmup); an invalid price raises from the
+    /// generator.
+    ///
+    /// # Example (Python)
+    /// ```python
+    /// prices = (float(line) for line in open("ticks.csv"))
+    /// for z in engine.stream(prices):
+    ///     ...
+    /// ```
+    pub fn stream(slf: Bound<'_, Self>, prices: &Bound<'_, PyAny>) -> PyResult<ZScoreStream> {
+        Ok(ZScoreStream {
+            engine: slf.unbind(),
+            prices: prices.try_iter()?.unbind(),
+        })
+    }
+
+    /// Update with new price and return the Z-Score, NaN during warmup
+    ///
+    /// Same as `update`, with NaN as the "no value" sentinel for numpy
+    /// pipelines.
+    pub fn update_nan(&mut self, price: f64) -> Result<f64, Error> {
+        Ok(self.update(price)?.unwrap_or(f64::NAN))
+    }
+
+    /// Batch update returning every Z-Score as a numpy array
+    ///
+    /// One value per input price, NaN during warmup. On an invalid price
+    /// the earlier prices stay applied, as with `update_batch`.
+    #[pyo3(name = "update_batch_all")]
+    fn py_update_batch_all<'py>(
+        &mut self,
+        py: Python<'py>,
+        prices: Vec<f64>,
+    ) -> Result<Bound<'py, PyArray1<f64>>, Error> {
+        Ok(PyArray1::from_vec(py, self.update_batch_all(&prices)?))
+    }
+
+    /// Get the EWMA smoothing weight (None if smoothing is off)
+    pub fn smoothing_alpha(&self) -> Option<f64> {
+        self.smoothing_alpha
+    }
+
+    /// Check whether non-positive prices are rejected
+    pub fn requires_positive(&self) -> bool {
+        self.require_positive
+    }
+
+    /// Get the per-bar age decay of window weights (None if off)
+    pub fn decay(&self) -> Option<f64> {
+        self.decay
+    }
+
+    /// Check whether Z-Scores exclude the newest value from the baseline
+    pub fn excludes_current(&self) -> bool {
+        self.exclude_current
+    }
+
+    /// Get the price rounding grid (None if prices are not rounded)
+    pub fn tick_size(&self) -> Option<f64> {
+        self.tick_size
+    }
+
+    /// Get the input mode ("price", "log_return" or "abs_log_return")
+    pub fn input_mode(&self) -> &'static str {
+        self.input_mode.as_str()
+    }
+}
+
+impl ZScoreEngine {
+    /// Create a new Z-Score engine with specified lookback period
+    ///
+    /// # Arguments
+    /// * `lookback` - Number of bars for rolling calculation (e.g., 20)
+    pub fn new(lookback: usize) -> Result<Self, Error> {
+        Self::check_lookback(lookback)?;
+
+        Ok(Self {
+            stats: RollingStats::with_capacity(lookback + 1),
+            lookback,
+            variance: None,
+            seq: 0,
+            max_deque: VecDeque::new(),
+            min_deque: VecDeque::new(),
+            input_mode: InputMode::Price,
+            prev_price: None,
+            history: None,
+            history_len: 0,
+            window_ns: None,
+            timestamps: VecDeque::new(),
+            last_timestamp: None,
+            last_evicted: None,
+            variance_floor: DEFAULT_VARIANCE_FLOOR,
+            smoothing_alpha: None,
+            smoothed: None,
+            require_positive: false,
+            tick_size: None,
+            exclude_current: false,
+            decay: None,
+            decay_weight: 1.0,
+            timings: None,
+            timings_len: 0,
+            z_count: 0,
+            z_floors: Vec::new(),
+            z_ceilings: Vec::new(),
+            signal_state: Signal::Flat,
+            last_zscore: None,
+            prev_zscore: None,
+            baseline: None,
+        })
+    }
+
+    /// Batch update returning one Z-Score per price, NaN during warmup
+    pub fn update_batch_all(&mut self, prices: &[f64]) -> Result<Vec<f64>, Error> {
+        prices.iter().map(|&price| self.update_nan(price)).collect()
+    }
+
+    /// Create a new Z-Score engine that transforms inputs per `input_mode`
+    pub fn with_input_mode(lookback: usize, input_mode: InputMode) -> Result<Self, Error> {
+        Ok(Self {
+            input_mode,
+            ..Self::new(lookback)?
+        })
+    }
+
+    /// Switch to a rolling time window spanning `window_ns` nanoseconds
+    ///
+    /// `lookback` becomes the minimum number of values before the engine
+    /// is ready.
+    pub fn with_time_window(self, window_ns: i64) -> Result<Self, Error> {
+        Self::check_window_ns(window_ns)?;
+
+        Ok(Self {
+            window_ns: Some(window_ns),
+            ..self
+        })
+    }
+
+    /// Smooth raw prices with an EWMA before they enter the window
+    ///
+    /// Each price becomes `alpha * price + (1 - alpha) * s_prev` (the
+    /// first price seeds the smoother), so single-tick noise is damped
+    /// before the rolling statistics see it.
+    pub fn with_smoothing(self, alpha: f64) -> Result<Self, Error> {
+        Self::check_smoothing_alpha(alpha)?;
+
+        Ok(Self {
+            smoothing_alpha: Some(alpha),
+            ..self
+        })
+    }
+
+    /// Reject zero or negative prices as bad ticks
+    ///
+    /// Off by default, since spreads can legitimately go negative.
+    /// Rejected prices leave the engine state untouched.
+    pub fn with_require_positive(self, require_positive: bool) -> Self {
+        Self {
+            require_positive,
+            ..self
+        }
+    }
+
+    /// Round each raw price to the nearest multiple of `tick_size`
+    ///
+    /// Off-grid prints (e.g., from averaged or synthetic feeds) are
+    /// snapped before validation, smoothing and the window, so
+    /// `get_prices` holds the rounded values.
+    pub fn with_tick_size(self, tick_size: f64) -> Result<Self, Error> {
+        Self::check_tick_size(tick_size)?;
+
+        Ok(Self {
+            tick_size: Some(tick_size),
+            ..self
+        })
+    }
+
+    /// Score each value against the prior `lookback` values only
+    ///
+    /// The mean and std behind the Z-Score leave out the value being
+    /// scored, so an outlier cannot damp its own Z-Score. The window
+    /// holds `lookback + 1` values, which delays readiness by one bar.
+    /// Mean, std and the other window statistics still cover the whole
+    /// window.
+    pub fn with_exclude_current(self, exclude_current: bool) -> Self {
+        Self {
+            exclude_current,
+            ..self
+        }
+    }
+
+    /// Weight window values by age, `decay^i` for the value `i` bars back
+    ///
+    /// The window still evicts by count, but mean, std and Z-Score
+    /// favour recent bars. Combines with `update_weighted`: a bar's
+    /// weight is its own weight times its age decay. `decay = 1` is the
+    /// equal-weight window.
+    pub fn with_decay(self, decay: f64) -> Result<Self, Error> {
+        Self::check_decay(decay)?;
+
+        Ok(Self {
+            decay: Some(decay),
+            ..self
+        })
+    }
+
+    /// Add a timestamped value to a time window
+    fn update_time_window(&mut self, price: f64, timestamp_ns: i64) -> Result<Option<f64>, Error> {
+        let window_ns = self.window_ns.ok_or_else(|| {
+            Error::InvalidArgument(
+                "Engine has no time window, construct it with window_ns".to_string(),
+            )
+        })?;
+
+        if let Some(last) = self.last_timestamp {
+            if timestamp_ns < last {
+                return Err(Error::InvalidArgument(format!(
+                    "Timestamps must be non-decreasing, got {} after {}",
+                    timestamp_ns, last
+                )));
+            }
+        }
+
+        self.last_evicted = None;
+        let value = self.transform_input(price)?;
+        self.last_timestamp = Some(timestamp_ns);
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if let Some(baseline) = self.baseline {
+            return Ok(Some(self.baseline_zscore(value, baseline)));
+        }
+
+        self.push_value(value, 1.0);
+        self.timestamps.push_back(timestamp_ns);
+
+        let cutoff = timestamp_ns.saturating_sub(window_ns);
+        while self.timestamps.front().is_some_and(|&t| t < cutoff) {
+            self.timestamps.pop_front();
+            self.evict_oldest();
+        }
+
+        Ok(self.finish_update(value))
+    }
+
+    /// Run an update, recording its duration when timing is enabled
+    fn timed<T>(&mut self, update: impl FnOnce(&mut Self) -> T) -> T {
+        if self.timings.is_none() {
+            return update(self);
+        }
+
+        let start = Instant::now();
+        let result = update(self);
+        let elapsed = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        if let Some(timings) = self.timings.as_mut() {
+            if timings.len() == self.timings_len {
+                timings.pop_front();
+            }
+            timings.push_back(elapsed);
+        }
+        result
+    }
+
+    /// Add a weighted value to a bar-count window
+    fn update_count_window(&mut self, price: f64, weight: f64) -> Result<Option<f64>, Error> {
+        if self.window_ns.is_some() {
+            return Err(Error::InvalidArgument(
+                "Engine uses a time window, feed it with update_at".to_string(),
+            ));
+        }
+
+        self.last_evicted = None;
+        let price = match self.transform_input(price)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if let Some(baseline) = self.baseline {
+            return Ok(Some(self.baseline_zscore(price, baseline)));
+        }
+
+        self.push_value(price, weight);
+
+        // Remove oldest price once the window is over capacity
+        if self.stats.len() > self.get_min_periods() {
+            self.evict_oldest();
+        }
+
+        Ok(self.finish_update(price))
+    }
+
+    /// Add a window value to the shifted sums and min/max deques
+    fn push_value(&mut self, price: f64, weight: f64) {
+        let weight = weight * self.next_decay_weight();
+        self.stats.push_weighted(price, weight);
+        self.push_extremes(price);
+    }
+
+    /// Age-decay scale for the value about to be pushed (1 without decay)
+    ///
+    /// Rather than shrinking every stored weight each bar, each new
+    /// value gets `1/decay` times the previous one's weight; only the
+    /// ratios matter. The weights are renormalized before they overflow.
+    fn next_decay_weight(&mut self) -> f64 {
+        let decay = match self.decay {
+            Some(decay) => decay,
+            None => return 1.0,
+        };
+        if self.decay_weight > DECAY_RESCALE_LIMIT {
+            self.stats.scale_weights(1.0 / self.decay_weight);
+            self.decay_weight = 1.0;
+        }
+        let weight = self.decay_weight;
+        self.decay_weight /= decay;
+        weight
+    }
+
+    /// Remove the oldest window value from the shifted sums
+    fn evict_oldest(&mut self) {
+        // K is re-centered inside RollingStats as the window slides
+        if let Some(old) = self.stats.pop() {
+            self.last_evicted = Some(old);
+            self.expire_extremes();
+        }
+    }
+
+    /// Refresh the cached variance and record the new Z-Score
+    fn finish_update(&mut self, price: f64) -> Option<f64> {
+        self.variance = self.compute_variance();
+
+        // Calculate Z-Score if we have enough data
+        let zscore = self.calculate_zscore(price);
+        self.track_last_zscore(zscore);
+        if let Some(z) = zscore {
+            self.push_streaks(z);
+        }
+        if let (Some(z), Some(history)) = (zscore, self.history.as_mut()) {
+            if history.len() == self.history_len {
+                history.pop_front();
+            }
+            history.push_back(z);
+        }
+        zscore
+    }
+
+    /// Score a value against a fixed (mean, std) baseline
+    ///
+    /// The window is left alone, but the Z-Score still feeds `crossed`.
+    fn baseline_zscore(&mut self, value: f64, (mean, std): (f64, f64)) -> f64 {
+        let z = (value - mean) / std;
+        self.track_last_zscore(Some(z));
+        z
+    }
+
+    /// Shift the Z-Score just returned into the pair `crossed` compares
+    fn track_last_zscore(&mut self, zscore: Option<f64>) {
+        self.prev_zscore = std::mem::replace(&mut self.last_zscore, zscore);
+    }
+
+    /// Push a new Z-Score onto the suffix minimum/maximum stacks
+    ///
+    /// Same idea as the min/max deques, without expiry: each stack keeps
+    /// only Z-Scores not dominated by a later one. A NaN Z-Score breaks
+    /// every streak.
+    fn push_streaks(&mut self, z: f64) {
+        let idx = self.z_count;
+        self.z_count += 1;
+
+        let floor = if z.is_nan() { f64::NEG_INFINITY } else { z };
+        while self.z_floors.last().is_some_and(|&(_, f)| f >= floor) {
+            self.z_floors.pop();
+        }
+        self.z_floors.push((idx, floor));
+
+        let ceiling = if z.is_nan() { f64::INFINITY } else { z };
+        while self.z_ceilings.last().is_some_and(|&(_, c)| c <= ceiling) {
+            self.z_ceilings.pop();
+        }
+        self.z_ceilings.push((idx, ceiling));
+    }
+
+    /// Bars after `last_inside` (the latest bar outside the streak), or
+    /// all Z-Scores so far if every one is in the streak
+    fn streak_since(&self, last_inside: Option<usize>) -> usize {
+        match last_inside {
+            Some(idx) => self.z_count - 1 - idx,
+            None => self.z_count,
+        }
+    }
+
+    /// Turn a raw input into the value fed to the window
+    ///
+    /// Returns None when the input only seeds state (first price in
+    /// log-return mode).
+    fn transform_input(&mut self, price: f64) -> Result<Option<f64>, Error> {
+        let price = match self.tick_size {
+            Some(tick) => (price / tick).round() * tick,
+            None => price,
+        };
+
+        if self.require_positive && (price <= 0.0 || price.is_nan()) {
+            return Err(Error::InvalidArgument(format!(
+                "Price must be positive, got {}",
+                price
+            )));
+        }
+
+        if self.input_mode.is_return() && (price <= 0.0 || price.is_nan()) {
+            return Err(Error::InvalidArgument(format!(
+                "Price must be positive in {} mode, got {}",
+                self.input_mode.as_str(),
+                price
+            )));
+        }
+
+        let price = match self.smoothing_alpha {
+            Some(alpha) => {
+                let smoothed = match self.smoothed {
+                    Some(prev) => alpha * price + (1.0 - alpha) * prev,
+                    None => price,
+                };
+                *self.smoothed.insert(smoothed)
+            }
+            None => price,
+        };
+
+        match self.input_mode {
+            InputMode::Price => Ok(Some(price)),
+            InputMode::LogReturn => {
+                Ok(self.prev_price.replace(price).map(|prev| (price / prev).ln()))
+            }
+            InputMode::AbsLogReturn => {
+                Ok(self.prev_price.replace(price).map(|prev| (price / prev).ln().abs()))
+            }
+        }
+    }
+
+    /// Push a new price onto the rolling min/max deques
+    ///
+    /// Each deque drops entries the new price dominates, so its front is
+    /// always the extreme of the window (amortized O(1)). Ties evict the
+    /// older entry so the extreme's age reflects its latest occurrence.
+    /// NaN prices still take a sequence slot but never become extremes.
+    fn push_extremes(&mut self, price: f64) {
+        let idx = self.seq;
+        self.seq += 1;
+
+        if price.is_nan() {
+            return;
+        }
+
+        while self.max_deque.back().is_some_and(|&(_, p)| p <= price) {
+            self.max_deque.pop_back();
+        }
+        self.max_deque.push_back((idx, price));
+
+        while self.min_deque.back().is_some_and(|&(_, p)| p >= price) {
+            self.min_deque.pop_back();
+        }
+        self.min_deque.push_back((idx, price));
+    }
+
+    fn check_lookback(lookback: usize) -> Result<(), Error> {
+        if lookback < 2 {
+            return Err(Error::InvalidArgument("Lookback must be > 1".to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_window_ns(window_ns: i64) -> Result<(), Error> {
+        if window_ns <= 0 {
+            return Err(Error::InvalidArgument(format!(
+                "window_ns must be > 0, got {}",
+                window_ns
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_smoothing_alpha(alpha: f64) -> Result<(), Error> {
+        if alpha.is_nan() || alpha <= 0.0 || alpha > 1.0 {
+            return Err(Error::InvalidArgument(format!(
+                "smoothing_alpha must be in (0, 1], got {}",
+                alpha
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_tick_size(tick_size: f64) -> Result<(), Error> {
+        if !tick_size.is_finite() || tick_size <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "tick_size must be > 0, got {}",
+                tick_size
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_decay(decay: f64) -> Result<(), Error> {
+        if decay.is_nan() || decay <= 0.0 || decay > 1.0 {
+            return Err(Error::InvalidArgument(format!(
+                "decay must be in (0, 1], got {}",
+                decay
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_variance_floor(floor: f64) -> Result<(), Error> {
+        if !floor.is_finite() || floor < 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Variance floor must be >= 0, got {}",
+                floor
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_baseline(mean: f64, std: f64) -> Result<(), Error> {
+        if !mean.is_finite() {
+            return Err(Error::InvalidArgument(format!(
+                "Baseline mean must be finite, got {}",
+                mean
+            )));
+        }
+        if !std.is_finite() || std <= 0.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Baseline std must be > 0, got {}",
+                std
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_history_len(max_len: usize) -> Result<(), Error> {
+        if max_len == 0 {
+            return Err(Error::InvalidArgument("History length must be > 0".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Validate a deserialized engine
+    ///
+    /// Applies the constructor and setter checks to the settings, then
+    /// checks that the window, extreme deques, streak stacks and history
+    /// agree with each other, so the index arithmetic cannot underflow.
+    fn check_state(&self) -> Result<(), Error> {
+        Self::check_lookback(self.lookback)?;
+        Self::check_variance_floor(self.variance_floor)?;
+        if let Some(window_ns) = self.window_ns {
+            Self::check_window_ns(window_ns)?;
+        }
+        if let Some(alpha) = self.smoothing_alpha {
+            Self::check_smoothing_alpha(alpha)?;
+        }
+        if let Some(tick_size) = self.tick_size {
+            Self::check_tick_size(tick_size)?;
+        }
+        if let Some(decay) = self.decay {
+            Self::check_decay(decay)?;
+        }
+        if let Some((mean, std)) = self.baseline {
+            Self::check_baseline(mean, std)?;
+        }
+        if let Some(history) = &self.history {
+            Self::check_history_len(self.history_len)?;
+            if history.len() > self.history_len {
+                return Err(Error::InvalidArgument(format!(
+                    "History holds {} Z-Scores, more than its length {}",
+                    history.len(),
+                    self.history_len
+                )));
+            }
+        }
+
+        let invalid = |what: &str| Err(Error::InvalidArgument(format!("Invalid state: {}", what)));
+        if !self.decay_weight.is_finite() || self.decay_weight <= 0.0 {
+            return invalid("decay weight must be > 0");
+        }
+        if !self.stats.is_consistent() {
+            return invalid("window weights must be finite and > 0");
+        }
+        if self.window_ns.is_some() {
+            if self.timestamps.len() != self.stats.len() {
+                return invalid("one timestamp per window value required");
+            }
+        } else if self.stats.len() > self.get_min_periods() {
+            return invalid("window longer than lookback");
+        }
+        if self.seq < self.stats.len() {
+            return invalid("sequence number behind the window");
+        }
+        let in_window = |&(i, _): &(usize, f64)| i < self.seq;
+        if !self.max_deque.iter().all(in_window) || !self.min_deque.iter().all(in_window) {
+            return invalid("min/max index beyond the sequence number");
+        }
+        let scored = |&(i, _): &(usize, f64)| i < self.z_count;
+        if !self.z_floors.iter().all(scored) || !self.z_ceilings.iter().all(scored) {
+            return invalid("streak index beyond the Z-Score count");
+        }
+        Ok(())
+    }
+
+    /// Drop min/max entries that have slid out of the window
+    fn expire_extremes(&mut self) {
+        let oldest = self.seq - self.stats.len();
+
+        while self.max_deque.front().is_some_and(|&(i, _)| i < oldest) {
+            self.max_deque.pop_front();
+        }
+        while self.min_deque.front().is_some_and(|&(i, _)| i < oldest) {
+            self.min_deque.pop_front();
+        }
+    }
+
+    /// Number of distinct values in the window
+    fn distinct_count(&self) -> usize {
+        let mut sorted: Vec<f64> = self.stats.values().iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        sorted.dedup();
+        sorted.len()
+    }
+
+    /// Shifted data variance over the current window (None if n < 2)
+    ///
+    /// When one weight dwarfs the rest (e.g., extreme decay) the sample
+    /// variance is undefined, so the window counts as flat at the
+    /// variance floor rather than losing its Z-Score.
+    fn compute_variance(&self) -> Option<f64> {
+        if self.stats.len() < 2 {
+            return None;
+        }
+        Some(self.stats.variance().unwrap_or(self.variance_floor))
+    }
+
+    /// Mean and variance of the window without its newest value, flat at
+    /// the variance floor like `compute_variance`
+    fn prior_mean_variance(&self) -> Option<(f64, f64)> {
+        if self.stats.len() < 3 {
+            return None;
+        }
+        self.stats
+            .prior_mean_variance()
+            .or_else(|| Some((self.stats.prior_mean()?, self.variance_floor)))
+    }
+
+    /// Mean and std the window Z-Score is scored against
+    fn scoring_mean_std(&self) -> Option<(f64, f64)> {
+        if self.exclude_current {
+            let (mean, variance) = self.prior_mean_variance()?;
+            Some((mean, variance.sqrt()))
+        } else {
+            self.get_mean().zip(self.get_std())
+        }
+    }
+
+    /// Internal Z-Score calculation using shifted data algorithm
+    fn calculate_zscore(&self, current_price: f64) -> Option<f64> {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let (mean, variance) = if self.exclude_current {
+            self.prior_mean_variance()?
+        } else {
+            (self.stats.mean()?, self.variance?)
+        };
+
+        // If variance is essentially zero, return 0 (price at mean)
+        if variance <= self.variance_floor {
+            return Some(0.0);
+        }
+
+        let std_dev = variance.sqrt();
+        Some((current_price - mean) / std_dev)
+    }
+}
+
+/// Generator of Z-Scores returned by `ZScoreEngine.stream`
+///
+/// Holds the engine and the source iterator; every `next()` feeds one
+/// price to the engine.
+#[pyclass]
+pub struct ZScoreStream {
+    engine: Py<ZScoreEngine>,
+    prices: Py<PyIterator>,
+}
+
+#[pymethods]
+impl ZScoreStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Option<f64>>> {
+        let price: f64 = match self.prices.bind(py).clone().next() {
+            Some(price) => price?.extract()?,
+            None => return Ok(None),
+        };
+        let zscore = self.engine.bind(py).try_borrow_mut()?.update(price)?;
+        Ok(Some(zscore))
+    }
+}
+
+/// Compute rolling Z-Scores over a whole price series in one call
+///
+/// Stateless alternative to feeding a `ZScoreEngine` for historical
+/// replays. Windows are processed in blocks of `lookback`: each block
+/// re-centers K on its first window's mean and seeds the shifted sums
+/// with a lane-split pass the compiler can vectorize, then slides the
+/// sums across the rest of the block. Results match the engine within
+/// float tolerance, including 0 for windows at or below the engine's
+/// default variance floor.
+///
+/// Returns one value per input price, with NaN during warmup.
+///
+/// # Example (Python)
+/// ```python
+/// from quant_scalper_rust import batch_zscore_static
+///
+/// zscores = batch_zscore_static(prices, 20)
+/// ```
+#[pyfunction]
+pub fn batch_zscore_static(prices: Vec<f64>, lookback: usize) -> Result<Vec<f64>, Error> {
+    if lookback < 2 {
+        return Err(Error::InvalidArgument("Lookback must be > 1".to_string()));
+    }
+
+    let len = prices.len();
+    let mut out = vec![f64::NAN; len];
+    let n = lookback as f64;
+
+    let mut block = 0;
+    while block + lookback <= len {
+        let first = &prices[block..block + lookback];
+        let k = first.iter().sum::<f64>() / n;
+        let (mut ex, mut ex2) = shifted_sums(first, k);
+
+        let last_start = (block + lookback - 1).min(len - lookback);
+        for start in block..=last_start {
+            let end = start + lookback - 1;
+            if start > block {
+                let added = prices[end] - k;
+                let removed = prices[start - 1] - k;
+                ex += added - removed;
+                ex2 += added * added - removed * removed;
+            }
+
+            let variance = (ex2 - (ex * ex) / n) / (n - 1.0);
+            out[end] = if variance <= DEFAULT_VARIANCE_FLOOR {
+                0.0
+            } else {
+                (prices[end] - (k + ex / n)) / variance.sqrt()
+            };
+        }
+
+        block += lookback;
+    }
+
+    Ok(out)
+}
+
+/// Sum of (x - k) and (x - k)² over a slice
+///
+/// Accumulates in four independent lanes so the loop auto-vectorizes.
+fn shifted_sums(values: &[f64], k: f64) -> (f64, f64) {
+    let mut ex = [0.0; 4];
+    let mut ex2 = [0.0; 4];
+
+    let chunks = values.chunks_exact(4);
+    let remainder = chunks.remainder();
+    for chunk in chunks {
+        for lane in 0..4 {
+            let dx = chunk[lane] - k;
+            ex[lane] += dx;
+            ex2[lane] += dx * dx;
+        }
+    }
+
+    let mut sum = ex.iter().sum::<f64>();
+    let mut sum2 = ex2.iter().sum::<f64>();
+    for &x in remainder {
+        let dx = x - k;
+        sum += dx;
+        sum2 += dx * dx;
+    }
+
+    (sum, sum2)
+}
+
+/// Reference implementation using naive calculation (for comparison and testing)
+/// This is NOT suitable for production due to catastrophic cancellation issues
+#[cfg(test)]
+fn reference_zscore(prices: &[f64], current: f64) -> Option<f64> {
+    if prices.is_empty() {
+        return None;
+    }
+
+    let n = prices.len() as f64;
+    let mean: f64 = prices.iter().sum::<f64>() / n;
+
+    let variance: f64 = prices.iter()
+        .map(|&x| (x - mean).powi(2))
+        .sum::<f64>() / n;
+
+    if variance < 1e-10 {
+        return Some(0.0);
+    }
+
+    Some((current - mean) / variance.sqrt())
+}
+
+/// Two-pass sample variance (for testing)
+#[cfg(test)]
+fn reference_variance(prices: &[f64]) -> f64 {
+    let n = prices.len() as f64;
+    let mean = prices.iter().sum::<f64>() / n;
+    prices.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic uniform noise in [-0.5, 0.5) (xorshift64)
+    fn noise(state: &mut u64) -> f64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    }
+
+    #[test]
+    fn test_new_engine() {
+        let engine = ZScoreEngine::new(20).unwrap();
+        assert_eq!(engine.count(), 0);
+        assert!(!engine.is_ready());
+        assert_eq!(engine.lookback(), 20);
+    }
+
+    #[test]
+    fn test_invalid_lookback() {
+        for lookback in [0, 1] {
+            assert_eq!(
+                ZScoreEngine::new(lookback).err(),
+                Some(Error::InvalidArgument("Lookback must be > 1".to_string()))
+            );
+        }
+        assert!(ZScoreEngine::py_new(1, "price", None, None, false, None, false, None).is_err());
+    }
+
+    #[test]
+    fn test_warmup() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        // First 4 updates should return None
+        for i in 0..4 {
+            assert!(engine.update(100.0 + i as f64).unwrap().is_none());
+            assert!(!engine.is_ready());
+        }
+
+        // 5th update should return a value
+        assert!(engine.update(104.0).unwrap().is_some());
+        assert!(engine.is_ready());
+    }
+
+    #[test]
+    fn test_zscore_at_mean() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        // Add prices with mean = 100
+        for p in [98.0, 99.0, 100.0, 101.0, 102.0] {
+            engine.update(p).unwrap();
+        }
+
+        // Current price (102) is not at mean, but let's verify mean
+        let mean = engine.get_mean().unwrap();
+        assert!((mean - 100.0).abs() < 0.001);
+
+        // Update with price at mean
+        let z = engine.update(100.0).unwrap().unwrap();
+        // Z-Score should be close to 0 (actually slightly negative due to window shift)
+        assert!(z.abs() < 0.5);
+    }
+
+    #[test]
+    fn test_no_variance() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        // All same prices = no variance
+        for _ in 0..5 {
+            engine.update(100.0).unwrap();
+        }
+
+        let z = engine.get_zscore().unwrap();
+        assert_eq!(z, 0.0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        for _ in 0..10 {
+            engine.update(100.0).unwrap();
+        }
+        assert!(engine.is_ready());
+
+        engine.reset();
+
+        assert_eq!(engine.count(), 0);
+        assert!(!engine.is_ready());
+        assert!(engine.get_zscore().is_none());
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        // Add 10 prices: 0, 1, 2, ..., 9
+        for i in 0..10 {
+            engine.update(i as f64).unwrap();
+        }
+
+        // Window should contain: 5, 6, 7, 8, 9
+        // Mean should be 7
+        let mean = engine.get_mean().unwrap();
+        assert!((mean - 7.0).abs() < 0.001);
+
+        assert_eq!(engine.count(), 5);
+    }
+
+    #[test]
+    fn test_batch_update() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        let prices = vec![100.0, 101.0, 102.0, 103.0, 104.0, 105.0];
+        let z = engine.update_batch(prices).unwrap();
+
+        assert!(z.is_some());
+        assert!(engine.is_ready());
+    }
+
+    #[test]
+    fn test_get_variance() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        assert!(engine.get_variance().is_none());
+
+        engine.update(1.0).unwrap();
+        assert!(engine.get_variance().is_none());
+
+        for p in [2.0, 3.0, 4.0] {
+            engine.update(p).unwrap();
+        }
+
+        // Sample variance of [1, 2, 3, 4] is 5/3
+        let variance = engine.get_variance().unwrap();
+        assert!((variance - 5.0 / 3.0).abs() < 1e-12);
+
+        let std = engine.get_std().unwrap();
+        assert!((std * std - variance).abs() < 1e-12);
+
+        // Cache follows the window as it slides
+        engine.update(10.0).unwrap();
+        let expected = reference_variance(&[2.0, 3.0, 4.0, 10.0]);
+        assert!((engine.get_variance().unwrap() - expected).abs() < 1e-12);
+
+        engine.reset();
+        assert!(engine.get_variance().is_none());
+    }
+
+    #[test]
+    fn test_rolling_min_max() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert!(engine.get_min().is_none());
+        assert!(engine.get_max().is_none());
+
+        let prices = [5.0, 3.0, 4.0, 1.0, 2.0, 6.0, 6.0, 2.0, 2.0, 2.0];
+        for (i, &p) in prices.iter().enumerate() {
+            engine.update(p).unwrap();
+
+            let start = (i + 1).saturating_sub(3);
+            let window = &prices[start..=i];
+            let min = window.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = window.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            assert_eq!(engine.get_min(), Some(min), "min at step {}", i);
+            assert_eq!(engine.get_max(), Some(max), "max at step {}", i);
+        }
+
+        engine.reset();
+        assert!(engine.get_min().is_none());
+        assert!(engine.get_max().is_none());
+    }
+
+    #[test]
+    fn test_batch_zscore_static_parity() {
+        let prices: Vec<f64> = (0..500)
+            .map(|i| 1e9 + (i as f64 * 0.37).sin() * 25.0 + (i % 7) as f64)
+            .collect();
+
+        for lookback in [2, 5, 20, 64] {
+            let batch = batch_zscore_static(prices.clone(), lookback).unwrap();
+            assert_eq!(batch.len(), prices.len());
+
+            let mut engine = ZScoreEngine::new(lookback).unwrap();
+            for (i, &p) in prices.iter().enumerate() {
+                match engine.update(p).unwrap() {
+                    None => assert!(batch[i].is_nan(), "lookback {} index {}", lookback, i),
+                    Some(z) => assert!(
+                        (z - batch[i]).abs() < 1e-6,
+                        "lookback {} index {}: engine {} vs batch {}",
+                        lookback, i, z, batch[i]
+                    ),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_zscore_static_edge_cases() {
+        assert!(batch_zscore_static(vec![1.0, 2.0], 1).is_err());
+
+        // Shorter than lookback: all warmup
+        let out = batch_zscore_static(vec![1.0, 2.0], 5).unwrap();
+        assert!(out.iter().all(|z| z.is_nan()));
+
+        // Flat series has zero Z-Score
+        let out = batch_zscore_static(vec![100.0; 10], 3).unwrap();
+        assert!(out[2..].iter().all(|&z| z == 0.0));
+    }
+
+    #[test]
+    fn test_bands_and_percent_b() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        for p in [1.0, 2.0, 3.0] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.bands(2.0).unwrap(), None);
+        assert_eq!(engine.percent_b(2.0, 2.0).unwrap(), None);
+
+        engine.update(4.0).unwrap();
+        let mean = engine.get_mean().unwrap();
+        let std = engine.get_std().unwrap();
+
+        let (lower, mid, upper) = engine.bands(2.0).unwrap().unwrap();
+        assert!((mid - mean).abs() < 1e-12);
+        assert!((lower - (mean - 2.0 * std)).abs() < 1e-12);
+        assert!((upper - (mean + 2.0 * std)).abs() < 1e-12);
+
+        assert!((engine.percent_b(lower, 2.0).unwrap().unwrap() - 0.0).abs() < 1e-12);
+        assert!((engine.percent_b(mean, 2.0).unwrap().unwrap() - 0.5).abs() < 1e-12);
+        assert!((engine.percent_b(upper, 2.0).unwrap().unwrap() - 1.0).abs() < 1e-12);
+
+        assert!(engine.bands(0.0).is_err());
+        assert!(engine.bands(-1.0).is_err());
+        assert!(engine.percent_b(2.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_reversion_half_life() {
+        let rho: f64 = 0.8;
+        let expected = -std::f64::consts::LN_2 / rho.ln();
+
+        let mut engine = ZScoreEngine::new(5000).unwrap();
+        let mut state = 42;
+        let mut x = 0.0;
+        for _ in 0..5000 {
+            x = rho * x + noise(&mut state);
+            engine.update(1000.0 + x).unwrap();
+        }
+
+        let half_life = engine.reversion_half_life().unwrap();
+        assert!(
+            (half_life - expected).abs() / expected < 0.15,
+            "Half-life {} too far from expected {}",
+            half_life, expected
+        );
+    }
+
+    #[test]
+    fn test_reversion_half_life_not_reverting() {
+        let mut engine = ZScoreEngine::new(10).unwrap();
+        for _ in 0..9 {
+            engine.update(100.0).unwrap();
+        }
+        assert!(engine.reversion_half_life().is_none());
+
+        // Alternating series has negative autocorrelation
+        for i in 0..10 {
+            engine.update(if i % 2 == 0 { 99.0 } else { 101.0 }).unwrap();
+        }
+        assert!(engine.reversion_half_life().is_none());
+    }
+
+    #[test]
+    fn test_log_return_mode() {
+        let mut engine = ZScoreEngine::with_input_mode(3, InputMode::LogReturn).unwrap();
+        assert_eq!(engine.input_mode(), "log_return");
+
+        // First price only seeds the previous price
+        assert!(engine.update(100.0).unwrap().is_none());
+        assert_eq!(engine.count(), 0);
+
+        let prices = [101.0, 99.0, 102.0, 100.0];
+        let mut prev: f64 = 100.0;
+        let mut returns = Vec::new();
+        for p in prices {
+            engine.update(p).unwrap();
+            returns.push((p / prev).ln());
+            prev = p;
+        }
+
+        let window = &returns[1..];
+        assert_eq!(engine.get_prices().len(), 3);
+        for (a, b) in engine.get_prices().iter().zip(window) {
+            assert!((a - b).abs() < 1e-12);
+        }
+
+        let z = engine.get_zscore().unwrap();
+        let expected = reference_zscore(window, returns[3]).unwrap();
+        // Reference uses population std; rescale to sample std
+        let expected = expected * (2.0_f64 / 3.0).sqrt();
+        assert!((z - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_abs_log_return_mode() {
+        let mut engine = ZScoreEngine::with_input_mode(3, InputMode::AbsLogReturn).unwrap();
+        assert_eq!(engine.input_mode(), "abs_log_return");
+        assert_eq!(engine.bars_until_ready(), 4);
+
+        assert!(engine.update(100.0).unwrap().is_none());
+        assert_eq!(engine.count(), 0);
+        assert!(engine.is_warming());
+
+        for p in [101.0, 100.0, 101.0] {
+            engine.update(p).unwrap();
+        }
+        // Up and down moves of 1% feed the same value
+        let step = (101.0f64 / 100.0).ln();
+        for value in engine.get_prices() {
+            assert!((value - step).abs() < 1e-12);
+        }
+
+        // A 5% drop is a volatility spike, whatever its sign
+        let z = engine.update(101.0 * 0.95).unwrap().unwrap();
+        assert!(z > 1.0, "{}", z);
+
+        assert!(engine.update(0.0).is_err());
+        assert!(engine.update(-1.0).is_err());
+    }
+
+    #[test]
+    fn test_log_return_mode_rejects_non_positive() {
+        let mut engine = ZScoreEngine::with_input_mode(3, InputMode::LogReturn).unwrap();
+        assert!(engine.update(0.0).is_err());
+        assert!(engine.update(-5.0).is_err());
+        assert!(engine.update(f64::NAN).is_err());
+        assert_eq!(engine.count(), 0);
+
+        // Raw prices may legitimately be non-positive in price mode
+        let mut price_engine = ZScoreEngine::new(3).unwrap();
+        assert!(price_engine.update(-5.0).is_ok());
+    }
+
+    #[test]
+    fn test_log_return_mode_reset_clears_prev_price() {
+        let mut engine = ZScoreEngine::with_input_mode(3, InputMode::LogReturn).unwrap();
+        engine.update(100.0).unwrap();
+        engine.update(110.0).unwrap();
+        assert_eq!(engine.count(), 1);
+
+        engine.reset();
+
+        // Next price seeds again instead of producing a return vs 110
+        engine.update(50.0).unwrap();
+        assert_eq!(engine.count(), 0);
+    }
+
+    #[test]
+    fn test_input_mode_parse() {
+        assert_eq!("price".parse::<InputMode>(), Ok(InputMode::Price));
+        assert_eq!("log_return".parse::<InputMode>(), Ok(InputMode::LogReturn));
+        assert_eq!("abs_log_return".parse::<InputMode>(), Ok(InputMode::AbsLogReturn));
+        assert!("returns".parse::<InputMode>().is_err());
+    }
+
+    #[test]
+    fn test_zscore_history() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert!(engine.get_history().is_empty());
+        assert!(engine.enable_history(0).is_err());
+
+        engine.enable_history(2).unwrap();
+        let mut zscores = Vec::new();
+        for p in [1.0, 2.0, 4.0, 3.0, 7.0] {
+            if let Some(z) = engine.update(p).unwrap() {
+                zscores.push(z);
+            }
+        }
+
+        // Warmup produces nothing; only the last 2 Z-Scores are kept
+        assert_eq!(zscores.len(), 3);
+        assert_eq!(engine.get_history(), zscores[1..].to_vec());
+
+        engine.reset();
+        assert!(engine.get_history().is_empty());
+
+        engine.disable_history();
+        engine.update_batch(vec![1.0, 2.0, 3.0]).unwrap();
+        assert!(engine.get_history().is_empty());
+    }
+
+    #[test]
+    fn test_memory_bytes() {
+        let small = ZScoreEngine::new(10).unwrap();
+        let large = ZScoreEngine::new(1000).unwrap();
+        assert!(small.memory_bytes() >= std::mem::size_of::<ZScoreEngine>());
+        assert!(large.memory_bytes() > small.memory_bytes());
+
+        let mut with_history = ZScoreEngine::new(10).unwrap();
+        with_history.enable_history(500).unwrap();
+        assert!(with_history.memory_bytes() >= small.memory_bytes() + 500 * 8);
+
+        with_history.disable_history();
+        assert_eq!(with_history.memory_bytes(), small.memory_bytes());
+    }
+
+    #[test]
+    fn test_ewma_variance_converges_to_true_variance() {
+        // Uniform noise on [-0.5, 0.5) has variance 1/12
+        let true_var = 1.0 / 12.0;
+
+        let mut engine = ZScoreEngine::new(5000).unwrap();
+        let mut state = 7;
+        for _ in 0..5000 {
+            engine.update(250.0 + noise(&mut state)).unwrap();
+        }
+
+        let ewma_var = engine.get_ewma_variance(0.002).unwrap().unwrap();
+        assert!(
+            (ewma_var - true_var).abs() / true_var < 0.15,
+            "EWMA variance {} too far from {}",
+            ewma_var,
+            true_var
+        );
+
+        // alpha = 1 keeps only the latest deviation
+        let mean = engine.get_mean().unwrap();
+        let last = *engine.get_prices().last().unwrap();
+        let v1 = engine.get_ewma_variance(1.0).unwrap().unwrap();
+        assert!((v1 - (last - mean).powi(2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_ewma_bias_correction_on_short_window() {
+        // Over a 20-bar window the alpha = 0.01 weights sum to ~0.18, so
+        // without the correction the estimate would be far too small
+        let mut state = 11;
+        let mut total = 0.0;
+        let mut samples = 0;
+        let mut engine = ZScoreEngine::new(20).unwrap();
+        for i in 0..20_000 {
+            engine.update(100.0 + noise(&mut state)).unwrap();
+            if i >= 20 && i % 20 == 0 {
+                total += engine.get_ewma_variance(0.01).unwrap().unwrap();
+                samples += 1;
+            }
+        }
+
+        // Deviations are taken from the window mean, which shrinks the
+        // expectation by roughly (n - 1) / n
+        let expected = (1.0 / 12.0) * 19.0 / 20.0;
+        let avg = total / samples as f64;
+        assert!(
+            (avg - expected).abs() / expected < 0.1,
+            "Average {} vs {}",
+            avg,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_hybrid_ewma_zscore() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        for p in [1.0, 2.0, 3.0] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.get_hybrid_ewma_zscore(0.5).unwrap(), None);
+
+        engine.update(6.0).unwrap();
+        let mean = engine.get_mean().unwrap();
+        let var = engine.get_ewma_variance(0.5).unwrap().unwrap();
+        let z = engine.get_hybrid_ewma_zscore(0.5).unwrap().unwrap();
+        assert!((z - (6.0 - mean) / var.sqrt()).abs() < 1e-12);
+
+        assert!(engine.get_hybrid_ewma_zscore(0.0).is_err());
+        assert!(engine.get_hybrid_ewma_zscore(1.5).is_err());
+        assert!(engine.get_ewma_variance(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_zscore_autocorr() {
+        let mut engine = ZScoreEngine::new(20).unwrap();
+        assert_eq!(engine.zscore_autocorr(1), None);
+
+        engine.enable_history(5000).unwrap();
+        let mut state = 3;
+        for _ in 0..5020 {
+            engine.update(100.0 + noise(&mut state)).unwrap();
+        }
+        let white = engine.zscore_autocorr(1).unwrap();
+        assert!(white.abs() < 0.15, "Random series autocorr {}", white);
+        assert!((engine.zscore_autocorr(0).unwrap() - 1.0).abs() < 1e-12);
+
+        // AR(1) prices: x[t] = 0.9 * x[t-1] + noise
+        engine.reset();
+        let mut x = 0.0;
+        for _ in 0..5020 {
+            x = 0.9 * x + noise(&mut state);
+            engine.update(100.0 + x).unwrap();
+        }
+        let ar = engine.zscore_autocorr(1).unwrap();
+        assert!(ar > 0.5, "AR(1) series autocorr {}", ar);
+
+        assert_eq!(engine.zscore_autocorr(5000), None);
+    }
+
+    #[test]
+    fn test_mean_reversion_halflife() {
+        let mut engine = ZScoreEngine::new(200).unwrap();
+        assert_eq!(engine.mean_reversion_halflife(), None);
+
+        // AR(1) around 5000 with b = 0.5: half-life of one bar
+        let mut state = 5;
+        let mut x = 5000.0;
+        for _ in 0..1000 {
+            x = 2500.0 + 0.5 * x + noise(&mut state);
+            engine.update(x).unwrap();
+        }
+        let half_life = engine.mean_reversion_halflife().unwrap();
+        assert!((half_life - 1.0).abs() < 0.3, "half-life {}", half_life);
+
+        // A trend is not mean-reverting
+        let mut trend = ZScoreEngine::new(20).unwrap();
+        for i in 0..40 {
+            trend.update(5000.0 + i as f64 * i as f64).unwrap();
+        }
+        assert_eq!(trend.mean_reversion_halflife(), None);
+    }
+
+    #[test]
+    fn test_baseline() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        for price in [100.0, 102.0, 104.0] {
+            engine.update(price).unwrap();
+        }
+        assert!(engine.set_baseline(100.0, 0.0).is_err());
+        assert!(engine.set_baseline(f64::NAN, 1.0).is_err());
+
+        engine.set_baseline(100.0, 4.0).unwrap();
+        assert_eq!(engine.update(110.0).unwrap(), Some(2.5));
+        assert_eq!(engine.update_full(98.0).unwrap(), Some((-0.5, 100.0, 4.0)));
+
+        // The rolling window never saw the baseline updates
+        assert_eq!(engine.get_prices(), vec![100.0, 102.0, 104.0]);
+        assert_eq!(engine.get_mean(), Some(102.0));
+
+        engine.clear_baseline();
+        assert_eq!(engine.get_baseline(), None);
+        let z = engine.update(106.0).unwrap().unwrap();
+        assert!((z - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_crossed_with_baseline() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        engine.set_baseline(100.0, 4.0).unwrap();
+        engine.update(101.0).unwrap();
+        assert_eq!(engine.crossed(1.0), Ok(None)); // No previous Z-Score
+
+        engine.update(106.0).unwrap(); // 0.25 -> 1.5
+        assert_eq!(engine.crossed(1.0), Ok(Some(Cross::Up)));
+        engine.update(104.0).unwrap(); // 1.5 -> 1.0
+        assert_eq!(engine.crossed(1.0), Ok(None));
+        engine.update(92.0).unwrap(); // 1.0 -> -2.0
+        assert_eq!(engine.crossed(1.0), Ok(Some(Cross::Down)));
+        assert_eq!(engine.count(), 0);
+    }
+
+    #[test]
+    fn test_signal_hysteresis() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        assert!(engine.signal_hysteresis(100.0, 0.0, 0.0).is_err());
+        assert!(engine.signal_hysteresis(100.0, 1.0, 1.0).is_err());
+
+        let mut states = Vec::new();
+        for price in [100.0, 101.0, 100.0, 101.0, 100.0, 96.0, 97.0, 99.0, 104.0, 103.0] {
+            let state = engine.signal_hysteresis(price, 1.5, 0.0).unwrap();
+            states.push(state);
+            assert_eq!(engine.current_signal_state(), state);
+        }
+
+        // Warmup is flat; the dip enters long and holds until the mean
+        assert_eq!(states[..5], [Signal::Flat; 5]);
+        assert_eq!(states[5], Signal::LongEntry);
+        assert_eq!(states[6], Signal::LongEntry);
+        assert_eq!(states[7], Signal::Flat);
+
+        // Rally past the entry band goes short and holds
+        assert_eq!(states[8], Signal::ShortEntry);
+        assert_eq!(states[9], Signal::ShortEntry);
+
+        engine.reset();
+        assert_eq!(engine.current_signal_state(), Signal::Flat);
+    }
+
+    #[test]
+    fn test_crossed() {
+        let mut engine = ZScoreEngine::new(2).unwrap();
+        engine.update(0.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(None));
+
+        // Window of 2: Z-Score is ±0.707 depending on direction
+        engine.update(1.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(None)); // First ready bar
+        engine.update(2.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(None)); // Still above
+        engine.update(1.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(Some(Cross::Down)));
+        assert_eq!(engine.crossed(1.0), Ok(None));
+        engine.update(3.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(Some(Cross::Up)));
+        engine.update(3.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(None)); // Back to 0
+
+        engine.reset();
+        engine.update(1.0).unwrap();
+        engine.update(2.0).unwrap();
+        assert_eq!(engine.crossed(0.5), Ok(None));
+
+        assert!(engine.crossed(-1.0).is_err());
+        assert!(engine.crossed(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_streaks() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert_eq!(engine.streak_above(1.0).unwrap(), 0);
+
+        // Warmup bars do not count
+        let mut zscores = Vec::new();
+        for price in [100.0, 100.0, 101.0, 103.0, 106.0, 110.0, 104.0, 95.0, 90.0] {
+            if let Some(z) = engine.update(price).unwrap() {
+                zscores.push(z);
+            }
+        }
+
+        // Compare against a backward scan for several thresholds
+        for threshold in [0.0, 0.5, 1.0, 1.1, 5.0] {
+            let above = zscores.iter().rev().take_while(|&&z| z >= threshold).count();
+            let below = zscores.iter().rev().take_while(|&&z| z <= -threshold).count();
+            assert_eq!(engine.streak_above(threshold).unwrap(), above, "above {}", threshold);
+            assert_eq!(engine.streak_below(threshold).unwrap(), below, "below {}", threshold);
+        }
+        assert!(engine.streak_below(0.5).unwrap() > 0);
+        assert!(engine.streak_above(f64::NAN).is_err());
+        assert!(engine.streak_below(f64::INFINITY).is_err());
+
+        engine.reset();
+        assert_eq!(engine.streak_below(0.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_update_weighted() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert!(engine.update_weighted(100.0, 0.0).is_err());
+        assert!(engine.update_weighted(100.0, f64::NAN).is_err());
+
+        // Evicted by count: the weight-5 bar at 90 drops out
+        engine.update_weighted(90.0, 5.0).unwrap();
+        engine.update_weighted(100.0, 1.0).unwrap();
+        engine.update_weighted(110.0, 1.0).unwrap();
+        let z = engine.update_weighted(104.0, 2.0).unwrap().unwrap();
+
+        // Weights 1, 1, 2: mean 104.5, Σw(x - mean)² = 20.25 + 30.25 + 0.5
+        let variance: f64 = 51.0 / (4.0 - 6.0 / 4.0);
+        assert!((engine.get_mean().unwrap() - 104.5).abs() < 1e-12);
+        assert!((engine.get_std().unwrap() - variance.sqrt()).abs() < 1e-12);
+        assert!((z - (104.0 - 104.5) / variance.sqrt()).abs() < 1e-12);
+
+        // Unit weights match plain updates exactly
+        let mut plain = ZScoreEngine::new(5).unwrap();
+        let mut unit = ZScoreEngine::new(5).unwrap();
+        let mut state = 11;
+        for _ in 0..50 {
+            let price = 5000.0 + noise(&mut state);
+            assert_eq!(plain.update(price).unwrap(), unit.update_weighted(price, 1.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_deviation_squares() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        engine.update(100.0).unwrap();
+        engine.update(102.0).unwrap();
+        assert!(engine.deviation_squares().is_empty());
+
+        engine.update(106.0).unwrap();
+        let rows = engine.deviation_squares();
+        let prices: Vec<f64> = rows.iter().map(|r| r.0).collect();
+        assert_eq!(prices, vec![100.0, 102.0, 106.0]);
+        assert!((rows[2].1 - 10.0 / 3.0).abs() < 1e-9);
+        assert!((rows[2].2 - 100.0 / 9.0).abs() < 1e-9);
+
+        // The squares add up to the sample variance
+        let total: f64 = rows.iter().map(|r| r.2).sum();
+        assert!((total / 2.0 - engine.get_variance().unwrap()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_update_nan() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert!(engine.update_nan(100.0).unwrap().is_nan());
+
+        let out = engine.update_batch_all(&[102.0, 104.0, 106.0]).unwrap();
+        assert!(out[0].is_nan());
+        assert!((out[1] - 1.0).abs() < 1e-12);
+        assert!((out[2] - 1.0).abs() < 1e-12);
+
+        // Matches the Option API element by element
+        let mut a = ZScoreEngine::new(4).unwrap();
+        let mut b = ZScoreEngine::new(4).unwrap();
+        let mut state = 9;
+        let prices: Vec<f64> = (0..20).map(|_| 100.0 + noise(&mut state)).collect();
+        let batch = a.update_batch_all(&prices).unwrap();
+        for (&price, z) in prices.iter().zip(batch) {
+            match b.update(price).unwrap() {
+                Some(expected) => assert_eq!(z, expected),
+                None => assert!(z.is_nan()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_warming() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert_eq!(engine.get_min_periods(), 3);
+        assert!(!engine.is_warming() && !engine.is_ready());
+
+        engine.update(100.0).unwrap();
+        engine.update(101.0).unwrap();
+        assert!(engine.is_warming());
+
+        engine.update(102.0).unwrap();
+        assert!(!engine.is_warming() && engine.is_ready());
+
+        let mut returns = ZScoreEngine::with_input_mode(3, InputMode::LogReturn).unwrap();
+        returns.update(100.0).unwrap();
+        assert_eq!(returns.count(), 0);
+        assert!(returns.is_warming());
+    }
+
+    #[test]
+    fn test_bars_until_ready() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        assert_eq!(engine.bars_until_ready(), 4);
+        assert_eq!(engine.warmup_progress(), 0.0);
+
+        engine.update(100.0).unwrap();
+        assert_eq!(engine.bars_until_ready(), 3);
+        assert_eq!(engine.warmup_progress(), 0.25);
 
-        Self {
-            prices: VecDeque::with_capacity(lookback + 1),
-            lookback,
-            K: 0.0,
-            n: 0.0,
-            Ex: 0.0,
-            Ex2: 0.0,
+        for price in [101.0, 102.0, 103.0, 104.0] {
+            engine.update(price).unwrap();
+        }
+        assert!(engine.is_ready());
+        assert_eq!(engine.bars_until_ready(), 0);
+        assert_eq!(engine.warmup_progress(), 1.0);
+
+        // The first log-return price only seeds the previous price
+        let mut returns = ZScoreEngine::with_input_mode(3, InputMode::LogReturn).unwrap();
+        assert_eq!(returns.bars_until_ready(), 4);
+        returns.update(100.0).unwrap();
+        assert_eq!(returns.bars_until_ready(), 3);
+        for price in [101.0, 102.0, 103.0] {
+            assert!(returns.bars_until_ready() > 0);
+            returns.update(price).unwrap();
         }
+        assert!(returns.is_ready());
+        assert_eq!(returns.bars_until_ready(), 0);
     }
 
-    /// Update with new price and return current Z-Score
-    ///
-    /// Returns None if insufficient data (warming up period)
-    ///
-    /// # Arguments
-    /// * `price` - New price to add to the rolling window
-    pub fn update(&mut self, price: f64) -> Option<f64> {
-        // Initialize K on first price for numerical stability
-        if self.prices.is_empty() {
-            self.K = price;
-        }
-
-        // Add new price using shifted data algorithm
-        let dx = price - self.K;
-        self.Ex += dx;
-        self.Ex2 += dx * dx;
-        self.n += 1.0;
-        self.prices.push_back(price);
-
-        // Remove oldest price if over lookback
-        if self.prices.len() > self.lookback {
-            // Check if we're about to remove our reference price
-            let removing_k = self.prices.front()
-                .map(|&front| (front - self.K).abs() < 1e-10)
-                .unwrap_or(false);
-
-            if let Some(old) = self.prices.pop_front() {
-                let dx = old - self.K;
-                self.Ex -= dx;
-                self.Ex2 -= dx * dx;
-                self.n -= 1.0;
-
-                // If we removed our reference K, update K to a new value in the window
-                // This maintains numerical stability as the window slides
-                if removing_k {
-                    if let Some(&new_k) = self.prices.front() {
-                        // Need to recalculate Ex and Ex2 with new reference
-                        let old_k = self.K;
-                        self.K = new_k;
-                        let shift = old_k - self.K;
-
-                        // Recalculate shifted sums
-                        // When K changes: new_Ex = Σ(x - new_K) = Σ(x - old_K + old_K - new_K) = old_Ex + n * shift
-                        // new_Ex2 = Σ(x - new_K)² = Σ((x - old_K) + shift)² = old_Ex2 + 2 * shift * old_Ex + n * shift²
-                        let n = self.n;
-                        let old_Ex = self.Ex;
-                        let old_Ex2 = self.Ex2;
-
-                        self.Ex = old_Ex + n * shift;
-                        self.Ex2 = old_Ex2 + 2.0 * shift * old_Ex + n * shift * shift;
-                    }
-                }
-            }
+    #[test]
+    fn test_require_positive() {
+        let mut engine = ZScoreEngine::new(3).unwrap().with_require_positive(true);
+        assert!(engine.requires_positive());
+        engine.update(100.0).unwrap();
+        assert!(engine.update(0.0).is_err());
+        assert!(engine.update(-5.0).is_err());
+        assert!(engine.update(f64::NAN).is_err());
+
+        // Bad ticks never reached the window
+        assert_eq!(engine.count(), 1);
+        engine.update(101.0).unwrap();
+        assert!(engine.update(102.0).unwrap().is_some());
+
+        // Default stays permissive for spreads
+        let mut spread = ZScoreEngine::new(3).unwrap();
+        assert!(!spread.requires_positive());
+        for value in [-1.0, 0.0, -2.0] {
+            spread.update(value).unwrap();
         }
+        assert!(spread.is_ready());
+    }
 
-        // Calculate Z-Score if we have enough data
-        self.calculate_zscore(price)
+    #[test]
+    fn test_tick_size() {
+        let mut engine = ZScoreEngine::new(3).unwrap().with_tick_size(0.25).unwrap();
+        assert_eq!(engine.tick_size(), Some(0.25));
+        for p in [5000.1, 5000.4, 4999.87, 5000.0] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.get_prices(), vec![5000.5, 4999.75, 5000.0]);
+
+        // Rounding happens before the positivity check
+        let mut strict = ZScoreEngine::new(3)
+            .unwrap()
+            .with_require_positive(true)
+            .with_tick_size(1.0)
+            .unwrap();
+        assert!(strict.update(0.4).is_err());
+        assert_eq!(strict.count(), 0);
+
+        assert_eq!(ZScoreEngine::new(3).unwrap().tick_size(), None);
+        for tick in [0.0, -0.25, f64::NAN, f64::INFINITY] {
+            assert!(ZScoreEngine::new(3).unwrap().with_tick_size(tick).is_err());
+        }
+        let engine = ZScoreEngine::py_new(3, "price", None, None, false, Some(0.0), false, None);
+        assert!(engine.is_err());
     }
 
-    /// Get current Z-Score without adding new data
-    pub fn get_zscore(&self) -> Option<f64> {
-        if let Some(&current) = self.prices.back() {
-            self.calculate_zscore(current)
-        } else {
-            None
+    #[test]
+    fn test_exclude_current() {
+        let mut engine = ZScoreEngine::new(3).unwrap().with_exclude_current(true);
+        assert!(engine.excludes_current());
+        assert_eq!(engine.get_min_periods(), 4);
+        assert_eq!(engine.bars_until_ready(), 4);
+
+        for p in [10.0, 12.0, 14.0] {
+            assert_eq!(engine.update(p).unwrap(), None);
+        }
+        assert!(!engine.is_ready());
+
+        // Prior bars 10, 12, 14: mean 12, std 2
+        assert_eq!(engine.update(20.0).unwrap(), Some(4.0));
+        assert_eq!(engine.count(), 4);
+
+        // 10 slides out; prior bars 12, 14, 20: mean 46/3, variance 52/3
+        let z = engine.update(15.0).unwrap().unwrap();
+        let expected = (15.0 - 46.0 / 3.0) / (52.0f64 / 3.0).sqrt();
+        assert!((z - expected).abs() < 1e-12);
+        assert_eq!(engine.get_zscore(), Some(z));
+
+        // Flat prior bars report 0
+        let mut flat = ZScoreEngine::new(2).unwrap().with_exclude_current(true);
+        for p in [5.0, 5.0] {
+            flat.update(p).unwrap();
         }
+        assert_eq!(flat.update(9.0).unwrap(), Some(0.0));
+
+        let default = ZScoreEngine::new(3).unwrap();
+        assert!(!default.excludes_current());
+        assert_eq!(default.get_min_periods(), 3);
     }
 
-    /// Get current rolling mean
-    ///
-    /// Uses shifted data formula: mean = K + Ex/n
-    /// where n is the window size
-    pub fn get_mean(&self) -> Option<f64> {
-        let n = self.prices.len() as f64;
-        if n >= 2.0 {
-            Some(self.K + self.Ex / n)
-        } else {
-            None
+    #[test]
+    fn test_update_timing() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        engine.update(1.0).unwrap();
+        assert_eq!(engine.last_update_ns(), None);
+        assert_eq!(engine.update_timing_percentiles(), None);
+        assert!(engine.enable_timing(0).is_err());
+
+        engine.enable_timing(4).unwrap();
+        assert_eq!(engine.update_timing_percentiles(), None);
+        for i in 0..10 {
+            engine.update(i as f64).unwrap();
+            assert!(engine.last_update_ns().is_some());
         }
+        assert_eq!(engine.timings.as_ref().unwrap().len(), 4);
+        let (p50, p90, p99, max) = engine.update_timing_percentiles().unwrap();
+        assert!(p50 <= p90 && p90 <= p99 && p99 <= max);
+        assert_eq!(max, *engine.timings.as_ref().unwrap().iter().max().unwrap());
+
+        engine.disable_timing();
+        engine.update(1.0).unwrap();
+        assert_eq!(engine.last_update_ns(), None);
     }
 
-    /// Get current rolling standard deviation
-    ///
-    /// Uses shifted data formula for variance:
-    /// variance = (Ex2 - Ex²/n) / (n-1)
-    ///
-    /// This is numerically stable because we work with small
-    /// values (differences from K) instead of large raw prices.
-    pub fn get_std(&self) -> Option<f64> {
-        let n = self.prices.len() as f64;
-        if n >= 2.0 {
-            // Shifted data variance formula
-            let variance = (self.Ex2 - (self.Ex * self.Ex) / n) / (n - 1.0);
-
-            // Handle numerical precision issues (tiny negative values possible)
-            if variance < 0.0 {
-                Some(0.0)
-            } else {
-                Some(variance.sqrt())
+    #[test]
+    fn test_decay_collapsed_weights() {
+        // Each bar outweighs the previous by 1e20, so the effective sample
+        // size rounds to 1: the window counts as flat instead of losing
+        // its std and Z-Score
+        for exclude_current in [false, true] {
+            let mut engine = ZScoreEngine::new(3)
+                .unwrap()
+                .with_decay(1e-20)
+                .unwrap()
+                .with_exclude_current(exclude_current);
+            for p in [1.0, 2.0, 4.0, 7.0, 3.0] {
+                engine.update(p).unwrap();
             }
-        } else {
-            None
+            assert!(engine.is_ready());
+            assert_eq!(engine.get_zscore(), Some(0.0));
+            assert_eq!(engine.get_variance(), Some(1e-10));
+            let (z, _, std) = engine.update_full(5.0).unwrap().unwrap();
+            assert_eq!(z, 0.0);
+            assert!(std <= 1e-5);
+
+            engine.set_variance_floor(0.0).unwrap();
+            assert_eq!(engine.get_zscore(), Some(0.0));
         }
     }
 
-    /// Reset the engine, clearing all data
-    pub fn reset(&mut self) {
-        self.prices.clear();
-        self.K = 0.0;
-        self.n = 0.0;
-        self.Ex = 0.0;
-        self.Ex2 = 0.0;
-    }
+    #[test]
+    fn test_decay() {
+        let mut engine = ZScoreEngine::new(3).unwrap().with_decay(0.5).unwrap();
+        assert_eq!(engine.decay(), Some(0.5));
+        for p in [1.0, 10.0, 2.0, 4.0] {
+            engine.update(p).unwrap();
+        }
 
-    /// Check if engine has enough data to generate signals
-    pub fn is_ready(&self) -> bool {
-        self.prices.len() >= self.lookback
-    }
+        // Window 10, 2, 4 with weights 1/4, 1/2, 1
+        let w = [0.25, 0.5, 1.0];
+        let x = [10.0, 2.0, 4.0];
+        let total: f64 = w.iter().sum();
+        let mean = w.iter().zip(&x).map(|(w, x)| w * x).sum::<f64>() / total;
+        let ss: f64 = w.iter().zip(&x).map(|(w, x)| w * (x - mean) * (x - mean)).sum();
+        let variance = ss / (total - w.iter().map(|w| w * w).sum::<f64>() / total);
+        assert!((engine.get_mean().unwrap() - mean).abs() < 1e-12);
+        assert!((engine.get_variance().unwrap() - variance).abs() < 1e-12);
+        let z = engine.get_zscore().unwrap();
+        assert!((z - (4.0 - mean) / variance.sqrt()).abs() < 1e-12);
+
+        // decay = 1 matches the equal-weight engine
+        let mut flat = ZScoreEngine::new(4).unwrap().with_decay(1.0).unwrap();
+        let mut plain = ZScoreEngine::new(4).unwrap();
+        for i in 0..20 {
+            let price = 100.0 + (i as f64).sin();
+            assert_eq!(flat.update(price).unwrap(), plain.update(price).unwrap());
+        }
 
-    /// Get number of prices currently in the window
-    pub fn count(&self) -> usize {
-        self.prices.len()
+        // Weights are renormalized long before they overflow
+        let mut fast = ZScoreEngine::new(5).unwrap().with_decay(0.01).unwrap();
+        let mut reference = ZScoreEngine::new(5).unwrap().with_decay(0.01).unwrap();
+        let mut state = 5;
+        for i in 0..500 {
+            fast.update(50.0 + noise(&mut state)).unwrap();
+            if i % 100 == 99 {
+                // Feed the last window to a fresh engine
+                reference.reset();
+                for &p in &fast.get_prices() {
+                    reference.update(p).unwrap();
+                }
+                let (a, b) = (fast.get_zscore().unwrap(), reference.get_zscore().unwrap());
+                assert!((a - b).abs() < 1e-9, "{} vs {}", a, b);
+            }
+        }
+
+        for decay in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(ZScoreEngine::new(3).unwrap().with_decay(decay).is_err());
+        }
+        let engine = ZScoreEngine::py_new(3, "price", None, None, false, None, false, Some(0.0));
+        assert!(engine.is_err());
     }
 
-    /// Get the lookback period
-    pub fn lookback(&self) -> usize {
-        self.lookback
+    #[test]
+    fn test_json_round_trip() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        engine.enable_history(4).unwrap();
+        let mut state = 3;
+        for i in 0..37 {
+            engine.update(5000.0 + i as f64 * 0.25 + noise(&mut state)).unwrap();
+        }
+
+        let mut restored = ZScoreEngine::from_json(&engine.to_json().unwrap()).unwrap();
+        assert_eq!(restored.get_prices(), engine.get_prices());
+        assert_eq!(restored.get_zscore(), engine.get_zscore());
+        assert_eq!(restored.get_history(), engine.get_history());
+        assert_eq!(restored.get_max(), engine.get_max());
+
+        // Both continue identically
+        assert_eq!(restored.update(5012.0).unwrap(), engine.update(5012.0).unwrap());
+
+        assert!(ZScoreEngine::from_json("not json").is_err());
     }
 
-    /// Get all prices in the current window (for debugging)
-    pub fn get_prices(&self) -> Vec<f64> {
-        self.prices.iter().copied().collect()
+    #[test]
+    fn test_from_json_rejects_invalid_state() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        for p in [10.0, 12.0, 11.0, 13.0, 12.5, 14.0, 13.5] {
+            engine.update(p).unwrap();
+        }
+        let state: serde_json::Value = serde_json::from_str(&engine.to_json().unwrap()).unwrap();
+
+        let mutations: [(&str, serde_json::Value); 6] = [
+            ("lookback", 1.into()),
+            ("decay", 2.0.into()),
+            ("variance_floor", (-1.0).into()),
+            ("seq", 3.into()),
+            ("z_count", 0.into()),
+            ("history_len", 0.into()),
+        ];
+        for (field, value) in mutations {
+            let mut bad = state.clone();
+            bad[field] = value;
+            if field == "history_len" {
+                bad["history"] = serde_json::json!([]);
+            }
+            let result = ZScoreEngine::from_json(&bad.to_string());
+            assert!(matches!(result, Err(Error::InvalidArgument(_))), "{}", field);
+        }
+
+        // A deque index past the sequence number would underflow the age
+        let mut bad = state.clone();
+        bad["max_deque"][0][0] = 100.into();
+        assert!(ZScoreEngine::from_json(&bad.to_string()).is_err());
+
+        // The binary checkpoint is validated the same way
+        engine.seq = 0;
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("zscore_bad_state_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        engine.save(path).unwrap();
+        assert!(matches!(ZScoreEngine::load(path), Err(Error::InvalidArgument(_))));
+        std::fs::remove_file(path).unwrap();
     }
 
-    /// Batch update with multiple prices, returns final Z-Score
-    ///
-    /// More efficient than calling update() in a loop from Python
-    pub fn update_batch(&mut self, prices: Vec<f64>) -> Option<f64> {
-        let mut result = None;
-        for price in prices {
-            result = self.update(price);
+    #[test]
+    fn test_save_load() {
+        let mut engine = ZScoreEngine::new(5).unwrap().with_tick_size(0.25).unwrap();
+        let mut state = 11;
+        for i in 0..23 {
+            engine.update(5000.0 + i as f64 * 0.5 + noise(&mut state)).unwrap();
         }
-        result
+
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("zscore_save_load_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        engine.save(path).unwrap();
+        let mut restored = ZScoreEngine::load(path).unwrap();
+        assert_eq!(restored.get_prices(), engine.get_prices());
+        assert_eq!(restored.get_zscore(), engine.get_zscore());
+        assert_eq!(restored.tick_size(), Some(0.25));
+        assert_eq!(restored.update(5012.1).unwrap(), engine.update(5012.1).unwrap());
+
+        std::fs::write(path, b"garbage").unwrap();
+        assert!(matches!(ZScoreEngine::load(path), Err(Error::InvalidArgument(_))));
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(ZScoreEngine::load(path), Err(Error::Io(_))));
+        let missing_dir = dir.join("zscore_no_such_dir").join("state.bin");
+        assert!(matches!(engine.save(missing_dir.to_str().unwrap()), Err(Error::Io(_))));
     }
-}
 
-impl ZScoreEngine {
-    /// Internal Z-Score calculation using shifted data algorithm
-    fn calculate_zscore(&self, current_price: f64) -> Option<f64> {
-        if self.prices.len() < self.lookback {
-            return None;
+    #[test]
+    fn test_smoothing_alpha() {
+        let mut engine = ZScoreEngine::new(3).unwrap().with_smoothing(0.5).unwrap();
+        assert_eq!(engine.smoothing_alpha(), Some(0.5));
+        for p in [10.0, 20.0, 10.0, 30.0] {
+            engine.update(p).unwrap();
         }
+        // Smoothed: 10, 15, 12.5, 21.25; window keeps the last 3
+        assert_eq!(engine.get_prices(), vec![15.0, 12.5, 21.25]);
+
+        // Reset clears the smoother: the next price seeds it again
+        engine.reset();
+        engine.update(100.0).unwrap();
+        assert_eq!(engine.get_prices(), vec![100.0]);
 
-        let n = self.prices.len() as f64;
-        let variance = (self.Ex2 - (self.Ex * self.Ex) / n) / (n - 1.0);
+        // alpha = 1 is a pass-through
+        let mut raw = ZScoreEngine::new(3).unwrap().with_smoothing(1.0).unwrap();
+        raw.update(10.0).unwrap();
+        raw.update(20.0).unwrap();
+        assert_eq!(raw.get_prices(), vec![10.0, 20.0]);
 
-        // If variance is essentially zero, return 0 (price at mean)
-        if variance < 1e-10 {
-            return Some(0.0);
+        for alpha in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(ZScoreEngine::new(3).unwrap().with_smoothing(alpha).is_err());
         }
+        let engine = ZScoreEngine::py_new(3, "price", None, Some(0.0), false, None, false, None);
+        assert!(engine.is_err());
+    }
 
-        let std_dev = variance.sqrt();
-        let mean = self.K + self.Ex / n;
-        Some((current_price - mean) / std_dev)
+    #[test]
+    fn test_variance_with_ddof() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        engine.update(2.0).unwrap();
+        assert_eq!(engine.get_variance_with(0).unwrap(), None);
+
+        for p in [4.0, 4.0, 6.0] {
+            engine.update(p).unwrap();
+        }
+        // Deviations -2, 0, 0, 2: sum of squares 8
+        assert_eq!(engine.get_variance_with(1).unwrap(), engine.get_variance());
+        assert!((engine.get_variance_with(1).unwrap().unwrap() - 8.0 / 3.0).abs() < 1e-12);
+        assert!((engine.get_variance_with(0).unwrap().unwrap() - 2.0).abs() < 1e-12);
+        assert!((engine.get_std_with(0).unwrap().unwrap() - 2f64.sqrt()).abs() < 1e-12);
+
+        assert!(engine.get_variance_with(2).is_err());
+        assert!(engine.get_std_with(2).is_err());
+
+        // Weighted: 1 (w=1), 3 (w=3); mean 2.5, Σw(x - mean)² = 3
+        let mut weighted = ZScoreEngine::new(4).unwrap();
+        weighted.update_weighted(1.0, 1.0).unwrap();
+        weighted.update_weighted(3.0, 3.0).unwrap();
+        // Divisors W = 4 and W - Σw²/W = 4 - 10/4
+        assert!((weighted.get_variance_with(0).unwrap().unwrap() - 0.75).abs() < 1e-12);
+        assert!((weighted.get_variance_with(1).unwrap().unwrap() - 2.0).abs() < 1e-12);
     }
-}
 
-/// Reference implementation using naive calculation (for comparison and testing)
-/// This is NOT suitable for production due to catastrophic cancellation issues
-#[cfg(test)]
-fn reference_zscore(prices: &[f64], current: f64) -> Option<f64> {
-    if prices.is_empty() {
-        return None;
+    #[test]
+    fn test_zscore_of() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        engine.update(1.0).unwrap();
+        engine.update(2.0).unwrap();
+        assert_eq!(engine.zscore_of(5.0), None);
+
+        engine.update(3.0).unwrap();
+        assert_eq!(engine.zscore_of(5.0), Some(3.0));
+        assert_eq!(engine.zscore_of(2.0), Some(0.0));
+
+        // State is untouched
+        assert_eq!(engine.count(), 3);
+        assert_eq!(engine.peek_newest(), Some(3.0));
+        assert_eq!(engine.get_zscore(), Some(1.0));
     }
 
-    let n = prices.len() as f64;
-    let mean: f64 = prices.iter().sum::<f64>() / n;
+    #[test]
+    fn test_update_full() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert_eq!(engine.update_full(1.0).unwrap(), None);
+        assert_eq!(engine.update_full(2.0).unwrap(), None);
+
+        let (z, mean, std) = engine.update_full(3.0).unwrap().unwrap();
+        assert_eq!(Some(z), engine.get_zscore());
+        assert_eq!(mean, 2.0);
+        assert_eq!(std, 1.0);
+        assert!((z - (3.0 - mean) / std).abs() < 1e-12);
+
+        // Excluding the current bar, the components are the prior window's
+        let mut engine = ZScoreEngine::new(3).unwrap().with_exclude_current(true);
+        for p in [1.0, 2.0, 4.0] {
+            assert_eq!(engine.update_full(p).unwrap(), None);
+        }
+        let (z, mean, std) = engine.update_full(10.0).unwrap().unwrap();
+        assert_eq!(Some(z), engine.get_zscore());
+        assert!((mean - 7.0 / 3.0).abs() < 1e-12);
+        assert!((z - (10.0 - mean) / std).abs() < 1e-12);
+    }
 
-    let variance: f64 = prices.iter()
-        .map(|&x| (x - mean).powi(2))
-        .sum::<f64>() / n;
+    #[test]
+    fn test_variance_floor() {
+        // Pip-sized moves: variance ~1e-11, below the default floor
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        for p in [1.10000, 1.10001, 1.09999, 1.10000, 1.10001] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.get_variance_floor(), 1e-10);
+        assert_eq!(engine.get_zscore(), Some(0.0));
 
-    if variance < 1e-10 {
-        return Some(0.0);
+        engine.set_variance_floor(1e-14).unwrap();
+        let z = engine.get_zscore().unwrap();
+        assert!(z > 0.5, "Z-Score {} swallowed by floor", z);
+
+        // A zero floor still treats an exactly flat window as 0
+        engine.set_variance_floor(0.0).unwrap();
+        for _ in 0..4 {
+            engine.update(1.1).unwrap();
+        }
+        assert_eq!(engine.get_zscore(), Some(0.0));
+
+        assert!(engine.set_variance_floor(-1.0).is_err());
+        assert!(engine.set_variance_floor(f64::NAN).is_err());
+
+        // The half-life fits follow the same floor
+        let mut engine = ZScoreEngine::new(8).unwrap();
+        for step in [0.0, 1.0, 2.0, 3.0, 2.5, 2.0, 1.5, 1.0] {
+            engine.update(1.1 + step * 1e-5).unwrap();
+        }
+        assert_eq!(engine.reversion_half_life(), None);
+        assert_eq!(engine.mean_reversion_halflife(), None);
+        engine.set_variance_floor(1e-14).unwrap();
+        assert!(engine.reversion_half_life().is_some());
+        assert!(engine.mean_reversion_halflife().is_some());
     }
 
-    Some((current - mean) / variance.sqrt())
-}
+    #[test]
+    fn test_last_evicted() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        for p in [1.0, 2.0, 3.0] {
+            engine.update(p).unwrap();
+            assert_eq!(engine.last_evicted(), None);
+        }
+        engine.update(4.0).unwrap();
+        assert_eq!(engine.last_evicted(), Some(1.0));
+        engine.update(5.0).unwrap();
+        assert_eq!(engine.last_evicted(), Some(2.0));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        engine.reset();
+        assert_eq!(engine.last_evicted(), None);
+
+        // Time window: expiring two values reports the later one
+        let mut timed = ZScoreEngine::new(2).unwrap().with_time_window(10).unwrap();
+        timed.update_at(1.0, 0).unwrap();
+        timed.update_at(2.0, 5).unwrap();
+        timed.update_at(3.0, 8).unwrap();
+        assert_eq!(timed.last_evicted(), None);
+        timed.update_at(4.0, 17).unwrap();
+        assert_eq!(timed.last_evicted(), Some(2.0));
+        timed.update_at(5.0, 18).unwrap();
+        assert_eq!(timed.last_evicted(), None);
+    }
 
     #[test]
-    fn test_new_engine() {
-        let engine = ZScoreEngine::new(20);
-        assert_eq!(engine.count(), 0);
-        assert!(!engine.is_ready());
-        assert_eq!(engine.lookback(), 20);
+    fn test_resync_keeps_window() {
+        let mut engine = ZScoreEngine::new(20).unwrap();
+        let mut state = 11;
+        for i in 0..1013 {
+            engine.update(1e8 + (i as f64 * 0.1).sin() * 3.0 + noise(&mut state)).unwrap();
+        }
+        let (mean, std, z) = (
+            engine.get_mean().unwrap(),
+            engine.get_std().unwrap(),
+            engine.get_zscore().unwrap(),
+        );
+
+        engine.resync();
+        assert!(engine.is_ready());
+        assert_eq!(engine.count(), 20);
+        assert!((engine.get_mean().unwrap() - mean).abs() / mean < 1e-12);
+        assert!((engine.get_std().unwrap() - std).abs() / std < 1e-9);
+        assert!((engine.get_zscore().unwrap() - z).abs() < 1e-6);
+
+        // Empty engine: no-op
+        let mut empty = ZScoreEngine::new(5).unwrap();
+        empty.resync();
+        assert_eq!(empty.get_mean(), None);
     }
 
     #[test]
-    #[should_panic(expected = "Lookback must be > 1")]
-    fn test_invalid_lookback() {
-        ZScoreEngine::new(1);
+    fn test_get_volatility() {
+        let mut engine = ZScoreEngine::with_input_mode(4, InputMode::LogReturn).unwrap();
+        for p in [100.0, 101.0, 100.0, 102.0] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.get_volatility(252.0).unwrap(), None);
+
+        engine.update(101.0).unwrap();
+        let std = engine.get_std().unwrap();
+        let vol = engine.get_volatility(252.0).unwrap().unwrap();
+        assert!((vol - std * 252f64.sqrt()).abs() < 1e-12);
+
+        assert!(engine.get_volatility(0.0).is_err());
+        assert!(engine.get_volatility(f64::INFINITY).is_err());
     }
 
     #[test]
-    fn test_warmup() {
-        let mut engine = ZScoreEngine::new(5);
+    fn test_stale_window_detection() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        assert_eq!(engine.fraction_unique(), 0.0);
+        assert!(engine.is_stale(1));
 
-        // First 4 updates should return None
-        for i in 0..4 {
-            assert!(engine.update(100.0 + i as f64).is_none());
-            assert!(!engine.is_ready());
+        for p in [100.0, 101.0, 100.5, 102.0, 101.5] {
+            engine.update(p).unwrap();
         }
+        assert_eq!(engine.fraction_unique(), 1.0);
+        assert!(!engine.is_stale(3));
 
-        // 5th update should return a value
-        assert!(engine.update(104.0).is_some());
-        assert!(engine.is_ready());
+        // Feed stalls on the last price
+        for _ in 0..4 {
+            engine.update(101.5).unwrap();
+        }
+        assert_eq!(engine.fraction_unique(), 0.2);
+        assert!(engine.is_stale(2));
+        assert!(!engine.is_stale(1));
     }
 
     #[test]
-    fn test_zscore_at_mean() {
-        let mut engine = ZScoreEngine::new(5);
+    fn test_percentile_rank_and_quantile() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        for p in [3.0, 1.0, 4.0, 5.0] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.percentile_rank(), None);
+        assert_eq!(engine.quantile(0.5).unwrap(), None);
+
+        // Window [3, 1, 4, 5, 2], sorted [1, 2, 3, 4, 5]
+        engine.update(2.0).unwrap();
+        assert_eq!(engine.percentile_rank(), Some(0.4));
+        assert_eq!(engine.quantile(0.0).unwrap(), Some(1.0));
+        assert_eq!(engine.quantile(0.5).unwrap(), Some(3.0));
+        assert_eq!(engine.quantile(1.0).unwrap(), Some(5.0));
+        assert!((engine.quantile(0.3).unwrap().unwrap() - 2.2).abs() < 1e-12);
+
+        // New high ranks at the top
+        engine.update(9.0).unwrap();
+        assert_eq!(engine.percentile_rank(), Some(1.0));
+
+        assert!(engine.quantile(-0.1).is_err());
+        assert!(engine.quantile(1.5).is_err());
+        assert!(engine.quantile(f64::NAN).is_err());
+    }
 
-        // Add prices with mean = 100
-        for p in [98.0, 99.0, 100.0, 101.0, 102.0] {
-            engine.update(p);
+    #[test]
+    fn test_min_max_ignore_nan() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        engine.update(2.0).unwrap();
+        engine.update(f64::NAN).unwrap();
+        engine.update(1.0).unwrap();
+        assert_eq!(engine.get_min(), Some(1.0));
+        assert_eq!(engine.get_max(), Some(2.0));
+        assert_eq!(engine.get_max_age(), Some(2));
+
+        // 2.0 slides out; the NaN is still in the window but excluded
+        engine.update(0.5).unwrap();
+        assert_eq!(engine.get_max(), Some(1.0));
+        assert_eq!(engine.get_min(), Some(0.5));
+
+        let mut all_nan = ZScoreEngine::new(2).unwrap();
+        all_nan.update(f64::NAN).unwrap();
+        assert_eq!(all_nan.get_max(), None);
+        assert_eq!(all_nan.get_min_age(), None);
+    }
+
+    #[test]
+    fn test_min_max_ties_report_latest_age() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        for p in [3.0, 1.0, 3.0, 2.0, 1.0] {
+            engine.update(p).unwrap();
         }
+        assert_eq!(engine.get_max(), Some(3.0));
+        assert_eq!(engine.get_max_age(), Some(2));
+        assert_eq!(engine.get_min(), Some(1.0));
+        assert_eq!(engine.get_min_age(), Some(0));
+
+        engine.update(2.5).unwrap();
+        assert_eq!(engine.get_max_age(), Some(3));
+        assert_eq!(engine.get_min_age(), Some(1));
+    }
 
-        // Current price (102) is not at mean, but let's verify mean
+    #[test]
+    fn test_expected_range() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        for p in [10.0, 12.0, 14.0] {
+            engine.update(p).unwrap();
+        }
+        assert_eq!(engine.expected_range(2.0).unwrap(), None);
+        assert_eq!(engine.expected_range_from_price(2.0).unwrap(), None);
+
+        engine.update(16.0).unwrap();
         let mean = engine.get_mean().unwrap();
-        assert!((mean - 100.0).abs() < 0.001);
+        let std = engine.get_std().unwrap();
 
-        // Update with price at mean
-        let z = engine.update(100.0).unwrap();
-        // Z-Score should be close to 0 (actually slightly negative due to window shift)
-        assert!(z.abs() < 0.5);
+        let (low1, high1) = engine.expected_range(1.0).unwrap().unwrap();
+        let (low3, high3) = engine.expected_range(3.0).unwrap().unwrap();
+        assert!((high1 - low1 - 2.0 * std).abs() < 1e-12);
+        assert!((high3 - low3 - 3.0 * (high1 - low1)).abs() < 1e-12);
+        assert!(((low1 + high1) / 2.0 - mean).abs() < 1e-12);
+
+        let (low, high) = engine.expected_range_from_price(1.5).unwrap().unwrap();
+        assert!((low - (16.0 - 1.5 * std)).abs() < 1e-12);
+        assert!((high - (16.0 + 1.5 * std)).abs() < 1e-12);
+
+        // Doubling the spread of the window doubles the width
+        let mut wide = ZScoreEngine::new(4).unwrap();
+        for p in [10.0, 14.0, 18.0, 22.0] {
+            wide.update(p).unwrap();
+        }
+        let (wlow, whigh) = wide.expected_range(1.0).unwrap().unwrap();
+        assert!((whigh - wlow - 2.0 * (high1 - low1)).abs() < 1e-12);
+
+        assert!(engine.expected_range(0.0).is_err());
+        assert!(engine.expected_range_from_price(-1.0).is_err());
     }
 
     #[test]
-    fn test_no_variance() {
-        let mut engine = ZScoreEngine::new(5);
+    fn test_sharpe() {
+        let mut engine = ZScoreEngine::new(4).unwrap();
+        for r in [0.01, -0.005, 0.02] {
+            engine.update(r).unwrap();
+        }
+        assert_eq!(engine.get_sharpe(0.0, 252.0).unwrap(), None);
 
-        // All same prices = no variance
-        for _ in 0..5 {
-            engine.update(100.0);
+        engine.update(0.003).unwrap();
+        let mean = engine.get_mean().unwrap();
+        let std = engine.get_std().unwrap();
+        let sharpe = engine.get_sharpe(0.001, 252.0).unwrap().unwrap();
+        assert!((sharpe - (mean - 0.001) / std * 252f64.sqrt()).abs() < 1e-12);
+
+        // Flat returns have no defined Sharpe
+        let mut flat = ZScoreEngine::new(3).unwrap();
+        for _ in 0..3 {
+            flat.update(0.01).unwrap();
         }
+        assert_eq!(flat.get_sharpe(0.0, 252.0).unwrap(), None);
 
-        let z = engine.get_zscore().unwrap();
-        assert_eq!(z, 0.0);
+        assert!(engine.get_sharpe(0.0, 0.0).is_err());
     }
 
     #[test]
-    fn test_reset() {
-        let mut engine = ZScoreEngine::new(5);
+    fn test_peek_oldest_newest() {
+        let mut engine = ZScoreEngine::new(3).unwrap();
+        assert_eq!(engine.peek_oldest(), None);
+        assert_eq!(engine.peek_newest(), None);
 
-        for _ in 0..10 {
-            engine.update(100.0);
+        for p in [1.0, 2.0, 3.0, 4.0] {
+            engine.update(p).unwrap();
         }
-        assert!(engine.is_ready());
-
-        engine.reset();
+        assert_eq!(engine.peek_oldest(), Some(2.0));
+        assert_eq!(engine.peek_newest(), Some(4.0));
+    }
 
-        assert_eq!(engine.count(), 0);
+    #[test]
+    fn test_time_window() {
+        const SEC: i64 = 1_000_000_000;
+        let mut engine = ZScoreEngine::new(2).unwrap().with_time_window(10 * SEC).unwrap();
+
+        engine.update_at(100.0, 0).unwrap();
+        engine.update_at(102.0, 3 * SEC).unwrap();
+        engine.update_at(104.0, 9 * SEC).unwrap();
+        assert_eq!(engine.count(), 3);
+        assert!((engine.get_mean().unwrap() - 102.0).abs() < 1e-12);
+
+        // A value exactly window_ns old is kept
+        engine.update_at(106.0, 10 * SEC).unwrap();
+        assert_eq!(engine.count(), 4);
+
+        // A long gap expires everything but the newest tick pair
+        engine.update_at(110.0, 19 * SEC).unwrap();
+        assert_eq!(engine.get_prices(), vec![104.0, 106.0, 110.0]);
+        engine.update_at(120.0, 30 * SEC).unwrap();
+        assert_eq!(engine.get_prices(), vec![120.0]);
         assert!(!engine.is_ready());
-        assert!(engine.get_zscore().is_none());
+        assert_eq!(engine.get_min(), Some(120.0));
+
+        // Matches a fresh engine fed only the surviving window
+        engine.update_at(118.0, 31 * SEC).unwrap();
+        let mut expected = ZScoreEngine::new(2).unwrap();
+        expected.update(120.0).unwrap();
+        let z = expected.update(118.0).unwrap();
+        assert_eq!(engine.get_zscore(), z);
     }
 
     #[test]
-    fn test_sliding_window() {
-        let mut engine = ZScoreEngine::new(5);
+    fn test_time_window_validation() {
+        assert!(ZScoreEngine::new(5).unwrap().with_time_window(0).is_err());
 
-        // Add 10 prices: 0, 1, 2, ..., 9
-        for i in 0..10 {
-            engine.update(i as f64);
+        let mut engine = ZScoreEngine::new(2).unwrap().with_time_window(1_000).unwrap();
+        assert!(engine.update(1.0).is_err());
+        engine.update_at(1.0, 500).unwrap();
+        engine.update_at(2.0, 500).unwrap();
+        assert!(engine.update_at(3.0, 499).is_err());
+        assert_eq!(engine.count(), 2);
+
+        engine.reset();
+        engine.update_at(3.0, 0).unwrap();
+
+        let mut count_engine = ZScoreEngine::new(2).unwrap();
+        assert!(count_engine.update_at(1.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+        engine.enable_history(10).unwrap();
+        for p in [1.0, 3.0, 2.0, 5.0, 4.0] {
+            engine.update(p).unwrap();
         }
 
-        // Window should contain: 5, 6, 7, 8, 9
-        // Mean should be 7
-        let mean = engine.get_mean().unwrap();
-        assert!((mean - 7.0).abs() < 0.001);
+        let mut fork = engine.py_clone();
+        assert_eq!(fork.get_zscore(), engine.get_zscore());
 
-        assert_eq!(engine.count(), 5);
+        fork.update(100.0).unwrap();
+        assert_eq!(engine.get_prices(), vec![1.0, 3.0, 2.0, 5.0, 4.0]);
+        assert_eq!(engine.get_max(), Some(5.0));
+        assert_eq!(engine.get_history().len(), 1);
+        assert_eq!(fork.get_max(), Some(100.0));
+        assert_eq!(fork.get_history().len(), 2);
+
+        // Both continue identically when fed the same path
+        let mut a = engine.py_clone();
+        for p in [6.0, 7.0] {
+            assert_eq!(a.update(p).unwrap(), engine.update(p).unwrap());
+        }
     }
 
     #[test]
-    fn test_batch_update() {
-        let mut engine = ZScoreEngine::new(5);
+    fn test_zscore_sensitivity() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
 
-        let prices = vec![100.0, 101.0, 102.0, 103.0, 104.0, 105.0];
-        let z = engine.update_batch(prices);
+        for p in [100.0, 102.0, 101.0, 103.0] {
+            engine.update(p).unwrap();
+        }
+        assert!(engine.zscore_sensitivity().is_none());
 
-        assert!(z.is_some());
-        assert!(engine.is_ready());
+        engine.update(99.0).unwrap();
+        let std = engine.get_std().unwrap();
+        let sensitivity = engine.zscore_sensitivity().unwrap();
+        assert!((sensitivity - 1.0 / std).abs() < 1e-12);
+
+        // Flat window has no meaningful sensitivity
+        let mut flat = ZScoreEngine::new(5).unwrap();
+        for _ in 0..5 {
+            flat.update(100.0).unwrap();
+        }
+        assert!(flat.zscore_sensitivity().is_none());
     }
 
     // ========== NUMERICAL STABILITY TESTS ==========
@@ -365,11 +3573,11 @@ mod tests {
     #[test]
     /// Test: Small values should closely match reference implementation
     fn test_small_values_accuracy() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
         let prices: Vec<f64> = (0..20).map(|i| 100.0 + i as f64 * 0.1).collect();
 
         for price in &prices {
-            engine.update(*price);
+            engine.update(*price).unwrap();
         }
 
         let engine_z = engine.get_zscore().unwrap();
@@ -389,14 +3597,14 @@ mod tests {
     /// This is the CORE FIX - when values are large (e.g., 1e10),
     /// the naive sum approach would produce negative variance
     fn test_large_values_stability() {
-        let mut engine = ZScoreEngine::new(20);
+        let mut engine = ZScoreEngine::new(20).unwrap();
         let large_offset = 1_000_000_000.0; // 1 billion
 
         // Create a series with consistent small variation around a large offset
         // Mean should be large_offset, std dev should be ~0.816 (std dev of [0,1,2])
         for i in 0..30 {
             let price = large_offset + (i % 3) as f64; // Values: large_offset, large_offset+1, large_offset+2
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();
@@ -417,7 +3625,7 @@ mod tests {
         );
 
         // Z-score at the mean should be close to 0
-        let z_at_mean = engine.update(large_offset + 1.0);
+        let z_at_mean = engine.update(large_offset + 1.0).unwrap();
         assert!(
             z_at_mean.unwrap().abs() < 0.1,
             "Large values: Z-score at mean incorrect. Got {}",
@@ -428,13 +3636,13 @@ mod tests {
     #[test]
     /// Test: Very large values (1e15) - extreme case
     fn test_very_large_values() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
         let huge_offset = 1e15;
 
         // Small variations around huge offset
         for i in 0..15 {
             let price = huge_offset + (i as f64 % 5.0);
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();
@@ -460,14 +3668,14 @@ mod tests {
     /// True variance: 30
     /// Naive algorithm: -170.666... (WRONG!)
     fn test_wikipedia_catastrophic_cancellation() {
-        let mut engine = ZScoreEngine::new(4);
+        let mut engine = ZScoreEngine::new(4).unwrap();
         let offset = 1_000_000_000.0;
 
         // The exact example from Wikipedia
         let prices = [offset + 4.0, offset + 7.0, offset + 13.0, offset + 16.0];
 
         for price in prices {
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();
@@ -500,14 +3708,14 @@ mod tests {
     /// Test: Extreme Wikipedia example (10^9 + offset)
     /// Naive algorithm would return -170.666 variance!
     fn test_wikipedia_extreme_case() {
-        let mut engine = ZScoreEngine::new(4);
+        let mut engine = ZScoreEngine::new(4).unwrap();
         let offset = 1_000_000_000.0;
 
         // Same relative values, larger offset (10^9)
         let prices = [offset + 4.0, offset + 7.0, offset + 13.0, offset + 16.0];
 
         for price in prices {
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let std = engine.get_std().unwrap();
@@ -530,16 +3738,16 @@ mod tests {
     #[test]
     /// Test: Mixed large and small values
     fn test_mixed_scale_values() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
 
         // Start with small values, then go large
         for i in 0..5 {
-            engine.update(100.0 + i as f64);
+            engine.update(100.0 + i as f64).unwrap();
         }
 
         let large_offset = 1e10;
         for i in 0..5 {
-            engine.update(large_offset + i as f64);
+            engine.update(large_offset + i as f64).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();
@@ -561,12 +3769,12 @@ mod tests {
     #[test]
     /// Test: Negative large values
     fn test_negative_large_values() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
         let large_offset = -1_000_000_000.0;
 
         for i in 0..15 {
             let price = large_offset + (i % 3) as f64;
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();
@@ -586,12 +3794,12 @@ mod tests {
     #[test]
     /// Test: Variance should never be negative (numerical precision issue)
     fn test_variance_never_negative() {
-        let mut engine = ZScoreEngine::new(20);
+        let mut engine = ZScoreEngine::new(20).unwrap();
 
         // Add values that could cause precision issues with naive algorithm
         for i in 0..30 {
             let price = 1e10 + (i as f64 % 100.0);
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let std = engine.get_std().unwrap();
@@ -607,30 +3815,30 @@ mod tests {
     #[test]
     /// Test: Zero variance handling
     fn test_zero_variance_stability() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
 
         // All same value - zero variance
         for _ in 0..20 {
-            engine.update(100.0);
+            engine.update(100.0).unwrap();
         }
 
         let z = engine.get_zscore().unwrap();
         assert_eq!(z, 0.0, "Zero variance should return Z=0");
 
         // Update with same value
-        let z2 = engine.update(100.0);
+        let z2 = engine.update(100.0).unwrap();
         assert_eq!(z2.unwrap(), 0.0, "Zero variance with same value should return Z=0");
     }
 
     #[test]
     /// Test: Nearly zero variance (should handle gracefully)
     fn test_nearly_zero_variance() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
 
         // Very small variations
         for i in 0..15 {
             let price = 100.0 + (i as f64 * 1e-10); // Tiny variations
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let z = engine.get_zscore().unwrap();
@@ -656,10 +3864,10 @@ mod tests {
         let prices: Vec<f64> = (0..50).map(|i| 100.0 + i as f64 * 0.5).collect();
 
         for lookback in [5, 10, 20, 30] {
-            let mut engine = ZScoreEngine::new(lookback);
+            let mut engine = ZScoreEngine::new(lookback).unwrap();
 
             for price in &prices {
-                engine.update(*price);
+                engine.update(*price).unwrap();
             }
 
             let mean = engine.get_mean().unwrap();
@@ -682,13 +3890,13 @@ mod tests {
     #[test]
     /// Test: Long sequence (simulate extended usage)
     fn test_long_sequence_stability() {
-        let mut engine = ZScoreEngine::new(20);
+        let mut engine = ZScoreEngine::new(20).unwrap();
         let base = 1_000_000.0;
 
         // Simulate 1000 updates
         for i in 0..1000 {
             let price = base + ((i as f64).sin() * 10.0); // Oscillating values
-            engine.update(price);
+            engine.update(price).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();
@@ -713,22 +3921,73 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test: Mean must not drift over a very long run at a huge price level
+    fn test_million_updates_no_drift() {
+        let mut engine = ZScoreEngine::new(20).unwrap();
+        let base = 1e12;
+
+        for i in 0..1_000_000 {
+            let price = base + (i as f64).sin() * 10.0;
+            engine.update(price).unwrap();
+        }
+
+        // Reference computed on offsets from base to avoid cancellation
+        let offsets: Vec<f64> = engine.get_prices().iter().map(|&p| p - base).collect();
+        let n = offsets.len() as f64;
+        let ref_mean = offsets.iter().sum::<f64>() / n;
+        let ref_var = offsets.iter().map(|&d| (d - ref_mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+        let mean = engine.get_mean().unwrap();
+        let std = engine.get_std().unwrap();
+
+        assert!(
+            (mean - (base + ref_mean)).abs() < 1e-3,
+            "Million updates: Mean drifted. Got {}, expected {}",
+            mean, base + ref_mean
+        );
+        assert!(
+            (std - ref_var.sqrt()).abs() < 1e-3,
+            "Million updates: Std drifted. Got {}, expected {}",
+            std, ref_var.sqrt()
+        );
+    }
+
+    #[test]
+    /// Test: An outlier at the front of the window must not poison K
+    fn test_recenter_after_outlier() {
+        let mut engine = ZScoreEngine::new(5).unwrap();
+
+        for p in [100.0, 1e9, 101.0, 102.0, 103.0, 104.0, 105.0] {
+            engine.update(p).unwrap();
+        }
+        for i in 0..20 {
+            engine.update(100.0 + i as f64).unwrap();
+        }
+
+        // Window is 115..=119
+        let mean = engine.get_mean().unwrap();
+        let std = engine.get_std().unwrap();
+        assert!((mean - 117.0).abs() < 1e-9);
+        assert!((std - 2.5_f64.sqrt()).abs() < 1e-9);
+    }
+
     #[test]
     /// Test: Extreme value followed by normal values
     fn test_extreme_value_recovery() {
-        let mut engine = ZScoreEngine::new(10);
+        let mut engine = ZScoreEngine::new(10).unwrap();
 
         // Normal values first
         for i in 0..10 {
-            engine.update(100.0 + i as f64);
+            engine.update(100.0 + i as f64).unwrap();
         }
 
         // Extreme value
-        engine.update(1e15);
+        engine.update(1e15).unwrap();
 
         // Normal values again
         for i in 0..10 {
-            engine.update(100.0 + i as f64);
+            engine.update(100.0 + i as f64).unwrap();
         }
 
         // Should recover and produce valid results
@@ -749,7 +4008,7 @@ mod tests {
     #[test]
     /// Test: Comparison with numpy-style calculation for real-world prices
     fn test_realistic_trading_prices() {
-        let mut engine = ZScoreEngine::new(20);
+        let mut engine = ZScoreEngine::new(20).unwrap();
 
         // Simulate realistic BTC prices (around $50,000)
         let base_price = 50_000.0;
@@ -758,7 +4017,7 @@ mod tests {
             .collect();
 
         for price in &prices {
-            engine.update(*price);
+            engine.update(*price).unwrap();
         }
 
         let mean = engine.get_mean().unwrap();